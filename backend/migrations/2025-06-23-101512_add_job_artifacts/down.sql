@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE "job_artifacts";
+ALTER TABLE "jobs" DROP COLUMN "artifacts";
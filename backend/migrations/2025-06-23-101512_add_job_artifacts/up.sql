@@ -0,0 +1,15 @@
+-- Glob patterns of files to collect from the job VM
+ALTER TABLE "jobs" ADD COLUMN "artifacts" TEXT[] NOT NULL DEFAULT '{}';
+
+-- Files uploaded by runners, the contents live in the artifact store
+CREATE TABLE "job_artifacts" (
+    "id" UUID NOT NULL PRIMARY KEY,
+    "job_id" UUID NOT NULL REFERENCES "jobs" ("id"),
+    "name" TEXT NOT NULL,
+    "size_bytes" INT8 NOT NULL,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    "expires_at" TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX "job_artifacts_job_id_idx" ON "job_artifacts" ("job_id");
+CREATE INDEX "job_artifacts_expires_at_idx" ON "job_artifacts" ("expires_at");
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE "job_dependencies";
+ALTER TABLE "jobs" DROP COLUMN "tasks";
+ALTER TABLE "jobs" DROP COLUMN "name";
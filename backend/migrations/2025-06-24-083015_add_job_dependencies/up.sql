@@ -0,0 +1,16 @@
+-- Name of the job within the pipeline declared in devenv.yaml
+ALTER TABLE "jobs" ADD COLUMN "name" TEXT NOT NULL DEFAULT 'devenv';
+
+-- Tasks to run, NULL means the configured defaults
+ALTER TABLE "jobs" ADD COLUMN "tasks" TEXT[];
+
+-- A job is only handed out once all jobs it depends on succeeded
+CREATE TABLE "job_dependencies" (
+    "job_id" UUID NOT NULL REFERENCES "jobs" ("id"),
+    "depends_on_job_id" UUID NOT NULL REFERENCES "jobs" ("id"),
+    -- Set once the job depended on succeeded
+    "satisfied" BOOLEAN NOT NULL DEFAULT FALSE,
+    PRIMARY KEY ("job_id", "depends_on_job_id")
+);
+
+CREATE INDEX "job_dependencies_depends_on_job_id_idx" ON "job_dependencies" ("depends_on_job_id");
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX "jobs_status_platform_idx";
+ALTER TABLE "github_owner" DROP COLUMN "share_weight";
+ALTER TABLE "github_owner" DROP COLUMN "max_concurrent_jobs";
+ALTER TABLE "jobs" DROP COLUMN "priority";
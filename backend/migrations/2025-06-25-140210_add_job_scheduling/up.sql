@@ -0,0 +1,10 @@
+-- Higher priority jobs of an owner are started first
+ALTER TABLE "jobs" ADD COLUMN "priority" INT4 NOT NULL DEFAULT 0;
+
+-- Maximum number of jobs an owner may run at once, NULL uses the configured default
+ALTER TABLE "github_owner" ADD COLUMN "max_concurrent_jobs" INT4;
+
+-- Relative share of the runners an owner gets when several owners have jobs queued
+ALTER TABLE "github_owner" ADD COLUMN "share_weight" INT4 NOT NULL DEFAULT 1 CHECK ("share_weight" > 0);
+
+CREATE INDEX "jobs_status_platform_idx" ON "jobs" ("status", "platform");
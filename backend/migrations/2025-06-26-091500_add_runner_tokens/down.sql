@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE "runners" DROP COLUMN "token_id";
+DROP TABLE "runner_tokens";
@@ -0,0 +1,12 @@
+-- Registration tokens presented by runners, only the SHA-256 hash is stored
+CREATE TABLE "runner_tokens" (
+    "id" UUID NOT NULL PRIMARY KEY,
+    "name" TEXT NOT NULL,
+    "token_hash" TEXT NOT NULL UNIQUE,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    "last_used_at" TIMESTAMPTZ,
+    "revoked_at" TIMESTAMPTZ
+);
+
+-- Token the runner authenticated with
+ALTER TABLE "runners" ADD COLUMN "token_id" UUID REFERENCES "runner_tokens" ("id");
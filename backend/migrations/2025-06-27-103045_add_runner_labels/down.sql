@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE "jobs" DROP COLUMN "required_labels";
+DROP TABLE "runner_labels";
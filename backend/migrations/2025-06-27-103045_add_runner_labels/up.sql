@@ -0,0 +1,9 @@
+-- Labels describing the capabilities of a runner, e.g. its region or hardware
+CREATE TABLE "runner_labels" (
+    "runner_id" UUID NOT NULL REFERENCES "runners" ("id") ON DELETE CASCADE,
+    "label" TEXT NOT NULL,
+    PRIMARY KEY ("runner_id", "label")
+);
+
+-- Labels a runner needs to have to run the job
+ALTER TABLE "jobs" ADD COLUMN "required_labels" TEXT[] NOT NULL DEFAULT '{}';
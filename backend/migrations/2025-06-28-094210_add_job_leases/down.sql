@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX "jobs_lease_expires_at_idx";
+ALTER TABLE "jobs" DROP COLUMN "guest_started_at";
+ALTER TABLE "jobs" DROP COLUMN "lease_expires_at";
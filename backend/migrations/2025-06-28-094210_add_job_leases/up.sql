@@ -0,0 +1,6 @@
+-- Runners renew the lease of their jobs with heartbeats, a lapsed lease means the runner is gone
+ALTER TABLE "jobs" ADD COLUMN "lease_expires_at" TIMESTAMPTZ;
+-- When the guest started working on the job, jobs that never started are safe to requeue
+ALTER TABLE "jobs" ADD COLUMN "guest_started_at" TIMESTAMPTZ;
+
+CREATE INDEX "jobs_lease_expires_at_idx" ON "jobs" ("lease_expires_at");
@@ -0,0 +1,2 @@
+-- Latest metrics reported by the runner
+ALTER TABLE "runners" ADD COLUMN "metrics" JSONB;
@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE "runners" DROP COLUMN "cordoned_at";
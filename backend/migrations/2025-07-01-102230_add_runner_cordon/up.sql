@@ -0,0 +1,2 @@
+-- Cordoned runners are not offered new jobs
+ALTER TABLE "runners" ADD COLUMN "cordoned_at" TIMESTAMPTZ;
@@ -0,0 +1,12 @@
+-- Tasks of a job as reported by the guest
+CREATE TABLE "job_steps" (
+    "id" UUID NOT NULL PRIMARY KEY,
+    "job_id" UUID NOT NULL REFERENCES "jobs" ("id") ON DELETE CASCADE,
+    "name" TEXT NOT NULL,
+    "started_at" TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    "finished_at" TIMESTAMPTZ,
+    "success" BOOLEAN,
+    "duration_ms" INT8
+);
+
+CREATE INDEX "job_steps_job_id_idx" ON "job_steps" ("job_id");
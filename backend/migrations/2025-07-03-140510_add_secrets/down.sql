@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE "github_commit" DROP COLUMN "trusted";
+ALTER TABLE "jobs" DROP COLUMN "secrets";
+DROP TABLE "github_secrets";
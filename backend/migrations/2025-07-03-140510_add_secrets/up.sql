@@ -0,0 +1,23 @@
+-- Secrets of an owner, or of one of its repositories if repo_id is set,
+-- encrypted with the key from the SECRETS_ENCRYPTION_KEY secret
+CREATE TABLE "github_secrets" (
+    "id" UUID NOT NULL PRIMARY KEY,
+    "owner_id" INT8 NOT NULL REFERENCES "github_owner" ("id") ON DELETE CASCADE,
+    "repo_id" INT8 REFERENCES "github_repo" ("id") ON DELETE CASCADE,
+    "name" TEXT NOT NULL,
+    "nonce" BYTEA NOT NULL,
+    "ciphertext" BYTEA NOT NULL,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    "updated_at" TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX "github_secrets_owner_name_idx" ON "github_secrets" ("owner_id", "name")
+    WHERE "repo_id" IS NULL;
+CREATE UNIQUE INDEX "github_secrets_repo_name_idx" ON "github_secrets" ("repo_id", "name")
+    WHERE "repo_id" IS NOT NULL;
+
+-- Names of the secrets delivered to the job
+ALTER TABLE "jobs" ADD COLUMN "secrets" TEXT[] NOT NULL DEFAULT '{}';
+
+-- Whether the commit was pushed to the repository itself rather than to a fork
+ALTER TABLE "github_commit" ADD COLUMN "trusted" BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE "jobs" DROP COLUMN "trigger";
+DROP TABLE "github_schedules";
@@ -0,0 +1,16 @@
+-- Cron schedules declared in devenv.yaml on the default branch of a repository
+CREATE TABLE "github_schedules" (
+    "id" UUID NOT NULL PRIMARY KEY,
+    "repo_id" INT8 NOT NULL REFERENCES "github_repo" ("id") ON DELETE CASCADE,
+    "cron" TEXT NOT NULL,
+    "branch" TEXT NOT NULL,
+    "next_run_at" TIMESTAMPTZ NOT NULL,
+    "last_run_at" TIMESTAMPTZ,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX "github_schedules_repo_cron_idx" ON "github_schedules" ("repo_id", "cron");
+CREATE INDEX "github_schedules_next_run_at_idx" ON "github_schedules" ("next_run_at");
+
+-- The event that created the job
+ALTER TABLE "jobs" ADD COLUMN "trigger" TEXT NOT NULL DEFAULT 'push';
@@ -0,0 +1,2 @@
+-- How long the job may run as configured in devenv.yaml, NULL uses the configured default
+ALTER TABLE "jobs" ADD COLUMN "timeout_seconds" INT4;
@@ -0,0 +1,5 @@
+-- When a maintainer approved the job, NULL for jobs that didn't need approval or weren't approved
+ALTER TABLE "jobs" ADD COLUMN "approved_at" TIMESTAMPTZ;
+
+-- Jobs that started before approvals were recorded were approved, or didn't need it
+UPDATE "jobs" SET "approved_at" = "started_at" WHERE "started_at" IS NOT NULL;
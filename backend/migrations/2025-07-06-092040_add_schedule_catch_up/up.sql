@@ -0,0 +1,2 @@
+-- Whether missed ticks of the schedule run, unset follows the backend configuration
+ALTER TABLE "github_schedules" ADD COLUMN "catch_up" BOOLEAN;
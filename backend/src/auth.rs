@@ -1,6 +1,8 @@
+use crate::config::AppState;
+use crate::runner::model::RunnerToken;
 use axum::{
     extract::{FromRef, FromRequestParts},
-    http::{StatusCode, request::Parts},
+    http::{StatusCode, header::AUTHORIZATION, request::Parts},
     response::{IntoResponse, Response},
 };
 use zitadel::axum::introspection::IntrospectedUser;
@@ -129,3 +131,102 @@ where
         }
     }
 }
+
+/// A wrapper around IntrospectedUser that ensures the user has admin role
+#[derive(Debug)]
+pub struct AdminUser(pub IntrospectedUser);
+
+impl std::ops::Deref for AdminUser {
+    type Target = IntrospectedUser;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Trait for checking admin access on users
+pub trait AdminAccessChecker {
+    fn has_admin_access(&self) -> bool;
+}
+
+impl AdminAccessChecker for IntrospectedUser {
+    fn has_admin_access(&self) -> bool {
+        if self.project_roles.contains_key("admin") || self.org_roles.contains_key("admin") {
+            tracing::debug!("User {} has admin role", self.sub);
+            return true;
+        }
+
+        // Check for admin role in custom_claims using the Zitadel OIDC format
+        self.custom_claims.iter().any(|(claim_name, claim_value)| {
+            claim_name.starts_with("urn:zitadel:iam:org:project:")
+                && claim_name.ends_with(":roles")
+                && claim_value
+                    .as_object()
+                    .is_some_and(|roles_obj| roles_obj.contains_key("admin"))
+        })
+    }
+}
+
+impl<S> FromRequestParts<S> for AdminUser
+where
+    S: Send + Sync,
+    zitadel::axum::introspection::IntrospectionState: FromRef<S>,
+{
+    type Rejection = AuthorizationError;
+
+    fn from_request_parts(
+        parts: &mut Parts,
+        state: &S,
+    ) -> impl std::future::Future<Output = Result<Self, Self::Rejection>> + Send {
+        async move {
+            let user = IntrospectedUser::from_request_parts(parts, state)
+                .await
+                .map_err(|_| AuthorizationError {
+                    message: "Authentication required".to_string(),
+                })?;
+
+            if user.has_admin_access() {
+                Ok(AdminUser(user))
+            } else {
+                Err(AuthorizationError {
+                    message: "Admin access required".to_string(),
+                })
+            }
+        }
+    }
+}
+
+/// A runner authenticated with a registration token passed as a bearer token
+#[derive(Debug)]
+pub struct RunnerAuth(pub RunnerToken);
+
+impl FromRequestParts<AppState> for RunnerAuth {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let token = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| StatusCode::UNAUTHORIZED.into_response())?;
+
+        let conn = &mut state
+            .pool
+            .get()
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?;
+
+        match RunnerToken::authenticate(conn, token.trim()).await {
+            Ok(Some(runner_token)) => Ok(RunnerAuth(runner_token)),
+            Ok(None) => Err(StatusCode::UNAUTHORIZED.into_response()),
+            Err(e) => {
+                tracing::error!("Failed to authenticate runner token: {}", e);
+                Err(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+            }
+        }
+    }
+}
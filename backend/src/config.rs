@@ -38,6 +38,8 @@ pub struct Config {
     pub job: Job,
     #[serde(default = "default_logger_url")]
     pub logger_url: String,
+    #[serde(default)]
+    pub artifacts: Artifacts,
 }
 
 fn default_logger_url() -> String {
@@ -63,26 +65,123 @@ impl Config {
     }
 }
 
+fn default_github_approval_label() -> String {
+    "devenv-approved".to_string()
+}
+
 #[derive(Deserialize)]
 pub struct GitHub {
     pub app_id: u64,
     pub app_name: String,
+    /// Label maintainers add to a pull request from a fork to run its jobs
+    #[serde(default = "default_github_approval_label")]
+    pub approval_label: String,
 }
 
 fn default_job_timeout_seconds() -> u64 {
     3600 // Default to 1 hour (3600 seconds)
 }
 
+fn default_job_max_timeout_seconds() -> u64 {
+    6 * 3600 // Jobs may ask for up to 6 hours
+}
+
+fn default_job_max_infrastructure_retries() -> u32 {
+    2
+}
+
+fn default_job_lease_seconds() -> u64 {
+    90 // Runners heartbeat every 15 seconds
+}
+
+fn default_job_clone_depth() -> u32 {
+    1 // Only the commit being built is needed
+}
+
+fn default_job_check_run_log_lines() -> usize {
+    50
+}
+
+fn default_job_tasks() -> Vec<String> {
+    vec!["build".to_string(), "test".to_string()]
+}
+
 #[derive(Deserialize)]
 pub struct Job {
+    /// How long jobs may run unless devenv.yaml sets a timeout
     #[serde(default = "default_job_timeout_seconds")]
     pub timeout_seconds: u64,
+    /// Longest timeout jobs may set in devenv.yaml
+    #[serde(default = "default_job_max_timeout_seconds")]
+    pub max_timeout_seconds: u64,
+    /// How often a job that failed because of the infrastructure is retried
+    #[serde(default = "default_job_max_infrastructure_retries")]
+    pub max_infrastructure_retries: u32,
+    /// How long a job stays owned by its runner without a heartbeat
+    #[serde(default = "default_job_lease_seconds")]
+    pub lease_seconds: u64,
+    #[serde(default = "default_job_clone_depth")]
+    pub clone_depth: u32,
+    #[serde(default = "default_job_tasks")]
+    pub tasks: Vec<String>,
+    /// Default limit of jobs an owner may run at once, unset means unlimited
+    #[serde(default)]
+    pub max_concurrent_per_owner: Option<u32>,
+    /// Lines at the end of the log shown on the check run of a completed job
+    #[serde(default = "default_job_check_run_log_lines")]
+    pub check_run_log_lines: usize,
+    /// Run a schedule once when its ticks were missed, e.g. while the backend
+    /// was down, instead of waiting for its next tick. Schedules setting
+    /// `catch-up` in devenv.yaml override this.
+    #[serde(default)]
+    pub catch_up_missed_schedules: bool,
 }
 
 impl Default for Job {
     fn default() -> Self {
         Self {
             timeout_seconds: default_job_timeout_seconds(),
+            max_timeout_seconds: default_job_max_timeout_seconds(),
+            max_infrastructure_retries: default_job_max_infrastructure_retries(),
+            lease_seconds: default_job_lease_seconds(),
+            clone_depth: default_job_clone_depth(),
+            tasks: default_job_tasks(),
+            max_concurrent_per_owner: None,
+            check_run_log_lines: default_job_check_run_log_lines(),
+            catch_up_missed_schedules: false,
+        }
+    }
+}
+
+fn default_artifacts_url() -> Url {
+    "file:///var/tmp/devenv-artifacts".parse().unwrap()
+}
+
+fn default_artifacts_retention_days() -> u32 {
+    30
+}
+
+fn default_artifacts_max_size_mb() -> u64 {
+    1024
+}
+
+#[derive(Deserialize)]
+pub struct Artifacts {
+    /// Object store location, e.g. `file:///var/lib/artifacts` or `s3://bucket/artifacts`
+    #[serde(default = "default_artifacts_url")]
+    pub url: Url,
+    #[serde(default = "default_artifacts_retention_days")]
+    pub retention_days: u32,
+    #[serde(default = "default_artifacts_max_size_mb")]
+    pub max_size_mb: u64,
+}
+
+impl Default for Artifacts {
+    fn default() -> Self {
+        Self {
+            url: default_artifacts_url(),
+            retention_days: default_artifacts_retention_days(),
+            max_size_mb: default_artifacts_max_size_mb(),
         }
     }
 }
@@ -120,6 +219,10 @@ pub struct InnerState {
     pub github: Octocrab,
     pub posthog: Option<posthog_rs::Client>,
     pub runner_state: crate::runner::serve::RunnerState,
+    pub job_tokens: crate::github::model::JobTokens,
+    pub artifact_store: crate::job::model::ArtifactStore,
+    /// Unset when no encryption key is configured, which disables secrets
+    pub secrets_cipher: Option<crate::github::secrets::SecretsCipher>,
 }
 
 impl FromRef<AppState> for IntrospectionState {
@@ -192,6 +295,15 @@ impl AppState {
         // Create the RunnerState
         let runner_state = crate::runner::serve::RunnerState::new();
 
+        let artifact_store = crate::job::model::ArtifactStore::new(&config.artifacts.url)
+            .map_err(|e| eyre::eyre!("Failed to configure artifact store: {}", e))?;
+
+        let secrets_cipher = secrets
+            .secrets_encryption_key
+            .as_deref()
+            .map(crate::github::secrets::SecretsCipher::from_hex)
+            .transpose()?;
+
         let state = InnerState {
             config,
             secrets,
@@ -200,6 +312,9 @@ impl AppState {
             github,
             posthog,
             runner_state,
+            job_tokens: crate::github::model::JobTokens::default(),
+            artifact_store,
+            secrets_cipher,
         };
 
         Ok(Self(Arc::new(state)))
@@ -0,0 +1,287 @@
+//! Output of the check runs of completed jobs and of jobs awaiting approval
+//!
+//! GitHub shows it right on the pull request, so it carries enough about the
+//! job and the end of its log to tell what happened without clicking through.
+
+use crate::job::model::Job;
+use chrono::{DateTime, TimeDelta, Utc};
+use devenv_runner::protocol::CompletionStatus;
+use octocrab::params::checks::CheckRunOutput;
+
+/// GitHub rejects check run summaries and texts longer than this
+const MAX_OUTPUT_CHARS: usize = 65535;
+
+/// Build the output of the check run of a completed job
+///
+/// `reason` explains failures that weren't caused by the job itself.
+pub fn render(
+    job: &Job,
+    status: &CompletionStatus,
+    finished_at: DateTime<Utc>,
+    reason: Option<&str>,
+    log_tail: &[String],
+    log_link: &str,
+) -> CheckRunOutput {
+    let duration = job
+        .started_at
+        .map(|started_at| format_duration(finished_at - started_at));
+
+    let title = match (reason, status, &duration) {
+        (Some(_), _, _) | (None, CompletionStatus::InfrastructureFailure, _) => {
+            "Infrastructure error".to_string()
+        }
+        (None, CompletionStatus::Skipped, _) => "Skipped".to_string(),
+        (None, CompletionStatus::Success, Some(duration)) => format!("Succeeded in {duration}"),
+        (None, CompletionStatus::Success, None) => "Succeeded".to_string(),
+        (None, CompletionStatus::Failed, Some(duration)) => format!("Failed after {duration}"),
+        (None, CompletionStatus::Failed, None) => "Failed".to_string(),
+        (None, CompletionStatus::TimedOut, Some(duration)) => {
+            format!("Timed out after {duration}")
+        }
+        (None, CompletionStatus::TimedOut, None) => "Timed out".to_string(),
+        (None, CompletionStatus::Cancelled, Some(duration)) => {
+            format!("Cancelled after {duration}")
+        }
+        (None, CompletionStatus::Cancelled, None) => "Cancelled".to_string(),
+    };
+
+    let mut summary = String::new();
+    if let Some(reason) = reason {
+        summary.push_str(&format!(
+            "The job didn't fail because of its tasks: {reason}.\n\n"
+        ));
+    } else if *status == CompletionStatus::InfrastructureFailure {
+        summary
+            .push_str("The job didn't fail because of its tasks, the runner couldn't run it.\n\n");
+    } else if *status == CompletionStatus::Skipped {
+        summary.push_str("A job this one depends on didn't succeed.\n\n");
+    }
+    if *status == CompletionStatus::InfrastructureFailure && job.retried_job_id.is_some() {
+        summary.push_str("It was retried automatically.\n\n");
+    }
+    summary.push_str("| Platform | CPUs | Memory | Duration | Runner |\n");
+    summary.push_str("| --- | --- | --- | --- | --- |\n");
+    summary.push_str(&format!(
+        "| {} | {} | {} MB | {} | {} |\n",
+        job.platform,
+        job.cpus,
+        job.memory_mb,
+        duration.as_deref().unwrap_or("-"),
+        job.runner_id
+            .map(|id| format!("`{id}`"))
+            .unwrap_or_else(|| "-".to_string()),
+    ));
+    if job.started_at.is_some() {
+        summary.push_str(&format!("\n[Full log]({log_link})\n"));
+    }
+
+    let text = (!log_tail.is_empty()).then(|| render_log_tail(log_tail));
+
+    CheckRunOutput {
+        title,
+        summary: truncate_front(&summary, MAX_OUTPUT_CHARS),
+        text,
+        annotations: vec![],
+        images: vec![],
+    }
+}
+
+/// Build the output of the check run of a job from a fork, telling maintainers how to approve it
+pub fn render_awaiting_approval(approval_label: &str) -> CheckRunOutput {
+    CheckRunOutput {
+        title: "Awaiting approval".to_string(),
+        summary: format!(
+            "This pull request comes from a fork, so its jobs only run once a maintainer \
+             with write access approves them by commenting `/devenv approve` or by \
+             adding the `{approval_label}` label."
+        ),
+        text: None,
+        annotations: vec![],
+        images: vec![],
+    }
+}
+
+/// Render the last lines of the log as a code block, dropping the oldest
+/// lines past GitHub's limit
+fn render_log_tail(lines: &[String]) -> String {
+    let header = format!("### Last {} lines of the log\n\n", lines.len());
+    let log = lines
+        .iter()
+        .map(|line| strip_ansi(line))
+        .collect::<Vec<_>>()
+        .join("\n")
+        // Keep the log from closing the code block
+        .replace("```", "'''");
+    // Room for the header and the fences
+    let budget = MAX_OUTPUT_CHARS - header.len() - 10;
+    format!("{header}```\n{}\n```", truncate_front(&log, budget))
+}
+
+/// Format a duration like `1h 2m 3s`, leaving out leading zero units
+fn format_duration(duration: TimeDelta) -> String {
+    let seconds = duration.num_seconds().max(0);
+    let (hours, minutes, seconds) = (seconds / 3600, seconds % 3600 / 60, seconds % 60);
+    if hours > 0 {
+        format!("{hours}h {minutes}m {seconds}s")
+    } else if minutes > 0 {
+        format!("{minutes}m {seconds}s")
+    } else {
+        format!("{seconds}s")
+    }
+}
+
+/// Remove the terminal escape sequences that color the output of the tasks
+fn strip_ansi(line: &str) -> String {
+    let mut stripped = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            stripped.push(c);
+            continue;
+        }
+        // CSI sequences end with a letter, other escapes are a single char
+        if chars.next() == Some('[') {
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        }
+    }
+    stripped
+}
+
+/// Keep the last `max` chars of the text
+fn truncate_front(text: &str, max: usize) -> String {
+    let len = text.chars().count();
+    if len <= max {
+        return text.to_string();
+    }
+    text.chars().skip(len - max).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::job::model::JobStatus;
+
+    fn job(started_at: Option<DateTime<Utc>>) -> Job {
+        Job {
+            status: JobStatus::running(),
+            started_at,
+            runner_id: Some(uuid::Uuid::nil()),
+            ..Job::test_default()
+        }
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(TimeDelta::seconds(5)), "5s");
+        assert_eq!(format_duration(TimeDelta::seconds(192)), "3m 12s");
+        assert_eq!(format_duration(TimeDelta::seconds(3723)), "1h 2m 3s");
+        assert_eq!(format_duration(TimeDelta::seconds(-1)), "0s");
+    }
+
+    #[test]
+    fn test_strip_ansi() {
+        assert_eq!(strip_ansi("\x1b[1;31merror:\x1b[0m oops"), "error: oops");
+        assert_eq!(strip_ansi("plain"), "plain");
+    }
+
+    #[test]
+    fn test_truncate_front() {
+        assert_eq!(truncate_front("abcdef", 3), "def");
+        assert_eq!(truncate_front("abc", 3), "abc");
+        assert_eq!(truncate_front("äöü", 2), "öü");
+    }
+
+    #[test]
+    fn test_render_success() {
+        let finished_at = Utc::now();
+        let job = job(Some(finished_at - TimeDelta::seconds(192)));
+        let output = render(
+            &job,
+            &CompletionStatus::Success,
+            finished_at,
+            None,
+            &["building".to_string(), "done".to_string()],
+            "https://example.com/log",
+        );
+        assert_eq!(output.title, "Succeeded in 3m 12s");
+        assert!(
+            output
+                .summary
+                .contains("| x86_64-linux | 2 | 4096 MB | 3m 12s |")
+        );
+        assert!(
+            output
+                .summary
+                .contains("[Full log](https://example.com/log)")
+        );
+        let text = output.text.unwrap();
+        assert!(text.starts_with("### Last 2 lines of the log"));
+        assert!(text.contains("```\nbuilding\ndone\n```"));
+    }
+
+    #[test]
+    fn test_render_infrastructure_error() {
+        let output = render(
+            &job(Some(Utc::now())),
+            &CompletionStatus::Failed,
+            Utc::now(),
+            Some("the runner was lost"),
+            &[],
+            "https://example.com/log",
+        );
+        assert_eq!(output.title, "Infrastructure error");
+        assert!(
+            output
+                .summary
+                .starts_with("The job didn't fail because of its tasks: the runner was lost.")
+        );
+        assert!(output.text.is_none());
+    }
+
+    #[test]
+    fn test_render_retried_infrastructure_failure() {
+        let mut job = job(Some(Utc::now()));
+        job.retried_job_id = Some(uuid::Uuid::now_v7());
+        let output = render(
+            &job,
+            &CompletionStatus::InfrastructureFailure,
+            Utc::now(),
+            None,
+            &[],
+            "https://example.com/log",
+        );
+        assert_eq!(output.title, "Infrastructure error");
+        assert!(output.summary.contains("It was retried automatically."));
+    }
+
+    #[test]
+    fn test_render_skipped() {
+        let output = render(
+            &job(None),
+            &CompletionStatus::Skipped,
+            Utc::now(),
+            None,
+            &[],
+            "https://example.com/log",
+        );
+        assert_eq!(output.title, "Skipped");
+        assert!(!output.summary.contains("Full log"));
+    }
+
+    #[test]
+    fn test_render_awaiting_approval() {
+        let output = render_awaiting_approval("devenv-approved");
+        assert_eq!(output.title, "Awaiting approval");
+        assert!(output.summary.contains("`devenv-approved` label"));
+    }
+
+    #[test]
+    fn test_render_log_tail_keeps_code_block_closed() {
+        let text = render_log_tail(&["```".to_string()]);
+        assert_eq!(text.matches("```").count(), 2);
+    }
+}
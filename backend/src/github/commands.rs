@@ -0,0 +1,120 @@
+//! Commands given in comments on pull requests
+//!
+//! A command is the first line of a comment, e.g. `/devenv retry x86_64-linux`.
+//! Only collaborators with write access to the repository may give them, and
+//! they act on the jobs of the head commit of the pull request.
+
+use devenv_runner::protocol::Platform;
+
+/// A command understood in pull request comments
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Run the jobs of a pull request from a fork
+    Approve,
+    /// Retry the jobs that didn't succeed, on one platform or all of them
+    Retry { platform: Option<Platform> },
+    /// Cancel the jobs that didn't finish
+    Cancel,
+    /// Create the jobs of the head commit again
+    Run,
+}
+
+impl Command {
+    /// Parse the command a comment starts with, if any
+    pub fn parse(body: &str) -> Option<Self> {
+        let mut words = body.lines().next()?.split_whitespace();
+        if words.next()? != "/devenv" {
+            return None;
+        }
+        match (words.next()?, words.next(), words.next()) {
+            ("approve", None, None) => Some(Self::Approve),
+            ("retry", platform, None) => Some(Self::Retry {
+                platform: platform.map(str::parse).transpose().ok()?,
+            }),
+            ("cancel", None, None) => Some(Self::Cancel),
+            ("run", None, None) => Some(Self::Run),
+            _ => None,
+        }
+    }
+
+    /// The comment answering a command that found nothing to act on
+    ///
+    /// Commands that acted on the commit get a reaction instead.
+    pub fn reply(&self, short_rev: &str, acted: bool) -> Option<String> {
+        if acted {
+            return None;
+        }
+        Some(match self {
+            Self::Approve => format!("No jobs of {short_rev} await approval."),
+            Self::Retry { .. } => format!("No jobs of {short_rev} failed, nothing to retry."),
+            Self::Cancel => format!("No jobs of {short_rev} are left to cancel."),
+            Self::Run => format!("{short_rev} has no devenv.nix, nothing to run."),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Command::parse("/devenv approve"), Some(Command::Approve));
+        assert_eq!(
+            Command::parse("  /devenv   approve \nLooks safe to me"),
+            Some(Command::Approve)
+        );
+        assert_eq!(Command::parse("/devenv cancel"), Some(Command::Cancel));
+        assert_eq!(Command::parse("/devenv run"), Some(Command::Run));
+        assert_eq!(Command::parse("/devenv"), None);
+        assert_eq!(Command::parse("/devenv approve everything"), None);
+        assert_eq!(Command::parse("/devenv unknown"), None);
+        // Only the first line counts
+        assert_eq!(Command::parse("LGTM\n/devenv approve"), None);
+    }
+
+    #[test]
+    fn test_parse_retry() {
+        assert_eq!(
+            Command::parse("/devenv retry"),
+            Some(Command::Retry { platform: None })
+        );
+        assert_eq!(
+            Command::parse("/devenv retry aarch64-darwin"),
+            Some(Command::Retry {
+                platform: Some(Platform::AArch64Darwin)
+            })
+        );
+        assert_eq!(Command::parse("/devenv retry windows"), None);
+        assert_eq!(Command::parse("/devenv retry x86_64-linux again"), None);
+    }
+
+    #[test]
+    fn test_reply() {
+        assert_eq!(Command::Run.reply("abc1234", true), None);
+        assert_eq!(
+            Command::Retry { platform: None }.reply("abc1234", true),
+            None
+        );
+        assert_eq!(
+            Command::Approve.reply("abc1234", false).as_deref(),
+            Some("No jobs of abc1234 await approval.")
+        );
+        assert_eq!(
+            Command::Retry {
+                platform: Some(Platform::X86_64Linux)
+            }
+            .reply("abc1234", false)
+            .as_deref(),
+            Some("No jobs of abc1234 failed, nothing to retry.")
+        );
+        assert_eq!(
+            Command::Cancel.reply("abc1234", false).as_deref(),
+            Some("No jobs of abc1234 are left to cancel.")
+        );
+        assert_eq!(
+            Command::Run.reply("abc1234", false).as_deref(),
+            Some("abc1234 has no devenv.nix, nothing to run.")
+        );
+    }
+}
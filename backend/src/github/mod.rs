@@ -1,2 +1,6 @@
+pub mod check_output;
+pub mod commands;
 pub mod model;
+pub mod schedule;
+pub mod secrets;
 pub mod serve;
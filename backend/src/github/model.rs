@@ -1,5 +1,8 @@
+use super::check_output;
+use super::secrets::GitHubSecret;
 use crate::config::AppState;
-use crate::job::model::Job;
+use crate::job::model::{Job, JobStatus, Trigger};
+use crate::runner::cloudconfig::{FinalCloud, JobSpec};
 use crate::schema::{
     github_commit, github_installation, github_instance, github_owner, github_repo, jobs,
     jobs_github,
@@ -8,9 +11,12 @@ use async_trait::async_trait;
 use devenv_runner::protocol;
 use diesel::prelude::*;
 use diesel_async::RunQueryDsl;
-use eyre::Result;
+use eyre::{OptionExt, Result};
 use serde::Deserialize;
 use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
 use utoipa::ToSchema;
 
 #[derive(Queryable, Selectable, Identifiable)]
@@ -231,24 +237,69 @@ pub struct GitHubCommit {
     pub repo_id: i64,
     pub author: String,
     pub message: String,
+    /// Whether the commit was pushed to the repository itself rather than to a fork
+    pub trusted: bool,
 }
 
+/// The identifier of the check run action that retries its job
+pub const RETRY_ACTION: &str = "retry";
+
+/// How long after a push or pull request event the other one for the same commit is ignored
+pub const PUSH_PULL_REQUEST_WINDOW: chrono::TimeDelta = chrono::TimeDelta::minutes(5);
+
 impl GitHubCommit {
+    /// Create a job for the commit, awaiting approval for pull requests from forks
     pub async fn create_job(
         &self,
         app_state: AppState,
-        vm_config: devenv_runner::protocol::VM,
+        spec: &JobSpec,
+        artifacts: &[String],
+        needs: &[uuid::Uuid],
+        priority: i32,
+        trigger: Trigger,
     ) -> Result<JobGitHub> {
         <JobGitHub as SourceControlIntegration>::create_job(
             self.id,
             &self.rev,
             self.repo_id,
             app_state,
-            vm_config,
+            spec,
+            artifacts,
+            needs,
+            priority,
+            trigger,
+            !self.trusted && trigger == Trigger::PullRequest,
         )
         .await
     }
 
+    /// Create all jobs declared in the cloud configuration, wiring up their dependencies
+    pub async fn create_jobs(
+        &self,
+        app_state: AppState,
+        cloud_config: &FinalCloud,
+        priority: i32,
+        trigger: Trigger,
+    ) -> Result<Vec<JobGitHub>> {
+        let mut jobs: Vec<JobGitHub> = Vec::with_capacity(cloud_config.jobs().len());
+        for spec in cloud_config.jobs() {
+            // Jobs are ordered so that the ones needed are already created
+            let needs: Vec<uuid::Uuid> = spec.needs.iter().map(|&i| jobs[i].job_id).collect();
+            let job = self
+                .create_job(
+                    app_state.clone(),
+                    spec,
+                    cloud_config.artifacts(),
+                    &needs,
+                    priority,
+                    trigger,
+                )
+                .await?;
+            jobs.push(job);
+        }
+        Ok(jobs)
+    }
+
     pub async fn get_by_id(
         conn: &mut diesel_async::AsyncPgConnection,
         id: uuid::Uuid,
@@ -281,38 +332,49 @@ impl GitHubCommit {
             .await
     }
 
-    pub async fn get_jobs_by_commit_id(
+    /// Get the latest record of a commit by repo and rev
+    pub async fn get_by_repo_and_rev(
         conn: &mut diesel_async::AsyncPgConnection,
-        commit_id: uuid::Uuid,
-    ) -> Result<Vec<(JobGitHub, crate::job::model::Job)>> {
-        let job_pairs = jobs_github::table
-            .inner_join(
-                crate::schema::jobs::table.on(jobs_github::job_id.eq(crate::schema::jobs::id)),
-            )
-            .filter(jobs_github::commit_id.eq(commit_id))
-            .order_by(crate::schema::jobs::platform)
-            .select((JobGitHub::as_select(), crate::job::model::Job::as_select()))
-            .load::<(JobGitHub, crate::job::model::Job)>(conn)
-            .await?;
-
-        Ok(job_pairs)
+        repo_id: i64,
+        rev: &str,
+    ) -> Result<Self> {
+        Self::find_by_repo_and_rev(conn, repo_id, rev)
+            .await?
+            .ok_or_eyre("commit not found")
     }
 
-    /// Get commit by repo and rev
-    pub async fn get_by_repo_and_rev(
+    /// Find the latest record of a commit by repo and rev, if it was seen
+    pub async fn find_by_repo_and_rev(
         conn: &mut diesel_async::AsyncPgConnection,
         repo_id: i64,
         rev: &str,
-    ) -> Result<Self> {
+    ) -> Result<Option<Self>> {
         let commit = github_commit::table
             .filter(github_commit::repo_id.eq(repo_id))
             .filter(github_commit::rev.eq(rev))
+            .order_by(github_commit::id.desc()) // UUIDv7 is time ordered
             .select(GitHubCommit::as_select())
             .first(conn)
-            .await?;
+            .await
+            .optional()?;
         Ok(commit)
     }
 
+    /// The trigger the jobs of the commit were created for, if it has jobs
+    pub async fn trigger(
+        &self,
+        conn: &mut diesel_async::AsyncPgConnection,
+    ) -> Result<Option<Trigger>> {
+        let trigger = jobs::table
+            .inner_join(jobs_github::table.on(jobs_github::job_id.eq(jobs::id)))
+            .filter(jobs_github::commit_id.eq(self.id))
+            .select(jobs::trigger)
+            .first(conn)
+            .await
+            .optional()?;
+        Ok(trigger)
+    }
+
     /// Create a new commit
     pub async fn create(conn: &mut diesel_async::AsyncPgConnection, commit: Self) -> Result<()> {
         diesel::insert_into(github_commit::table)
@@ -322,18 +384,54 @@ impl GitHubCommit {
         Ok(())
     }
 
-    /// Get commits by repo ordered by newest first
-    pub async fn get_by_repo_ordered(
+    /// Create a commit from a push or pull request event, unless the other one just recorded it
+    ///
+    /// GitHub sends both events at once for branches with an open pull request, so
+    /// they're serialized per commit. Only a record of the same ref within
+    /// [`PUSH_PULL_REQUEST_WINDOW`] counts, so the commit still runs when it lands on
+    /// another branch later. Returns false if the commit was already recorded.
+    pub async fn create_unless_seen(
         conn: &mut diesel_async::AsyncPgConnection,
-        repo_id: i64,
-    ) -> Result<Vec<Self>> {
-        let commits = github_commit::table
-            .filter(github_commit::repo_id.eq(repo_id))
-            .order_by(github_commit::id.desc()) // UUIDv7 is time ordered
-            .select(GitHubCommit::as_select())
-            .load::<GitHubCommit>(conn)
+        commit: Self,
+    ) -> Result<bool> {
+        let created = conn
+            .build_transaction()
+            .run::<_, diesel::result::Error, _>(|conn| {
+                Box::pin(async move {
+                    diesel::sql_query("SELECT pg_advisory_xact_lock(hashtext($1))")
+                        .bind::<diesel::sql_types::Text, _>(format!(
+                            "github_commit:{}:{}",
+                            commit.repo_id, commit.rev
+                        ))
+                        .execute(conn)
+                        .await?;
+                    // Commit ids are UUIDv7, so they start with the time they were recorded at
+                    let window_start = (chrono::Utc::now() - PUSH_PULL_REQUEST_WINDOW)
+                        .timestamp_millis()
+                        .max(0) as u64;
+                    let window_start =
+                        uuid::Builder::from_unix_timestamp_millis(window_start, &[0; 10])
+                            .into_uuid();
+                    let seen = diesel::select(diesel::dsl::exists(
+                        github_commit::table
+                            .filter(github_commit::repo_id.eq(commit.repo_id))
+                            .filter(github_commit::rev.eq(&commit.rev))
+                            .filter(github_commit::git_ref.eq(&commit.r#ref))
+                            .filter(github_commit::id.ge(window_start)),
+                    ))
+                    .get_result::<bool>(conn)
+                    .await?;
+                    if !seen {
+                        diesel::insert_into(github_commit::table)
+                            .values(&commit)
+                            .execute(conn)
+                            .await?;
+                    }
+                    Ok(!seen)
+                })
+            })
             .await?;
-        Ok(commits)
+        Ok(created)
     }
 }
 
@@ -388,6 +486,23 @@ pub struct RepoInfo {
     pub latest_commit: Option<Commit>,
 }
 
+/// Installation access tokens handed out to jobs, keyed by job id
+///
+/// Tokens are revoked as soon as their job completes. If the backend restarts
+/// before that, they still expire on their own after an hour.
+#[derive(Clone, Default)]
+pub struct JobTokens(Arc<Mutex<HashMap<uuid::Uuid, String>>>);
+
+impl JobTokens {
+    pub async fn insert(&self, job_id: uuid::Uuid, token: String) {
+        self.0.lock().await.insert(job_id, token);
+    }
+
+    pub async fn take(&self, job_id: &uuid::Uuid) -> Option<String> {
+        self.0.lock().await.remove(job_id)
+    }
+}
+
 #[async_trait]
 pub trait SourceControlIntegration {
     /// Gets a specific job by ID
@@ -405,13 +520,21 @@ pub trait SourceControlIntegration {
         id: uuid::Uuid,
     ) -> Result<()>;
 
+    /// Builds the job specification handed to the runner that claimed the job
+    async fn job_config(app_state: &AppState, id: uuid::Uuid) -> Result<protocol::JobConfig>;
+
     /// Creates a job for the CI/CD system
     async fn create_job(
         commit_id: uuid::Uuid,
         rev: &str,
         repo_id: i64,
         app_state: AppState,
-        vm_config: devenv_runner::protocol::VM,
+        spec: &JobSpec,
+        artifacts: &[String],
+        needs: &[uuid::Uuid],
+        priority: i32,
+        trigger: Trigger,
+        awaiting_approval: bool,
     ) -> Result<Self>
     where
         Self: Sized;
@@ -429,6 +552,124 @@ impl JobGitHub {
         Ok(client)
     }
 
+    /// Load the cloud configuration of a repository at a ref
+    ///
+    /// Returns `None` if the ref has no devenv.nix, in which case nothing is built.
+    pub async fn fetch_cloud_config(
+        installation_client: &octocrab::Octocrab,
+        owner: &str,
+        repo: &str,
+        r#ref: &str,
+    ) -> Result<Option<FinalCloud>> {
+        // Check if devenv.nix exists
+        let content_items = match installation_client
+            .repos(owner, repo)
+            .get_content()
+            .path("devenv.nix")
+            .r#ref(r#ref)
+            .send()
+            .await
+        {
+            Ok(content_items) => content_items,
+            Err(octocrab::Error::GitHub { source, .. }) if source.status_code.as_u16() == 404 => {
+                return Ok(None);
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if content_items.items.is_empty() {
+            return Ok(None);
+        }
+
+        // Try to fetch devenv.yaml to determine VM configurations
+        let devenv_yaml_content = match installation_client
+            .repos(owner, repo)
+            .get_content()
+            .path("devenv.yaml")
+            .r#ref(r#ref)
+            .send()
+            .await
+        {
+            Ok(content) if !content.items.is_empty() => {
+                if let Some(content) = content.items[0].decoded_content() {
+                    Some(content)
+                } else {
+                    tracing::warn!("Failed to decode devenv.yaml content");
+                    None
+                }
+            }
+            _ => None,
+        };
+
+        // Parse devenv.yaml and get the job configurations
+        let yaml_str = devenv_yaml_content.as_deref().unwrap_or("");
+        let cloud_config = FinalCloud::new(yaml_str)
+            .map_err(|e| eyre::eyre!("Failed to parse devenv.yaml: {}", e))?;
+        Ok(Some(cloud_config))
+    }
+
+    /// Offer to retry the job of a completed check run from the GitHub UI
+    ///
+    /// octocrab's check run builder has no actions, so they're set on their own.
+    async fn offer_retry(
+        installation_client: &octocrab::Octocrab,
+        owner: &str,
+        repo: &str,
+        check_run_id: i64,
+    ) -> Result<()> {
+        let _: serde_json::Value = installation_client
+            .patch(
+                format!("/repos/{owner}/{repo}/check-runs/{check_run_id}"),
+                Some(&serde_json::json!({
+                    "actions": [{
+                        "label": "Retry",
+                        "description": "Run this job again",
+                        "identifier": RETRY_ACTION,
+                    }],
+                })),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Mint an installation token that can only read the contents of one repository
+    async fn create_repo_token(
+        app_state: &AppState,
+        installation_id: i64,
+        repo_id: i64,
+    ) -> Result<octocrab::models::InstallationToken> {
+        let token = app_state
+            .github
+            .post(
+                format!("/app/installations/{installation_id}/access_tokens"),
+                Some(&serde_json::json!({
+                    "repository_ids": [repo_id],
+                    "permissions": { "contents": "read" },
+                })),
+            )
+            .await?;
+        Ok(token)
+    }
+
+    /// Revoke an installation token handed out to a job, if there is one
+    async fn revoke_job_token(app_state: &AppState, job_id: uuid::Uuid) -> Result<()> {
+        let Some(token) = app_state.job_tokens.take(&job_id).await else {
+            return Ok(());
+        };
+
+        let client = octocrab::Octocrab::builder()
+            .personal_token(token)
+            .build()?;
+        let response = client._delete("/installation/token", None::<&()>).await?;
+        if !response.status().is_success() {
+            return Err(eyre::eyre!(
+                "Failed to revoke token for job {}: {}",
+                job_id,
+                response.status()
+            ));
+        }
+        Ok(())
+    }
+
     async fn get_repo_and_owner(
         &self,
         conn: &mut diesel_async::AsyncPgConnection,
@@ -473,24 +714,6 @@ impl JobGitHub {
         Ok(owners_with_repos)
     }
 
-    pub async fn get_jobs_for_repo(
-        conn: &mut diesel_async::AsyncPgConnection,
-        repo_id: i64,
-    ) -> Result<Vec<(JobGitHub, crate::job::model::Job)>> {
-        let job_pairs = jobs_github::table
-            .inner_join(github_commit::table.on(jobs_github::commit_id.eq(github_commit::id)))
-            .inner_join(
-                crate::schema::jobs::table.on(jobs_github::job_id.eq(crate::schema::jobs::id)),
-            )
-            .filter(github_commit::repo_id.eq(repo_id))
-            .order_by(crate::schema::jobs::platform)
-            .select((JobGitHub::as_select(), crate::job::model::Job::as_select()))
-            .load::<(JobGitHub, crate::job::model::Job)>(conn)
-            .await?;
-
-        Ok(job_pairs)
-    }
-
     pub async fn get_all_jobs_for_commit(
         conn: &mut diesel_async::AsyncPgConnection,
         commit_id: uuid::Uuid,
@@ -567,6 +790,514 @@ impl JobGitHub {
         Ok(updated_github)
     }
 
+    /// Fail a job because of the infrastructure rather than its tasks, explaining why on the check run
+    ///
+    /// The job is retried like any other infrastructure failure.
+    pub async fn fail_with_infrastructure_error(
+        app_state: &AppState,
+        id: uuid::Uuid,
+        reason: &str,
+    ) -> Result<()> {
+        <Self as SourceControlIntegration>::update_status(
+            app_state.clone(),
+            protocol::JobStatus::Complete(protocol::CompletionStatus::InfrastructureFailure),
+            id,
+        )
+        .await?;
+
+        let conn = &mut app_state.pool.get().await?;
+        let job_github = Self::get_job_by_id(conn, id).await?;
+        let job = Job::get_by_id(conn, id).await?;
+        let (repo, owner) = job_github.get_repo_and_owner(conn).await?;
+        let installation = GithubInstallation::get_for_owner_id(conn, owner.id).await?;
+        let installation_client = Self::get_installation_client(app_state, installation.id)?;
+
+        let output = Self::completed_output(
+            app_state,
+            &job,
+            &protocol::CompletionStatus::InfrastructureFailure,
+            job.finished_at.unwrap_or_else(chrono::Utc::now),
+            Some(reason),
+            &owner,
+            &repo,
+        )
+        .await;
+        installation_client
+            .checks(&owner.login, &repo.name)
+            .update_check_run(octocrab::models::CheckRunId(job_github.check_run_id as u64))
+            .output(output)
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    /// Retry a job that failed because of the infrastructure, unless it already was too often
+    ///
+    /// Returns the retry, which takes over the jobs waiting on the failed one.
+    async fn retry_infrastructure_failure(
+        app_state: &AppState,
+        id: uuid::Uuid,
+    ) -> Result<Option<Job>> {
+        let conn = &mut app_state.pool.get().await?;
+        let job = Job::get_by_id(conn, id).await?;
+        let failures = job.infrastructure_failures(conn).await?;
+        if failures > app_state.config.job.max_infrastructure_retries {
+            tracing::warn!(
+                "Not retrying job {} after {} infrastructure failures in a row",
+                id,
+                failures
+            );
+            return Ok(None);
+        }
+
+        let job_github = Self::get_job_by_id(conn, id).await?;
+        let commit: GitHubCommit = github_commit::table
+            .filter(github_commit::id.eq(job_github.commit_id))
+            .select(GitHubCommit::as_select())
+            .first(conn)
+            .await?;
+        let retried_job = Self::retry_with_check_run(app_state, conn, job, &commit).await?;
+        tracing::info!(
+            "Retrying job {} as {} after an infrastructure failure",
+            id,
+            retried_job.id
+        );
+        Ok(Some(retried_job))
+    }
+
+    /// Retry a job that didn't succeed, giving the retry its own check run
+    ///
+    /// The retry runs even if GitHub doesn't get its check run.
+    async fn retry_with_check_run(
+        app_state: &AppState,
+        conn: &mut diesel_async::AsyncPgConnection,
+        job: Job,
+        commit: &GitHubCommit,
+    ) -> Result<Job> {
+        let (retried_job, requeued) = conn
+            .build_transaction()
+            .run::<_, diesel::result::Error, _>(|conn| {
+                Box::pin(async move { job.retry(conn).await })
+            })
+            .await?;
+        if let Err(e) = Self::create_with_check_run(conn, app_state, &retried_job, commit).await {
+            tracing::error!(
+                "Failed to create the check run of retried job {}: {}",
+                retried_job.id,
+                e
+            );
+        }
+        for job in &requeued {
+            Self::recreate_check_run(app_state, conn, job).await;
+        }
+        crate::runner::serve::notify_runners_about_job(app_state, &retried_job).await;
+        Ok(retried_job)
+    }
+
+    /// Give a job that's queued again a new check run, as GitHub can't reopen its completed one
+    ///
+    /// The job runs even if GitHub doesn't get its check run.
+    pub async fn recreate_check_run(
+        app_state: &AppState,
+        conn: &mut diesel_async::AsyncPgConnection,
+        job: &Job,
+    ) {
+        let result = async {
+            let (_, job_github, commit) = Job::get_with_github_details(conn, job.id).await?;
+            job_github
+                .create_check_run_for_job(conn, app_state, job, &commit)
+                .await
+        }
+        .await;
+        if let Err(e) = result {
+            tracing::error!("Failed to create a new check run for job {}: {}", job.id, e);
+        }
+    }
+
+    /// Queue the jobs of a commit from a fork that are awaiting approval
+    ///
+    /// Their neutral check runs can't be reopened, so each approved job gets a
+    /// new one. Returns the number of approved jobs.
+    pub async fn approve(
+        app_state: &AppState,
+        conn: &mut diesel_async::AsyncPgConnection,
+        repo_id: i64,
+        rev: &str,
+        approver: &str,
+    ) -> Result<usize> {
+        let awaiting: Vec<uuid::Uuid> = jobs::table
+            .inner_join(jobs_github::table.on(jobs_github::job_id.eq(jobs::id)))
+            .inner_join(github_commit::table.on(github_commit::id.eq(jobs_github::commit_id)))
+            .filter(github_commit::repo_id.eq(repo_id))
+            .filter(github_commit::rev.eq(rev))
+            .filter(jobs::status.eq(JobStatus::awaiting_approval()))
+            .select(jobs::id)
+            .load(conn)
+            .await?;
+        let approved = Job::approve(conn, &awaiting).await?;
+
+        for job in &approved {
+            Self::recreate_check_run(app_state, conn, job).await;
+            if Job::get_dependencies(conn, job.id).await?.is_empty() {
+                crate::runner::serve::notify_runners_about_job(app_state, job).await;
+            }
+        }
+        if !approved.is_empty() {
+            tracing::info!("{} approved {} jobs of {}", approver, approved.len(), rev);
+        }
+        Ok(approved.len())
+    }
+
+    /// Cancel a job unless it finished, stopping its VM if it's running
+    ///
+    /// Returns false if the job already finished.
+    async fn cancel(
+        app_state: &AppState,
+        conn: &mut diesel_async::AsyncPgConnection,
+        job_id: uuid::Uuid,
+    ) -> Result<bool> {
+        let (true, job) = Job::cancel(conn, job_id).await? else {
+            return Ok(false);
+        };
+        // The runner stops the VM of a running job
+        if let Some(runner_id) = job.and_then(|job| job.runner_id) {
+            app_state
+                .runner_state
+                .try_send_to(
+                    &runner_id,
+                    protocol::ServerMessage::JobCancelled { id: job_id },
+                )
+                .await;
+        }
+        if let Err(e) = <Self as SourceControlIntegration>::update_status(
+            app_state.clone(),
+            protocol::JobStatus::Complete(protocol::CompletionStatus::Cancelled),
+            job_id,
+        )
+        .await
+        {
+            tracing::error!("Failed to update status of cancelled job {}: {}", job_id, e);
+        }
+        Ok(true)
+    }
+
+    /// Cancel the unfinished jobs of a commit, returning how many were cancelled
+    pub async fn cancel_commit(
+        app_state: &AppState,
+        conn: &mut diesel_async::AsyncPgConnection,
+        repo_id: i64,
+        rev: &str,
+    ) -> Result<usize> {
+        let unfinished: Vec<uuid::Uuid> = jobs::table
+            .inner_join(jobs_github::table.on(jobs_github::job_id.eq(jobs::id)))
+            .inner_join(github_commit::table.on(github_commit::id.eq(jobs_github::commit_id)))
+            .filter(github_commit::repo_id.eq(repo_id))
+            .filter(github_commit::rev.eq(rev))
+            .filter(jobs::status.eq_any(vec![
+                JobStatus::queued(),
+                JobStatus::running(),
+                JobStatus::awaiting_approval(),
+            ]))
+            .select(jobs::id)
+            .load(conn)
+            .await?;
+
+        let mut cancelled = 0;
+        for job_id in unfinished {
+            if Self::cancel(app_state, conn, job_id).await? {
+                cancelled += 1;
+            }
+        }
+        Ok(cancelled)
+    }
+
+    /// Retry the latest runs of the jobs of a commit that didn't succeed
+    ///
+    /// Only the jobs of the latest record of the commit are retried, on the given
+    /// platform or all of them. Returns the retries.
+    pub async fn retry_commit(
+        app_state: &AppState,
+        conn: &mut diesel_async::AsyncPgConnection,
+        repo_id: i64,
+        rev: &str,
+        platform: Option<&protocol::Platform>,
+    ) -> Result<Vec<Job>> {
+        let commit: GitHubCommit = github_commit::table
+            .filter(github_commit::repo_id.eq(repo_id))
+            .filter(github_commit::rev.eq(rev))
+            .order_by(github_commit::id.desc()) // UUIDv7 is time ordered
+            .select(GitHubCommit::as_select())
+            .first(conn)
+            .await?;
+        // Jobs are retried in the order they were created, so retries of the jobs
+        // others depend on come first
+        let latest: Vec<Job> = jobs::table
+            .inner_join(jobs_github::table.on(jobs_github::job_id.eq(jobs::id)))
+            .filter(jobs_github::commit_id.eq(commit.id))
+            .filter(jobs::retried_job_id.is_null())
+            .order_by(jobs::id)
+            .select(Job::as_select())
+            .load::<Job>(conn)
+            .await?;
+        let failed = retryable_on(latest, platform);
+
+        let mut retries = Vec::with_capacity(failed.len());
+        for job in failed {
+            let id = job.id;
+            let retried_job = Self::retry_with_check_run(app_state, conn, job, &commit).await?;
+            tracing::info!("Retrying job {} as {}", id, retried_job.id);
+            retries.push(retried_job);
+        }
+        Ok(retries)
+    }
+
+    /// Retry the job of a check run GitHub was asked to run again
+    ///
+    /// Check runs are found by id, or by their external id, the id of their job.
+    /// Jobs that were retried already have their latest run retried. Returns
+    /// `None` for check runs of other apps and for jobs that can't be retried.
+    pub async fn retry_check_run(
+        app_state: &AppState,
+        conn: &mut diesel_async::AsyncPgConnection,
+        check_run_id: i64,
+        external_id: Option<&str>,
+    ) -> Result<Option<Job>> {
+        let external_job_id = external_id.and_then(|id| id.parse::<uuid::Uuid>().ok());
+        let Some(job_github) = jobs_github::table
+            .filter(
+                jobs_github::check_run_id
+                    .eq(check_run_id)
+                    .or(jobs_github::job_id.nullable().eq(external_job_id)),
+            )
+            .select(JobGitHub::as_select())
+            .first(conn)
+            .await
+            .optional()?
+        else {
+            return Ok(None);
+        };
+
+        let mut job = Job::get_by_id(conn, job_github.job_id).await?;
+        while let Some(retried_job_id) = job.retried_job_id {
+            job = Job::get_by_id(conn, retried_job_id).await?;
+        }
+        if !job.is_retryable() {
+            tracing::info!("Not retrying job {} of check run {}", job.id, check_run_id);
+            return Ok(None);
+        }
+
+        let commit: GitHubCommit = github_commit::table
+            .filter(github_commit::id.eq(job_github.commit_id))
+            .select(GitHubCommit::as_select())
+            .first(conn)
+            .await?;
+        let id = job.id;
+        let retried_job = Self::retry_with_check_run(app_state, conn, job, &commit).await?;
+        tracing::info!(
+            "Retrying job {} as {} for check run {}",
+            id,
+            retried_job.id,
+            check_run_id
+        );
+        Ok(Some(retried_job))
+    }
+
+    /// Whether a GitHub user may push to a repository, and so approve the jobs of forks
+    pub async fn has_write_access(
+        installation_client: &octocrab::Octocrab,
+        owner: &str,
+        repo: &str,
+        login: &str,
+    ) -> Result<bool> {
+        #[derive(Deserialize)]
+        struct Permission {
+            permission: String,
+        }
+
+        let permission: Permission = match installation_client
+            .get(
+                format!("/repos/{owner}/{repo}/collaborators/{login}/permission"),
+                None::<&()>,
+            )
+            .await
+        {
+            Ok(permission) => permission,
+            // Users that aren't collaborators
+            Err(octocrab::Error::GitHub { source, .. }) if source.status_code.as_u16() == 404 => {
+                return Ok(false);
+            }
+            Err(e) => return Err(e.into()),
+        };
+        Ok(matches!(permission.permission.as_str(), "admin" | "write"))
+    }
+
+    /// Whether a branch of a repository is the head of an open draft pull request
+    pub async fn has_draft_pull_request(
+        installation_client: &octocrab::Octocrab,
+        owner: &str,
+        repo: &str,
+        branch: &str,
+    ) -> Result<bool> {
+        let pull_requests = installation_client
+            .pulls(owner, repo)
+            .list()
+            .state(octocrab::params::State::Open)
+            .head(format!("{owner}:{branch}"))
+            .send()
+            .await?;
+        Ok(pull_requests
+            .items
+            .iter()
+            .any(|pull_request| pull_request.draft == Some(true)))
+    }
+
+    /// Cancel the unfinished jobs of older commits on the same ref as a new commit
+    ///
+    /// Only jobs created for pushes and pull requests are superseded, scheduled and
+    /// dispatched runs finish. Returns the number of cancelled jobs.
+    pub async fn cancel_superseded(app_state: &AppState, commit: &GitHubCommit) -> Result<usize> {
+        let conn = &mut app_state.pool.get().await?;
+        let superseded: Vec<uuid::Uuid> = jobs::table
+            .inner_join(jobs_github::table.on(jobs_github::job_id.eq(jobs::id)))
+            .inner_join(github_commit::table.on(github_commit::id.eq(jobs_github::commit_id)))
+            .filter(github_commit::repo_id.eq(commit.repo_id))
+            .filter(github_commit::git_ref.eq(&commit.r#ref))
+            // Commit ids are UUIDv7, so lower ones were recorded earlier
+            .filter(github_commit::id.lt(commit.id))
+            // Pushes and pull requests record the same commit separately
+            .filter(github_commit::rev.ne(&commit.rev))
+            .filter(jobs::status.eq_any(vec![
+                JobStatus::queued(),
+                JobStatus::running(),
+                JobStatus::awaiting_approval(),
+            ]))
+            .filter(jobs::trigger.eq_any(vec![Trigger::Push, Trigger::PullRequest]))
+            .select(jobs::id)
+            .load(conn)
+            .await?;
+
+        let mut cancelled = 0;
+        for job_id in superseded {
+            if Self::cancel(app_state, conn, job_id).await? {
+                cancelled += 1;
+            }
+        }
+        if cancelled > 0 {
+            tracing::info!(
+                "Cancelled {} jobs superseded by {} on {}",
+                cancelled,
+                commit.rev,
+                commit.r#ref
+            );
+        }
+        Ok(cancelled)
+    }
+
+    /// Link to the page of a job, which also shows its full log
+    fn details_url(
+        app_state: &AppState,
+        owner: &GithubOwner,
+        repo: &GitHubRepo,
+        id: uuid::Uuid,
+    ) -> String {
+        format!(
+            "{}/github/{}/{}#{}",
+            app_state.config.base_url, owner.login, repo.name, id
+        )
+    }
+
+    /// Fetch the last lines of the log of a job from the logger
+    async fn fetch_log_tail(app_state: &AppState, job: &Job) -> Result<Vec<String>> {
+        #[derive(Deserialize)]
+        struct LogLine {
+            message: String,
+        }
+
+        let lines: Vec<LogLine> = reqwest::Client::new()
+            .get(format!(
+                "{}/tail",
+                job.log_url(&app_state.config.logger_url)
+            ))
+            .query(&[("lines", app_state.config.job.check_run_log_lines)])
+            // The last lines can still be on their way when the job completes
+            .query(&[("wait", true)])
+            .timeout(std::time::Duration::from_secs(15))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(lines.into_iter().map(|line| line.message).collect())
+    }
+
+    /// Output of the check run of a completed job, ending with the tail of its log
+    async fn completed_output(
+        app_state: &AppState,
+        job: &Job,
+        completion_status: &protocol::CompletionStatus,
+        finished_at: chrono::DateTime<chrono::Utc>,
+        reason: Option<&str>,
+        owner: &GithubOwner,
+        repo: &GitHubRepo,
+    ) -> octocrab::params::checks::CheckRunOutput {
+        // Jobs that never started have no log, and a summary is better than no output
+        let log_tail = if job.started_at.is_some() {
+            Self::fetch_log_tail(app_state, job)
+                .await
+                .unwrap_or_else(|e| {
+                    tracing::warn!("Failed to fetch the log of job {}: {}", job.id, e);
+                    vec![]
+                })
+        } else {
+            vec![]
+        };
+        check_output::render(
+            job,
+            completion_status,
+            finished_at,
+            reason,
+            &log_tail,
+            &Self::details_url(app_state, owner, repo, job.id),
+        )
+    }
+
+    /// Queue the jobs that were waiting on a successful job, or skip them if it didn't succeed
+    async fn resolve_dependents(
+        app_state: &AppState,
+        id: uuid::Uuid,
+        completion_status: &protocol::CompletionStatus,
+    ) -> Result<()> {
+        if *completion_status == protocol::CompletionStatus::Success {
+            let released = {
+                let conn = &mut app_state.pool.get().await?;
+                Job::release_dependents(conn, id).await?
+            };
+            for job in released {
+                tracing::info!("Job {} is unblocked by job {}", job.id, id);
+                crate::runner::serve::notify_runners_about_job(app_state, &job).await;
+            }
+        } else {
+            let skipped = {
+                let conn = &mut app_state.pool.get().await?;
+                Job::skip_dependents(conn, id).await?
+            };
+            // Updating the skipped jobs in turn skips the jobs depending on them
+            for job in skipped {
+                tracing::info!("Skipping job {} as job {} didn't succeed", job.id, id);
+                if let Err(e) = <Self as SourceControlIntegration>::update_status(
+                    app_state.clone(),
+                    protocol::JobStatus::Complete(protocol::CompletionStatus::Skipped),
+                    job.id,
+                )
+                .await
+                {
+                    tracing::error!("Failed to update skipped job {}: {}", job.id, e);
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Helper to create a GitHub check run and return the check_run_id
     async fn create_github_check_run(
         conn: &mut diesel_async::AsyncPgConnection,
@@ -592,18 +1323,24 @@ impl JobGitHub {
 
         // Create check run
         let checks = installation_client.checks(&owner.login, &repo.name);
-        let details_url = format!(
-            "{}/github/{}/{}#{}",
-            app_state.config.base_url, owner.login, repo.name, job.id
-        );
+        let details_url = Self::details_url(app_state, &owner, &repo, job.id);
 
         let check_run = checks
-            .create_check_run(format!("devenv ({})", job.platform), &commit.rev)
+            .create_check_run(format!("{} ({})", job.name, job.platform), &commit.rev)
             .details_url(details_url)
-            .external_id(job.id)
-            .status(octocrab::params::checks::CheckRunStatus::Queued)
-            .send()
-            .await?;
+            .external_id(job.id);
+        // Retries of jobs from forks that were never approved await approval too
+        let check_run = if matches!(job.status.0, protocol::JobStatus::AwaitingApproval) {
+            check_run
+                .status(octocrab::params::checks::CheckRunStatus::Completed)
+                .conclusion(octocrab::params::checks::CheckRunConclusion::Neutral)
+                .output(check_output::render_awaiting_approval(
+                    &app_state.config.github.approval_label,
+                ))
+        } else {
+            check_run.status(octocrab::params::checks::CheckRunStatus::Queued)
+        };
+        let check_run = check_run.send().await?;
 
         Ok(check_run.id.0 as i64)
     }
@@ -632,6 +1369,26 @@ impl SourceControlIntegration for JobGitHub {
         status: protocol::JobStatus,
         id: uuid::Uuid,
     ) -> Result<()> {
+        // Infrastructure failures are retried, the retry takes over the jobs waiting on this one
+        let retried_job = match &status {
+            protocol::JobStatus::Complete(protocol::CompletionStatus::InfrastructureFailure) => {
+                Self::retry_infrastructure_failure(&app_state, id)
+                    .await
+                    .unwrap_or_else(|e| {
+                        tracing::error!("Failed to retry job {}: {}", id, e);
+                        None
+                    })
+            }
+            _ => None,
+        };
+
+        // Release or skip the jobs waiting on this one before talking to GitHub
+        if let (protocol::JobStatus::Complete(completion_status), None) = (&status, &retried_job) {
+            if let Err(e) = Self::resolve_dependents(&app_state, id, completion_status).await {
+                tracing::error!("Failed to resolve jobs depending on {}: {}", id, e);
+            }
+        }
+
         let conn = &mut app_state.pool.get().await?;
 
         // Get the GitHub job directly
@@ -646,7 +1403,19 @@ impl SourceControlIntegration for JobGitHub {
         let check =
             checks.update_check_run(octocrab::models::CheckRunId(job_github.check_run_id as u64));
         match status {
-            protocol::JobStatus::Queued => {}
+            // Jobs only go back to the queue when their runner was lost,
+            // the next runner to claim the job gets a new token
+            protocol::JobStatus::Queued => {
+                if let Err(e) = Self::revoke_job_token(&app_state, job_github.job_id).await {
+                    tracing::warn!("{}", e);
+                }
+                check
+                    .status(octocrab::params::checks::CheckRunStatus::Queued)
+                    .send()
+                    .await?;
+            }
+            // Jobs are created awaiting approval, they never go back to it
+            protocol::JobStatus::AwaitingApproval => {}
             protocol::JobStatus::Running => {
                 let now = chrono::Utc::now();
                 check
@@ -678,13 +1447,55 @@ impl SourceControlIntegration for JobGitHub {
                     protocol::CompletionStatus::Skipped => {
                         octocrab::params::checks::CheckRunConclusion::Skipped
                     }
+                    // The check run of the retry tells how the job went
+                    protocol::CompletionStatus::InfrastructureFailure if retried_job.is_some() => {
+                        octocrab::params::checks::CheckRunConclusion::Neutral
+                    }
+                    protocol::CompletionStatus::InfrastructureFailure => {
+                        octocrab::params::checks::CheckRunConclusion::Failure
+                    }
                 };
+                // Skipped jobs run again when the job they waited on is retried
+                let retryable = match &completion_status {
+                    protocol::CompletionStatus::Success | protocol::CompletionStatus::Skipped => {
+                        false
+                    }
+                    protocol::CompletionStatus::InfrastructureFailure => retried_job.is_none(),
+                    _ => true,
+                };
+                if let Err(e) = Self::revoke_job_token(&app_state, job_github.job_id).await {
+                    tracing::warn!("{}", e);
+                }
+                let job = Job::get_by_id(conn, job_github.job_id).await?;
+                let output = Self::completed_output(
+                    &app_state,
+                    &job,
+                    &completion_status,
+                    now,
+                    None,
+                    &owner,
+                    &repo,
+                )
+                .await;
                 check
                     .status(octocrab::params::checks::CheckRunStatus::Completed)
                     .completed_at(now)
                     .conclusion(conclusion)
+                    .output(output)
                     .send()
                     .await?;
+                if retryable {
+                    if let Err(e) = Self::offer_retry(
+                        &installation_client,
+                        &owner.login,
+                        &repo.name,
+                        job_github.check_run_id,
+                    )
+                    .await
+                    {
+                        tracing::warn!("Failed to offer retrying job {}: {}", job_github.job_id, e);
+                    }
+                }
                 diesel::update(jobs::table)
                     .filter(jobs::id.eq(job_github.job_id))
                     .set(jobs::finished_at.eq(now))
@@ -695,32 +1506,91 @@ impl SourceControlIntegration for JobGitHub {
         Ok(())
     }
 
+    async fn job_config(app_state: &AppState, id: uuid::Uuid) -> Result<protocol::JobConfig> {
+        let conn = &mut app_state.pool.get().await?;
+
+        let job_github = Self::get_job_by_id(conn, id).await?;
+        let job = Job::get_by_id(conn, id).await?;
+        let commit: GitHubCommit = github_commit::table
+            .filter(github_commit::id.eq(job_github.commit_id))
+            .select(GitHubCommit::as_select())
+            .first(conn)
+            .await?;
+        let (repo, owner) = job_github.get_repo_and_owner(conn).await?;
+        let instance: GithubInstance = github_instance::table
+            .filter(github_instance::id.eq(owner.instance_id))
+            .select(GithubInstance::as_select())
+            .first(conn)
+            .await?;
+
+        // Private repositories are cloned with a token scoped to this repository only
+        let git_token = if repo.is_private {
+            let installation = GithubInstallation::get_for_owner_id(conn, owner.id).await?;
+            let token = Self::create_repo_token(app_state, installation.id, repo.id).await?;
+            app_state.job_tokens.insert(id, token.token.clone()).await;
+            Some(protocol::Secret::new(token.token))
+        } else {
+            None
+        };
+
+        // Commits from forks could exfiltrate the secrets, so they never get them
+        let secrets = if job.secrets.is_empty() {
+            Default::default()
+        } else if !commit.trusted {
+            tracing::info!("Not exposing secrets to job {} of an untrusted commit", id);
+            Default::default()
+        } else if let Some(cipher) = &app_state.secrets_cipher {
+            GitHubSecret::resolve(conn, cipher, owner.id, repo.id, &job.secrets).await?
+        } else {
+            tracing::warn!(
+                "Not exposing secrets to job {}, no encryption key is set",
+                id
+            );
+            Default::default()
+        };
+
+        Ok(protocol::JobConfig {
+            id,
+            project_url: format!(
+                "https://{}/{}/{}.git",
+                instance.host, owner.login, repo.name
+            ),
+            git_ref: Some(commit.r#ref),
+            git_rev: Some(commit.rev),
+            tasks: job
+                .tasks
+                .unwrap_or_else(|| app_state.config.job.tasks.clone()),
+            cachix_push: false,
+            clone_depth: Some(app_state.config.job.clone_depth),
+            git_token,
+            artifacts: job.artifacts,
+            secrets,
+        })
+    }
+
     async fn create_job(
         commit_id: uuid::Uuid,
         rev: &str,
         repo_id: i64,
         app_state: AppState,
-        vm_config: devenv_runner::protocol::VM,
+        spec: &JobSpec,
+        artifacts: &[String],
+        needs: &[uuid::Uuid],
+        priority: i32,
+        trigger: Trigger,
+        awaiting_approval: bool,
     ) -> Result<Self> {
         let conn = &mut app_state.pool.get().await?;
 
-        // Convert VM config platform to job platform
-        // Get platform enum for job creation and formatting
-        let job_platform = match vm_config.platform {
-            devenv_runner::protocol::Platform::X86_64Linux => {
-                crate::job::model::Platform::X86_64Linux
-            }
-            devenv_runner::protocol::Platform::AArch64Darwin => {
-                crate::job::model::Platform::AArch64Darwin
-            }
-        };
-
         // Create job with specified VM configuration
         let job = Job::new(
             conn,
-            job_platform,
-            Some(vm_config.cpu_count as i32),
-            Some(vm_config.memory_size_mb as i64),
+            spec,
+            artifacts,
+            needs,
+            priority,
+            trigger,
+            awaiting_approval,
         )
         .await?;
 
@@ -742,18 +1612,24 @@ impl SourceControlIntegration for JobGitHub {
         let checks = installation_client.checks(&owner.login, &repo.name);
         // Create a details URL with a fragment pointing to the job UI
         // Format: https://cloud.devenv.sh/github/{owner}/{repo}#{job_id}
-        let details_url = format!(
-            "{}/github/{}/{}#{}",
-            app_state.config.base_url, owner.login, repo.name, job.id
-        );
+        let details_url = Self::details_url(&app_state, &owner, &repo, job.id);
 
         let check_run = checks
-            .create_check_run(format!("devenv ({})", job.platform), rev)
+            .create_check_run(format!("{} ({})", job.name, job.platform), rev)
             .details_url(details_url)
-            .external_id(job.id)
-            .status(octocrab::params::checks::CheckRunStatus::Queued)
-            .send()
-            .await?;
+            .external_id(job.id);
+        // Jobs awaiting approval don't block the pull request until they're approved
+        let check_run = if awaiting_approval {
+            check_run
+                .status(octocrab::params::checks::CheckRunStatus::Completed)
+                .conclusion(octocrab::params::checks::CheckRunConclusion::Neutral)
+                .output(check_output::render_awaiting_approval(
+                    &app_state.config.github.approval_label,
+                ))
+        } else {
+            check_run.status(octocrab::params::checks::CheckRunStatus::Queued)
+        };
+        let check_run = check_run.send().await?;
 
         let githubjob = diesel::insert_into(jobs_github::table)
             .values((
@@ -768,8 +1644,10 @@ impl SourceControlIntegration for JobGitHub {
         // Retrieve job from database to get full job::model::Job type
         let job_for_runner = crate::job::model::Job::get_by_id(conn, job.id).await?;
 
-        // Notify all connected runners about the new job
-        crate::runner::serve::notify_runners_about_job(&app_state, &job_for_runner).await;
+        // Notify all connected runners about the new job, unless it has to wait for others
+        if needs.is_empty() && !awaiting_approval {
+            crate::runner::serve::notify_runners_about_job(&app_state, &job_for_runner).await;
+        }
 
         Ok(githubjob)
     }
@@ -800,3 +1678,65 @@ impl WebhookProcessor {
             .map_err(|_| eyre::eyre!("Invalid signature"))
     }
 }
+
+/// The jobs that can be retried, on the given platform or all of them
+fn retryable_on(jobs: Vec<Job>, platform: Option<&protocol::Platform>) -> Vec<Job> {
+    jobs.into_iter()
+        .filter(|job| job.is_retryable())
+        .filter(|job| {
+            platform
+                .is_none_or(|platform| protocol::Platform::from(job.platform.clone()) == *platform)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::job::model::Platform;
+
+    fn job(platform: Platform, status: JobStatus) -> Job {
+        Job {
+            platform,
+            status,
+            trigger: Trigger::PullRequest,
+            ..Job::test_default()
+        }
+    }
+
+    fn ids(jobs: &[Job]) -> Vec<uuid::Uuid> {
+        jobs.iter().map(|job| job.id).collect()
+    }
+
+    #[test]
+    fn test_retryable_on() {
+        let jobs = vec![
+            job(Platform::X86_64Linux, JobStatus::failed()),
+            job(Platform::X86_64Linux, JobStatus::success()),
+            job(Platform::AArch64Darwin, JobStatus::timed_out()),
+            job(Platform::AArch64Darwin, JobStatus::running()),
+            job(Platform::X86_64Linux, JobStatus::cancelled()),
+            job(Platform::AArch64Darwin, JobStatus::queued()),
+        ];
+
+        assert_eq!(
+            ids(&retryable_on(jobs.clone(), None)),
+            vec![jobs[0].id, jobs[2].id, jobs[4].id]
+        );
+        assert_eq!(
+            ids(&retryable_on(
+                jobs.clone(),
+                Some(&protocol::Platform::X86_64Linux)
+            )),
+            vec![jobs[0].id, jobs[4].id]
+        );
+        let timed_out = jobs[2].id;
+        assert_eq!(
+            ids(&retryable_on(
+                jobs,
+                Some(&protocol::Platform::AArch64Darwin)
+            )),
+            vec![timed_out]
+        );
+    }
+}
@@ -0,0 +1,339 @@
+//! Scheduled runs of the jobs declared in `cloud.schedule`
+//!
+//! Pushes to the default branch record its schedules. A background task then
+//! creates the jobs for the head of the branch whenever a schedule is due,
+//! reusing the commit record of the head if it has one.
+
+use super::model::{GitHubCommit, GitHubRepo, GithubInstallation, GithubOwner, JobGitHub};
+use crate::config::AppState;
+use crate::job::cron::Cron;
+use crate::job::model::Trigger;
+use crate::runner::cloudconfig::Schedule;
+use crate::schema::{github_commit, github_owner, github_repo, github_schedules};
+use chrono::{DateTime, TimeDelta, Utc};
+use diesel::prelude::*;
+use diesel::upsert::excluded;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use eyre::{OptionExt, Result};
+use uuid::Uuid;
+
+/// How often due schedules are looked for
+const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Ticks further in the past were missed, e.g. while the backend was down
+const MISSED_AFTER: TimeDelta = TimeDelta::minutes(5);
+
+#[derive(Debug, Queryable, Selectable, Identifiable)]
+#[diesel(table_name = github_schedules)]
+pub struct GitHubSchedule {
+    pub id: Uuid,
+    pub repo_id: i64,
+    pub cron: String,
+    /// The default branch of the repository, whose head is built
+    pub branch: String,
+    pub next_run_at: DateTime<Utc>,
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    /// Whether missed ticks run once, `None` uses the configured default
+    pub catch_up: Option<bool>,
+}
+
+impl GitHubSchedule {
+    /// Replace the schedules of a repository with the ones of its default branch
+    ///
+    /// Schedules that didn't change keep their next run.
+    pub async fn replace(
+        conn: &mut AsyncPgConnection,
+        repo_id: i64,
+        branch: &str,
+        schedule: &[Schedule],
+    ) -> Result<(), diesel::result::Error> {
+        let now = Utc::now();
+        let expressions: Vec<String> = schedule.iter().map(|s| s.cron.to_string()).collect();
+        let branch = branch.to_string();
+        let rows: Vec<_> = schedule
+            .iter()
+            .filter_map(|schedule| {
+                let next_run_at = schedule.cron.next_after(now)?;
+                Some((
+                    github_schedules::id.eq(Uuid::now_v7()),
+                    github_schedules::repo_id.eq(repo_id),
+                    github_schedules::cron.eq(schedule.cron.to_string()),
+                    github_schedules::branch.eq(branch.clone()),
+                    github_schedules::next_run_at.eq(next_run_at),
+                    github_schedules::catch_up.eq(schedule.catch_up),
+                ))
+            })
+            .collect();
+
+        conn.build_transaction()
+            .run::<_, diesel::result::Error, _>(|conn| {
+                Box::pin(async move {
+                    diesel::delete(github_schedules::table)
+                        .filter(github_schedules::repo_id.eq(repo_id))
+                        .filter(github_schedules::cron.ne_all(&expressions))
+                        .execute(conn)
+                        .await?;
+                    // The default branch may have been renamed
+                    diesel::update(github_schedules::table)
+                        .filter(github_schedules::repo_id.eq(repo_id))
+                        .set(github_schedules::branch.eq(&branch))
+                        .execute(conn)
+                        .await?;
+                    if !rows.is_empty() {
+                        diesel::insert_into(github_schedules::table)
+                            .values(rows)
+                            .on_conflict((github_schedules::repo_id, github_schedules::cron))
+                            .do_update()
+                            .set(
+                                github_schedules::catch_up.eq(excluded(github_schedules::catch_up)),
+                            )
+                            .execute(conn)
+                            .await?;
+                    }
+                    Ok(())
+                })
+            })
+            .await
+    }
+
+    /// Get the schedules whose next run is due
+    pub async fn find_due(
+        conn: &mut AsyncPgConnection,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<Self>, diesel::result::Error> {
+        github_schedules::table
+            .filter(github_schedules::next_run_at.le(now))
+            .order_by(github_schedules::next_run_at)
+            .select(Self::as_select())
+            .load(conn)
+            .await
+    }
+
+    /// Move a due schedule to its next run
+    ///
+    /// Returns false if another backend instance already did, so each run
+    /// happens once.
+    pub async fn advance(
+        &self,
+        conn: &mut AsyncPgConnection,
+        next_run_at: DateTime<Utc>,
+        last_run_at: Option<DateTime<Utc>>,
+    ) -> Result<bool, diesel::result::Error> {
+        let updated = diesel::update(github_schedules::table.find(self.id))
+            .filter(github_schedules::next_run_at.eq(self.next_run_at))
+            .set((
+                github_schedules::next_run_at.eq(next_run_at),
+                github_schedules::last_run_at.eq(last_run_at.or(self.last_run_at)),
+            ))
+            .execute(conn)
+            .await?;
+        Ok(updated > 0)
+    }
+
+    /// Remove a schedule that never runs again
+    pub async fn delete(&self, conn: &mut AsyncPgConnection) -> Result<(), diesel::result::Error> {
+        diesel::delete(github_schedules::table.find(self.id))
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Whether a schedule due at `due_at` runs now
+///
+/// Missed ticks only run if catching up is enabled, and then only once.
+fn should_run(due_at: DateTime<Utc>, now: DateTime<Utc>, catch_up: bool) -> bool {
+    catch_up || now - due_at <= MISSED_AFTER
+}
+
+/// Create the jobs for the head of the scheduled branch
+///
+/// Without a devenv.nix on the branch anymore, the schedules of the repository
+/// are removed.
+async fn run(app_state: &AppState, schedule: &GitHubSchedule) -> Result<()> {
+    let conn = &mut app_state.pool.get().await?;
+    let repo: GitHubRepo = github_repo::table
+        .find(schedule.repo_id)
+        .select(GitHubRepo::as_select())
+        .first(conn)
+        .await?;
+    if repo.disabled {
+        return Ok(());
+    }
+    let owner: GithubOwner = github_owner::table
+        .find(repo.owner_id)
+        .select(GithubOwner::as_select())
+        .first(conn)
+        .await?;
+
+    // Get an installation-authenticated client
+    let installation = GithubInstallation::get_for_owner_id(conn, owner.id).await?;
+    let installation_client = JobGitHub::get_installation_client(app_state, installation.id)?;
+
+    let head = installation_client
+        .repos(&owner.login, &repo.name)
+        .list_commits()
+        .sha(&schedule.branch)
+        .per_page(1)
+        .send()
+        .await?
+        .items
+        .into_iter()
+        .next()
+        .ok_or_eyre("scheduled branch has no commits")?;
+
+    let Some(cloud_config) =
+        JobGitHub::fetch_cloud_config(&installation_client, &owner.login, &repo.name, &head.sha)
+            .await?
+    else {
+        tracing::info!(
+            "Removing the schedules of {}/{}, {} has no devenv.nix",
+            owner.login,
+            repo.name,
+            schedule.branch
+        );
+        GitHubSchedule::replace(conn, repo.id, &schedule.branch, &[]).await?;
+        return Ok(());
+    };
+
+    let existing: Option<GitHubCommit> = github_commit::table
+        .filter(github_commit::repo_id.eq(repo.id))
+        .filter(github_commit::rev.eq(&head.sha))
+        .filter(github_commit::git_ref.eq(&schedule.branch))
+        .filter(github_commit::trusted.eq(true))
+        .order_by(github_commit::id.desc()) // UUIDv7 is time ordered
+        .select(GitHubCommit::as_select())
+        .first(conn)
+        .await
+        .optional()?;
+    let github_commit = match existing {
+        Some(commit) => commit,
+        None => {
+            let commit = GitHubCommit {
+                id: Uuid::now_v7(),
+                rev: head.sha,
+                r#ref: schedule.branch.clone(),
+                repo_id: repo.id,
+                author: head
+                    .author
+                    .map(|author| author.login)
+                    .unwrap_or_else(|| String::from("Unknown")),
+                message: head.commit.message,
+                // The branch head was pushed to the repository itself
+                trusted: true,
+            };
+            GitHubCommit::create(conn, commit.clone()).await?;
+            commit
+        }
+    };
+
+    github_commit
+        .create_jobs(
+            app_state.clone(),
+            &cloud_config,
+            crate::job::scheduler::PRIORITY_DEFAULT,
+            Trigger::Schedule,
+        )
+        .await?;
+    tracing::info!(
+        "Scheduled run '{}' of {}/{} on {}",
+        schedule.cron,
+        owner.login,
+        repo.name,
+        schedule.branch
+    );
+    Ok(())
+}
+
+/// Run all due schedules once, moving them to their next tick
+async fn run_due_schedules(app_state: &AppState) -> Result<()> {
+    let now = Utc::now();
+    let due = {
+        let conn = &mut app_state.pool.get().await?;
+        GitHubSchedule::find_due(conn, now).await?
+    };
+
+    for schedule in due {
+        let mut conn = app_state.pool.get().await?;
+        let next_run_at = schedule
+            .cron
+            .parse::<Cron>()
+            .ok()
+            .and_then(|cron| cron.next_after(now));
+        let Some(next_run_at) = next_run_at else {
+            tracing::warn!(
+                "Removing schedule '{}' of repository {} that doesn't run anymore",
+                schedule.cron,
+                schedule.repo_id
+            );
+            schedule.delete(&mut conn).await?;
+            continue;
+        };
+
+        let catch_up = schedule
+            .catch_up
+            .unwrap_or(app_state.config.job.catch_up_missed_schedules);
+        let runs = should_run(schedule.next_run_at, now, catch_up);
+        if !schedule
+            .advance(&mut conn, next_run_at, runs.then_some(now))
+            .await?
+        {
+            continue;
+        }
+        drop(conn);
+        if !runs {
+            tracing::info!(
+                "Skipping missed run '{}' of repository {}",
+                schedule.cron,
+                schedule.repo_id
+            );
+            continue;
+        }
+
+        if let Err(e) = run(app_state, &schedule).await {
+            tracing::error!(
+                "Failed to run schedule '{}' of repository {}: {}",
+                schedule.cron,
+                schedule.repo_id,
+                e
+            );
+        }
+    }
+    Ok(())
+}
+
+// Task that periodically creates the jobs of due schedules
+async fn schedule_checker(app_state: AppState) {
+    let mut interval_timer = tokio::time::interval(CHECK_INTERVAL);
+
+    loop {
+        interval_timer.tick().await;
+
+        if let Err(e) = run_due_schedules(&app_state).await {
+            tracing::error!("Failed to run due schedules: {}", e);
+        }
+    }
+}
+
+// Start the schedule checker task with the AppState
+pub fn start_schedule_checker(app_state: AppState) {
+    tokio::spawn(async move {
+        schedule_checker(app_state).await;
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_should_run() {
+        let now = Utc::now();
+        assert!(should_run(now - TimeDelta::seconds(30), now, false));
+        // A tick missed while the backend was down is skipped
+        assert!(!should_run(now - TimeDelta::hours(2), now, false));
+        // unless catching up
+        assert!(should_run(now - TimeDelta::hours(2), now, true));
+    }
+}
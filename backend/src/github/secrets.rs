@@ -0,0 +1,303 @@
+//! Secrets of owners and repositories, delivered to their jobs
+//!
+//! Values are encrypted at rest with AES-256-GCM. The owner, repository and
+//! name of a secret are authenticated along with its value, so a ciphertext
+//! copied to another row fails to decrypt.
+
+use crate::schema::github_secrets;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use devenv_runner::protocol::Secret;
+use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use eyre::{Result, eyre};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Encrypts and decrypts the values of secrets
+#[derive(Clone)]
+pub struct SecretsCipher(Aes256Gcm);
+
+impl SecretsCipher {
+    /// Create a cipher from a hex-encoded 32 byte key
+    pub fn from_hex(key: &str) -> Result<Self> {
+        let key = hex::decode(key.trim())
+            .map_err(|e| eyre!("Secrets encryption key isn't valid hex: {}", e))?;
+        if key.len() != 32 {
+            return Err(eyre!(
+                "Secrets encryption key must be 32 bytes, got {}",
+                key.len()
+            ));
+        }
+        Ok(Self(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))))
+    }
+
+    /// Encrypt a value, returning the nonce and the ciphertext
+    fn encrypt(&self, value: &str, aad: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .0
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: value.as_bytes(),
+                    aad,
+                },
+            )
+            .map_err(|_| eyre!("Failed to encrypt secret"))?;
+        Ok((nonce.to_vec(), ciphertext))
+    }
+
+    fn decrypt(&self, nonce: &[u8], ciphertext: &[u8], aad: &[u8]) -> Result<String> {
+        if nonce.len() != 12 {
+            return Err(eyre!("Secret has a nonce of {} bytes", nonce.len()));
+        }
+        let value = self
+            .0
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad,
+                },
+            )
+            .map_err(|_| eyre!("Failed to decrypt secret"))?;
+        String::from_utf8(value).map_err(|_| eyre!("Secret isn't valid UTF-8"))
+    }
+}
+
+/// Data authenticated along with the value of a secret
+fn associated_data(owner_id: i64, repo_id: Option<i64>, name: &str) -> Vec<u8> {
+    match repo_id {
+        Some(repo_id) => format!("{owner_id}/{repo_id}/{name}"),
+        None => format!("{owner_id}//{name}"),
+    }
+    .into_bytes()
+}
+
+/// Whether a name can be used for a secret, which ends up as an environment variable
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Variables the driver sets up for the job, which secrets must not replace
+const RESERVED_NAMES: &[&str] = &["PATH", "HOME", "SHELL", "SSL_CERT_FILE"];
+
+/// Prefixes of variables read by the dynamic linker, Nix and devenv
+const RESERVED_PREFIXES: &[&str] = &["LD_", "NIX_", "DEVENV_"];
+
+/// Whether a name is kept for the environment of the driver, and can't be used for a secret
+pub fn is_reserved_name(name: &str) -> bool {
+    RESERVED_NAMES.contains(&name)
+        || RESERVED_PREFIXES
+            .iter()
+            .any(|prefix| name.starts_with(prefix))
+}
+
+#[derive(Queryable, Selectable, Identifiable)]
+#[diesel(table_name = github_secrets)]
+pub struct GitHubSecret {
+    pub id: Uuid,
+    pub owner_id: i64,
+    /// Repository the secret is scoped to, `None` for secrets of the owner
+    pub repo_id: Option<i64>,
+    pub name: String,
+    pub nonce: Vec<u8>,
+    pub ciphertext: Vec<u8>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// A secret without its value, which is never returned
+#[derive(Queryable, Selectable, Serialize, ToSchema)]
+#[diesel(table_name = github_secrets)]
+pub struct SecretInfo {
+    pub name: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl GitHubSecret {
+    /// Set the value of a secret of an owner, or of one of its repositories
+    pub async fn set(
+        conn: &mut AsyncPgConnection,
+        cipher: &SecretsCipher,
+        owner_id: i64,
+        repo_id: Option<i64>,
+        name: &str,
+        value: &str,
+    ) -> Result<()> {
+        let (nonce, ciphertext) =
+            cipher.encrypt(value, &associated_data(owner_id, repo_id, name))?;
+
+        let updated = diesel::update(github_secrets::table)
+            .filter(github_secrets::owner_id.eq(owner_id))
+            .filter(github_secrets::repo_id.is_not_distinct_from(repo_id))
+            .filter(github_secrets::name.eq(name))
+            .set((
+                github_secrets::nonce.eq(&nonce),
+                github_secrets::ciphertext.eq(&ciphertext),
+                github_secrets::updated_at.eq(diesel::dsl::now),
+            ))
+            .execute(conn)
+            .await?;
+
+        if updated == 0 {
+            diesel::insert_into(github_secrets::table)
+                .values((
+                    github_secrets::id.eq(Uuid::now_v7()),
+                    github_secrets::owner_id.eq(owner_id),
+                    github_secrets::repo_id.eq(repo_id),
+                    github_secrets::name.eq(name),
+                    github_secrets::nonce.eq(&nonce),
+                    github_secrets::ciphertext.eq(&ciphertext),
+                ))
+                .execute(conn)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Delete a secret, returning whether it existed
+    pub async fn delete(
+        conn: &mut AsyncPgConnection,
+        owner_id: i64,
+        repo_id: Option<i64>,
+        name: &str,
+    ) -> Result<bool, diesel::result::Error> {
+        let deleted = diesel::delete(github_secrets::table)
+            .filter(github_secrets::owner_id.eq(owner_id))
+            .filter(github_secrets::repo_id.is_not_distinct_from(repo_id))
+            .filter(github_secrets::name.eq(name))
+            .execute(conn)
+            .await?;
+        Ok(deleted > 0)
+    }
+
+    /// List the secrets of an owner, or of one of its repositories
+    pub async fn list(
+        conn: &mut AsyncPgConnection,
+        owner_id: i64,
+        repo_id: Option<i64>,
+    ) -> Result<Vec<SecretInfo>, diesel::result::Error> {
+        github_secrets::table
+            .filter(github_secrets::owner_id.eq(owner_id))
+            .filter(github_secrets::repo_id.is_not_distinct_from(repo_id))
+            .order_by(github_secrets::name)
+            .select(SecretInfo::as_select())
+            .load(conn)
+            .await
+    }
+
+    /// Decrypt the named secrets available to a repository
+    ///
+    /// Secrets of the repository take precedence over the ones of its owner,
+    /// names without a secret are left out.
+    pub async fn resolve(
+        conn: &mut AsyncPgConnection,
+        cipher: &SecretsCipher,
+        owner_id: i64,
+        repo_id: i64,
+        names: &[String],
+    ) -> Result<BTreeMap<String, Secret>> {
+        // Secrets stored before their name was reserved stay out of the environment
+        let names: Vec<&String> = names
+            .iter()
+            .filter(|name| !is_reserved_name(name))
+            .collect();
+        if names.is_empty() {
+            return Ok(BTreeMap::new());
+        }
+
+        // Owner secrets sort first, so the repository ones overwrite them
+        let secrets: Vec<GitHubSecret> = github_secrets::table
+            .filter(github_secrets::owner_id.eq(owner_id))
+            .filter(
+                github_secrets::repo_id
+                    .is_null()
+                    .or(github_secrets::repo_id.eq(repo_id)),
+            )
+            .filter(github_secrets::name.eq_any(names))
+            .order_by(github_secrets::repo_id.asc().nulls_first())
+            .select(GitHubSecret::as_select())
+            .load(conn)
+            .await?;
+
+        let mut resolved = BTreeMap::new();
+        for secret in secrets {
+            let value = cipher.decrypt(
+                &secret.nonce,
+                &secret.ciphertext,
+                &associated_data(secret.owner_id, secret.repo_id, &secret.name),
+            )?;
+            resolved.insert(secret.name, Secret::new(value));
+        }
+        Ok(resolved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+    #[test]
+    fn test_cipher_roundtrip() {
+        let cipher = SecretsCipher::from_hex(KEY).unwrap();
+        let aad = associated_data(1, Some(2), "API_KEY");
+        let (nonce, ciphertext) = cipher.encrypt("hunter2", &aad).unwrap();
+        assert_ne!(ciphertext, b"hunter2");
+        assert_eq!(
+            cipher.decrypt(&nonce, &ciphertext, &aad).unwrap(),
+            "hunter2"
+        );
+    }
+
+    #[test]
+    fn test_cipher_rejects_other_secret() {
+        let cipher = SecretsCipher::from_hex(KEY).unwrap();
+        let (nonce, ciphertext) = cipher
+            .encrypt("hunter2", &associated_data(1, Some(2), "API_KEY"))
+            .unwrap();
+        // The same ciphertext stored for another repository doesn't decrypt
+        let other = associated_data(1, Some(3), "API_KEY");
+        assert!(cipher.decrypt(&nonce, &ciphertext, &other).is_err());
+        let owner = associated_data(1, None, "API_KEY");
+        assert!(cipher.decrypt(&nonce, &ciphertext, &owner).is_err());
+    }
+
+    #[test]
+    fn test_cipher_rejects_invalid_key() {
+        assert!(SecretsCipher::from_hex("not hex").is_err());
+        assert!(SecretsCipher::from_hex("0011").is_err());
+    }
+
+    #[test]
+    fn test_is_valid_name() {
+        assert!(is_valid_name("API_KEY"));
+        assert!(is_valid_name("_token2"));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name("2FA"));
+        assert!(!is_valid_name("DEPLOY-KEY"));
+        assert!(!is_valid_name("A=B"));
+    }
+
+    #[test]
+    fn test_is_reserved_name() {
+        assert!(is_reserved_name("PATH"));
+        assert!(is_reserved_name("SSL_CERT_FILE"));
+        assert!(is_reserved_name("LD_PRELOAD"));
+        assert!(is_reserved_name("NIX_SSL_CERT_FILE"));
+        assert!(is_reserved_name("DEVENV_NIX"));
+        assert!(!is_reserved_name("API_KEY"));
+        assert!(!is_reserved_name("MY_PATH"));
+        assert!(!is_reserved_name("NIXOS_TOKEN"));
+    }
+}
@@ -1,18 +1,30 @@
-use crate::auth::BetaUser;
+use crate::auth::{AdminUser, BetaUser};
 use crate::config::AppState;
 use crate::error::Result;
+use crate::github::commands::Command;
 use crate::github::model::{
-    Commit, GitHubCommit, GitHubRepo, GithubInstallation, GithubOwner, OwnerWithRepos, RepoInfo,
-    WebhookProcessor,
+    Commit, GitHubCommit, GitHubRepo, GithubInstallation, GithubOwner, OwnerWithRepos,
+    RETRY_ACTION, RepoInfo, WebhookProcessor,
 };
+use crate::github::schedule::GitHubSchedule;
+use crate::github::secrets::{
+    GitHubSecret, SecretInfo, is_reserved_name as is_reserved_secret_name,
+    is_valid_name as is_valid_secret_name,
+};
+use crate::job::model::Trigger;
 use crate::schema::github_owner;
 use axum::body::Bytes;
+use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
 use axum::{Json, extract::State};
 use diesel::prelude::*;
 use diesel_async::RunQueryDsl;
 use eyre::{OptionExt, eyre};
+use octocrab::models::reactions::ReactionContent;
 use octocrab::models::webhook_events::payload::{
-    InstallationWebhookEventAction, PullRequestWebhookEventAction,
+    CheckRunWebhookEventAction, CheckSuiteWebhookEventAction, InstallationWebhookEventAction,
+    IssueCommentWebhookEventAction, IssueCommentWebhookEventPayload, PullRequestWebhookEventAction,
 };
 use octocrab::models::webhook_events::{EventInstallation, WebhookEvent, WebhookEventPayload};
 use utoipa_axum::{router::OpenApiRouter, routes};
@@ -65,6 +77,13 @@ async fn get_repos(
         }
     }
 
+    // Load the steps of all these jobs at once
+    let job_ids: Vec<uuid::Uuid> = repo_latest_commits
+        .values()
+        .flat_map(|(_, jobs)| jobs.iter().map(|(job, _)| job.id))
+        .collect();
+    let mut steps = crate::job::model::JobStep::get_for_jobs(conn, &job_ids).await?;
+
     // Convert to OwnerWithRepos structs
     let result = owner_map
         .into_values()
@@ -78,14 +97,13 @@ async fn get_repos(
                             let jobs = all_jobs
                                 .iter()
                                 .map(|(job, job_github)| {
-                                    let log_url =
-                                        format!("{}/{}", app_state.config.logger_url, job.id);
-                                    JobResponse {
-                                        github: job_github.clone(),
-                                        job: job.clone(),
-                                        commit: commit.clone(),
-                                        log_url,
-                                    }
+                                    JobResponse::new(
+                                        job.clone(),
+                                        job_github.clone(),
+                                        commit.clone(),
+                                        steps.remove(&job.id).unwrap_or_default(),
+                                        &app_state.config.logger_url,
+                                    )
                                 })
                                 .collect();
 
@@ -124,7 +142,8 @@ async fn get_repos(
 }
 
 // Using the JobResponse from job/serve.rs
-use crate::job::serve::JobResponse;
+use crate::job::search::{JobFilter, Page};
+use crate::job::serve::{JobPage, JobResponse};
 
 #[utoipa::path(
     get,
@@ -149,24 +168,24 @@ async fn get_rev(
     let owner = GithubOwner::get_by_login(conn, &owner_login).await?;
     let repo = GitHubRepo::get_by_owner_and_name(conn, owner.id, &repo_name).await?;
     let commit = GitHubCommit::get_by_repo_and_rev(conn, repo.id, &rev).await?;
-    // Fetch JobGitHub entries and Jobs in a single query using a join
-    // Get all the job data
-    let job_pairs = GitHubCommit::get_jobs_by_commit_id(conn, commit.id).await?;
-
-    // Create the JobResponse objects with commit info
-    let job_responses: Vec<JobResponse> = job_pairs
-        .into_iter()
-        .map(|(github, job)| {
-            // Generate a log URL for this job
-            let log_url = format!("{}/{}", app_state.config.logger_url, job.id);
-            JobResponse {
-                github,
-                job,
-                commit: commit.clone(),
-                log_url,
-            }
-        })
-        .collect();
+    // A commit has all of its jobs, through every page of the search
+    let mut filter = JobFilter {
+        owner: Some(owner_login.clone()),
+        repo: Some(repo_name.clone()),
+        rev: Some(rev),
+        limit: Some(crate::job::search::MAX_LIMIT),
+        ..Default::default()
+    };
+    let mut job_responses = Vec::new();
+    loop {
+        let result = crate::job::search::search(conn, &filter).await?;
+        let page = JobPage::load(conn, result, &app_state.config.logger_url).await?;
+        job_responses.extend(page.jobs);
+        match page.next_cursor {
+            Some(cursor) => filter.cursor = Some(cursor),
+            None => break,
+        }
+    }
 
     Ok(Json(Commit {
         owner: owner_login,
@@ -310,6 +329,7 @@ async fn webhook(
             let conn = &mut app_state.pool.get().await?;
             let db_owner: GithubOwner = github_owner::table
                 .filter(github_owner::id.eq(owner_id))
+                .select(GithubOwner::as_select())
                 .first(conn)
                 .await?;
 
@@ -319,87 +339,107 @@ async fn webhook(
             let installation_client =
                 JobGitHub::get_installation_client(&app_state, installation.id)?;
 
-            // Check if devenv.nix exists
-            let content_items = installation_client
-                .repos(owner_login.clone(), repo_name.clone())
-                .get_content()
-                .path("devenv.nix")
-                .r#ref(&push.r#ref)
-                .send()
-                .await?;
+            // Extract reference name without refs/heads/ prefix
+            let ref_name = push.r#ref.trim_start_matches("refs/heads/").to_string();
+            let repo_id = repository.id.into_inner() as i64;
+            let is_default_branch = repository.default_branch.as_deref() == Some(ref_name.as_str());
 
-            if !content_items.items.is_empty() {
-                // Extract reference name without refs/heads/ prefix
-                let ref_name = push.r#ref.trim_start_matches("refs/heads/").to_string();
-
-                // Get the author handle and message from the latest commit
-                let (author, message) = if let Some(commit) = push.commits.get(0) {
-                    (
-                        commit
-                            .author
-                            .username
-                            .clone()
-                            .unwrap_or_else(|| String::from("Unknown")),
-                        commit.message.clone(),
-                    )
-                } else {
-                    (String::from("Unknown"), String::from("No message provided"))
-                };
+            let Some(cloud_config) = JobGitHub::fetch_cloud_config(
+                &installation_client,
+                &owner_login,
+                &repo_name,
+                &push.r#ref,
+            )
+            .await?
+            else {
+                // Nothing runs on a schedule anymore either
+                if is_default_branch {
+                    GitHubSchedule::replace(conn, repo_id, &ref_name, &[]).await?;
+                }
+                return Ok(());
+            };
 
-                // Create the commit record
-                let github_commit = GitHubCommit {
-                    id: uuid::Uuid::now_v7(),
-                    rev: push.after,
-                    r#ref: ref_name,
-                    repo_id: repository.id.into_inner() as i64,
-                    author,
-                    message,
-                };
+            // Schedules only follow the default branch
+            if is_default_branch {
+                GitHubSchedule::replace(conn, repo_id, &ref_name, cloud_config.schedule()).await?;
+            }
 
-                // Insert the commit into the database
-                GitHubCommit::create(conn, github_commit.clone()).await?;
-
-                // Try to fetch devenv.yaml to determine VM configurations
-                let devenv_yaml_content = match installation_client
-                    .repos(owner_login.clone(), repo_name.clone())
-                    .get_content()
-                    .path("devenv.yaml")
-                    .r#ref(&push.r#ref)
-                    .send()
-                    .await
-                {
-                    Ok(content) if !content.items.is_empty() => {
-                        if let Some(content) = content.items[0].decoded_content() {
-                            Some(content)
-                        } else {
-                            tracing::warn!("Failed to decode devenv.yaml content");
-                            None
-                        }
-                    }
-                    _ => None,
-                };
+            // Branches of draft pull requests run once they're ready for review
+            if cloud_config.skip_drafts()
+                && !is_default_branch
+                && JobGitHub::has_draft_pull_request(
+                    &installation_client,
+                    &owner_login,
+                    &repo_name,
+                    &ref_name,
+                )
+                .await?
+            {
+                tracing::info!("Skipping {} of a draft pull request", ref_name);
+                return Ok(());
+            }
+
+            // Get the author handle and message from the latest commit
+            let (author, message) = if let Some(commit) = push.commits.get(0) {
+                (
+                    commit
+                        .author
+                        .username
+                        .clone()
+                        .unwrap_or_else(|| String::from("Unknown")),
+                    commit.message.clone(),
+                )
+            } else {
+                (String::from("Unknown"), String::from("No message provided"))
+            };
 
-                // Parse devenv.yaml and get VM configurations
-                let yaml_str = devenv_yaml_content.as_deref().unwrap_or("");
-                let cloud_config = crate::runner::cloudconfig::FinalCloud::new(yaml_str)
-                    .map_err(|e| eyre!("Failed to parse devenv.yaml: {}", e))?;
-                let vms = cloud_config.into_vms();
+            // Create the commit record
+            let github_commit = GitHubCommit {
+                id: uuid::Uuid::now_v7(),
+                rev: push.after,
+                r#ref: ref_name,
+                repo_id,
+                author,
+                message,
+                // Pushing to the repository takes write access to it
+                trusted: true,
+            };
 
-                // Create a job for each VM configuration
-                for vm in vms {
-                    github_commit.create_job(app_state.clone(), vm).await?;
-                }
+            // Insert the commit into the database, unless a pull request event already did
+            if !GitHubCommit::create_unless_seen(conn, github_commit.clone()).await? {
+                tracing::info!("Commit {} already has jobs", github_commit.rev);
+                return Ok(());
+            }
+
+            // Pushes to the default branch go ahead of other work
+            let priority = if is_default_branch {
+                crate::job::scheduler::PRIORITY_DEFAULT_BRANCH
+            } else {
+                crate::job::scheduler::PRIORITY_DEFAULT
+            };
+
+            // Create the jobs of the pipeline
+            github_commit
+                .create_jobs(app_state.clone(), &cloud_config, priority, Trigger::Push)
+                .await?;
+
+            // Newer commits make the jobs of older ones on the branch moot
+            if cloud_config.cancel_superseded(is_default_branch) {
+                JobGitHub::cancel_superseded(&app_state, &github_commit).await?;
             }
         }
         WebhookEventPayload::PullRequest(pr) => match pr.action {
-            PullRequestWebhookEventAction::Synchronize => {
-                let ref_field = pr.pull_request.head.ref_field;
-                // Get the head repository where the branch exists
+            PullRequestWebhookEventAction::Opened
+            | PullRequestWebhookEventAction::Reopened
+            | PullRequestWebhookEventAction::ReadyForReview
+            | PullRequestWebhookEventAction::Synchronize => {
+                let github_commit = pull_request_commit(&pr.pull_request)?;
+                // Jobs run in the base repository, where the app is installed
                 let repo = pr
                     .pull_request
-                    .head
+                    .base
                     .repo
-                    .ok_or_eyre("could not get head repository from pull request")?;
+                    .ok_or_eyre("could not get base repository from pull request")?;
                 let owner_name = repo
                     .owner
                     .ok_or_eyre("could not get repository owner")?
@@ -413,85 +453,434 @@ async fn webhook(
                 let installation_client =
                     JobGitHub::get_installation_client(&app_state, installation.id)?;
 
-                // Check if devenv.nix exists
-                let content_items = installation_client
-                    .repos(owner_name.clone(), repo.name.clone())
-                    .get_content()
-                    .path("devenv.nix")
-                    .r#ref(&ref_field)
-                    .send()
-                    .await?;
+                let Some(cloud_config) = JobGitHub::fetch_cloud_config(
+                    &installation_client,
+                    &owner_name,
+                    &repo.name,
+                    &github_commit.rev,
+                )
+                .await?
+                else {
+                    return Ok(());
+                };
 
-                if !content_items.items.is_empty() {
-                    // Get author and commit message from PR
-                    let (author, message) = (
-                        pr.pull_request
-                            .user
-                            .as_ref()
-                            .map(|user| user.login.clone())
-                            .unwrap_or_else(|| String::from("Unknown")),
-                        pr.pull_request
-                            .title
-                            .clone()
-                            .unwrap_or_else(|| String::from("No message provided")),
-                    );
-
-                    let github_commit = GitHubCommit {
-                        id: uuid::Uuid::now_v7(),
-                        rev: pr.pull_request.head.sha,
-                        r#ref: ref_field.clone(),
-                        repo_id: repo.id.into_inner() as i64,
-                        author,
-                        message,
-                    };
+                // Drafts run once they're ready for review
+                if cloud_config.skip_drafts() && pr.pull_request.draft == Some(true) {
+                    tracing::info!("Skipping draft pull request #{}", pr.number);
+                    return Ok(());
+                }
 
-                    // Insert the commit into the database
+                // Insert the commit into the database, unless a push event already did.
+                // Reopening a pull request runs its jobs again.
+                if matches!(pr.action, PullRequestWebhookEventAction::Reopened) {
                     GitHubCommit::create(conn, github_commit.clone()).await?;
+                } else if !GitHubCommit::create_unless_seen(conn, github_commit.clone()).await? {
+                    tracing::info!("Commit {} already has jobs", github_commit.rev);
+                    return Ok(());
+                }
 
-                    // Try to fetch devenv.yaml to determine VM configurations
-                    let devenv_yaml_content = match installation_client
-                        .repos(owner_name.clone(), repo.name.clone())
-                        .get_content()
-                        .path("devenv.yaml")
-                        .r#ref(&ref_field)
-                        .send()
-                        .await
-                    {
-                        Ok(content) if !content.items.is_empty() => {
-                            if let Some(content) = content.items[0].decoded_content() {
-                                Some(content)
-                            } else {
-                                tracing::warn!("Failed to decode devenv.yaml content");
-                                None
-                            }
-                        }
-                        _ => None,
-                    };
-
-                    // Parse devenv.yaml and get VM configurations
-                    let yaml_str = devenv_yaml_content.as_deref().unwrap_or("");
-                    let cloud_config = crate::runner::cloudconfig::FinalCloud::new(yaml_str)
-                        .map_err(|e| eyre!("Failed to parse devenv.yaml: {}", e))?;
-                    let vms = cloud_config.into_vms();
+                // Create the jobs of the pipeline, forks' ones await approval
+                github_commit
+                    .create_jobs(
+                        app_state.clone(),
+                        &cloud_config,
+                        crate::job::scheduler::PRIORITY_DEFAULT,
+                        Trigger::PullRequest,
+                    )
+                    .await?;
 
-                    // Create a job for each VM configuration
-                    for vm in vms {
-                        github_commit.create_job(app_state.clone(), vm).await?;
-                    }
+                // Newer commits make the jobs of older ones on the pull request moot
+                if cloud_config.cancel_superseded(false) {
+                    JobGitHub::cancel_superseded(&app_state, &github_commit).await?;
                 }
             }
+            // Maintainers approve the jobs of forks by labeling the pull request
+            PullRequestWebhookEventAction::Labeled
+                if pr
+                    .label
+                    .as_ref()
+                    .is_some_and(|label| label.name == app_state.config.github.approval_label) =>
+            {
+                let repo = pr
+                    .pull_request
+                    .base
+                    .repo
+                    .ok_or_eyre("could not get base repository from pull request")?;
+                let sender = event.sender.ok_or_eyre("could not get label sender")?;
+                let installation_client =
+                    JobGitHub::get_installation_client(&app_state, installation_id.0 as i64)?;
+                approve_as(
+                    &app_state,
+                    conn,
+                    &installation_client,
+                    &repo,
+                    &pr.pull_request.head.sha,
+                    &sender.login,
+                )
+                .await?;
+            }
             _ => {}
         },
+        WebhookEventPayload::IssueComment(comment)
+            if matches!(comment.action, IssueCommentWebhookEventAction::Created)
+                && comment.issue.pull_request.is_some() =>
+        {
+            let Some(command) = comment.comment.body.as_deref().and_then(Command::parse) else {
+                return Ok(());
+            };
+            let repo = event
+                .repository
+                .ok_or_eyre("could not get repository of comment")?;
+            let installation_client =
+                JobGitHub::get_installation_client(&app_state, installation_id.0 as i64)?;
+            run_command(
+                &app_state,
+                conn,
+                &installation_client,
+                &repo,
+                &comment,
+                command,
+            )
+            .await?;
+        }
+        // "Re-run" on a check run retries its job
+        WebhookEventPayload::CheckRun(payload)
+            if matches!(payload.action, CheckRunWebhookEventAction::Rerequested)
+                || (matches!(payload.action, CheckRunWebhookEventAction::RequestedAction)
+                    && serde_json::from_slice::<RequestedActionEvent>(&body)
+                        .is_ok_and(|event| event.requested_action.identifier == RETRY_ACTION)) =>
+        {
+            let check_run: CheckRunEvent = serde_json::from_value(payload.check_run)?;
+            let repo = event
+                .repository
+                .ok_or_eyre("could not get repository of check run")?;
+            let sender = event.sender.ok_or_eyre("could not get check run sender")?;
+            let installation_client =
+                JobGitHub::get_installation_client(&app_state, installation_id.0 as i64)?;
+            if !can_push(&installation_client, &repo, &sender.login).await? {
+                return Ok(());
+            }
+            if JobGitHub::retry_check_run(
+                &app_state,
+                conn,
+                check_run.id,
+                check_run.external_id.as_deref(),
+            )
+            .await?
+            .is_none()
+            {
+                tracing::info!("Nothing to retry for check run {}", check_run.id);
+            }
+        }
+        // "Re-run all jobs" on a check suite creates the jobs of its commit again
+        WebhookEventPayload::CheckSuite(payload)
+            if matches!(payload.action, CheckSuiteWebhookEventAction::Rerequested) =>
+        {
+            let check_suite: CheckSuiteEvent = serde_json::from_value(payload.check_suite)?;
+            let repo = event
+                .repository
+                .ok_or_eyre("could not get repository of check suite")?;
+            let sender = event
+                .sender
+                .ok_or_eyre("could not get check suite sender")?;
+            let installation_client =
+                JobGitHub::get_installation_client(&app_state, installation_id.0 as i64)?;
+            if !can_push(&installation_client, &repo, &sender.login).await? {
+                return Ok(());
+            }
+
+            let repo_id = repo.id.into_inner() as i64;
+            let Some(commit) =
+                GitHubCommit::find_by_repo_and_rev(conn, repo_id, &check_suite.head_sha).await?
+            else {
+                tracing::info!("Check suite of unknown commit {}", check_suite.head_sha);
+                return Ok(());
+            };
+            let Some(trigger) = commit.trigger(conn).await? else {
+                tracing::info!("Commit {} has no jobs to run again", commit.rev);
+                return Ok(());
+            };
+            let github_commit = GitHubCommit {
+                id: uuid::Uuid::now_v7(),
+                ..commit
+            };
+            rerun_commit(
+                &app_state,
+                conn,
+                &installation_client,
+                &repo,
+                github_commit,
+                trigger,
+                &sender.login,
+            )
+            .await?;
+        }
         _ => {}
     }
     Ok(())
 }
 
+/// The fields of a check run event that aren't part of its octocrab payload
+#[derive(serde::Deserialize)]
+struct RequestedActionEvent {
+    requested_action: RequestedAction,
+}
+
+#[derive(serde::Deserialize)]
+struct RequestedAction {
+    identifier: String,
+}
+
+/// The fields of the check run of a check run event that jobs are found by
+#[derive(serde::Deserialize)]
+struct CheckRunEvent {
+    id: i64,
+    /// The id of the job, for check runs created by us
+    external_id: Option<String>,
+}
+
+/// The fields of the check suite of a check suite event
+#[derive(serde::Deserialize)]
+struct CheckSuiteEvent {
+    head_sha: String,
+}
+
+/// Whether a GitHub user may push to a repository, and so act on its jobs
+async fn can_push(
+    installation_client: &octocrab::Octocrab,
+    repo: &octocrab::models::Repository,
+    login: &str,
+) -> Result<bool> {
+    let owner_name = &repo
+        .owner
+        .as_ref()
+        .ok_or_eyre("could not get repository owner")?
+        .login;
+    let can_push =
+        JobGitHub::has_write_access(installation_client, owner_name, &repo.name, login).await?;
+    if !can_push {
+        tracing::info!(
+            "Ignoring {} who can't push to {}/{}",
+            login,
+            owner_name,
+            repo.name
+        );
+    }
+    Ok(can_push)
+}
+
+/// Create the jobs of a commit again under a new commit record
+///
+/// Asking for the run takes write access, so jobs of forks are approved too.
+/// Returns false if the commit has no devenv.nix.
+async fn rerun_commit(
+    app_state: &AppState,
+    conn: &mut diesel_async::AsyncPgConnection,
+    installation_client: &octocrab::Octocrab,
+    repo: &octocrab::models::Repository,
+    github_commit: GitHubCommit,
+    trigger: Trigger,
+    login: &str,
+) -> Result<bool> {
+    let owner_name = &repo
+        .owner
+        .as_ref()
+        .ok_or_eyre("could not get repository owner")?
+        .login;
+    let Some(cloud_config) = JobGitHub::fetch_cloud_config(
+        installation_client,
+        owner_name,
+        &repo.name,
+        &github_commit.rev,
+    )
+    .await?
+    else {
+        return Ok(false);
+    };
+
+    GitHubCommit::create(conn, github_commit.clone()).await?;
+    github_commit
+        .create_jobs(
+            app_state.clone(),
+            &cloud_config,
+            crate::job::scheduler::PRIORITY_DEFAULT,
+            trigger,
+        )
+        .await?;
+    if !github_commit.trusted {
+        JobGitHub::approve(
+            app_state,
+            conn,
+            github_commit.repo_id,
+            &github_commit.rev,
+            login,
+        )
+        .await?;
+    }
+    tracing::info!(
+        "Running the jobs of {} again for {}",
+        github_commit.rev,
+        login
+    );
+    Ok(true)
+}
+
+/// The commit record of the head of a pull request, whose jobs run in the base repository
+fn pull_request_commit(
+    pull_request: &octocrab::models::pulls::PullRequest,
+) -> eyre::Result<GitHubCommit> {
+    let base = pull_request
+        .base
+        .repo
+        .as_ref()
+        .ok_or_eyre("could not get base repository from pull request")?;
+    // Only branches of the repository itself are trusted, not forks
+    let trusted = pull_request
+        .head
+        .repo
+        .as_ref()
+        .is_some_and(|head| head.id == base.id);
+    // Branches of forks are fetched through the pull request
+    let git_ref = if trusted {
+        pull_request.head.ref_field.clone()
+    } else {
+        format!("refs/pull/{}/head", pull_request.number)
+    };
+
+    Ok(GitHubCommit {
+        id: uuid::Uuid::now_v7(),
+        rev: pull_request.head.sha.clone(),
+        r#ref: git_ref,
+        repo_id: base.id.into_inner() as i64,
+        author: pull_request
+            .user
+            .as_ref()
+            .map(|user| user.login.clone())
+            .unwrap_or_else(|| String::from("Unknown")),
+        message: pull_request
+            .title
+            .clone()
+            .unwrap_or_else(|| String::from("No message provided")),
+        trusted,
+    })
+}
+
+/// Act on a command given in a comment on a pull request
+///
+/// The comment gets a thumbs up once the command is done, or an answer if
+/// there was nothing to do. Commands of users who can't push are turned down.
+async fn run_command(
+    app_state: &AppState,
+    conn: &mut diesel_async::AsyncPgConnection,
+    installation_client: &octocrab::Octocrab,
+    repo: &octocrab::models::Repository,
+    payload: &IssueCommentWebhookEventPayload,
+    command: Command,
+) -> Result<()> {
+    let owner_name = &repo
+        .owner
+        .as_ref()
+        .ok_or_eyre("could not get repository owner")?
+        .login;
+    let login = &payload.comment.user.login;
+    let issues = installation_client.issues(owner_name, &repo.name);
+
+    if !JobGitHub::has_write_access(installation_client, owner_name, &repo.name, login).await? {
+        tracing::info!(
+            "Ignoring {:?} by {} who can't push to {}/{}",
+            command,
+            login,
+            owner_name,
+            repo.name
+        );
+        issues
+            .create_comment_reaction(payload.comment.id, ReactionContent::MinusOne)
+            .await?;
+        return Ok(());
+    }
+
+    let pull_request = installation_client
+        .pulls(owner_name, &repo.name)
+        .get(payload.issue.number)
+        .await?;
+    let repo_id = repo.id.into_inner() as i64;
+    let rev = &pull_request.head.sha;
+    let short_rev = &rev[..rev.len().min(7)];
+
+    let acted = match &command {
+        Command::Approve => JobGitHub::approve(app_state, conn, repo_id, rev, login).await? > 0,
+        Command::Retry { platform } => {
+            !JobGitHub::retry_commit(app_state, conn, repo_id, rev, platform.as_ref())
+                .await?
+                .is_empty()
+        }
+        Command::Cancel => JobGitHub::cancel_commit(app_state, conn, repo_id, rev).await? > 0,
+        Command::Run => {
+            let github_commit = pull_request_commit(&pull_request)?;
+            rerun_commit(
+                app_state,
+                conn,
+                installation_client,
+                repo,
+                github_commit,
+                Trigger::PullRequest,
+                login,
+            )
+            .await?
+        }
+    };
+
+    match command.reply(short_rev, acted) {
+        Some(answer) => {
+            issues.create_comment(payload.issue.number, answer).await?;
+        }
+        None => {
+            issues
+                .create_comment_reaction(payload.comment.id, ReactionContent::PlusOne)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Approve the jobs of a commit from a fork on behalf of a GitHub user, if they have write access
+async fn approve_as(
+    app_state: &AppState,
+    conn: &mut diesel_async::AsyncPgConnection,
+    installation_client: &octocrab::Octocrab,
+    repo: &octocrab::models::Repository,
+    rev: &str,
+    login: &str,
+) -> Result<()> {
+    let owner_name = &repo
+        .owner
+        .as_ref()
+        .ok_or_eyre("could not get repository owner")?
+        .login;
+    if !JobGitHub::has_write_access(installation_client, owner_name, &repo.name, login).await? {
+        tracing::info!(
+            "Ignoring approval of {} by {} who can't push to {}/{}",
+            rev,
+            login,
+            owner_name,
+            repo.name
+        );
+        return Ok(());
+    }
+    JobGitHub::approve(app_state, conn, repo.id.into_inner() as i64, rev, login).await?;
+    Ok(())
+}
+
 #[derive(serde::Serialize, utoipa::ToSchema)]
 struct RepoJobs {
     owner: String,
     repo: String,
+    /// Commits of the jobs on this page, newest first
     commits: Vec<Commit>,
+    /// Number of jobs of the repository
+    total: i64,
+    /// Pass as `cursor` to get the next page, missing on the last page
+    next_cursor: Option<uuid::Uuid>,
 }
 
 #[utoipa::path(
@@ -499,7 +888,8 @@ struct RepoJobs {
     path = "/{owner}/{repo}/jobs",
     params(
         ("owner" = String, Path, description = "The repository owner"),
-        ("repo" = String, Path, description = "The repository name")
+        ("repo" = String, Path, description = "The repository name"),
+        Page
     ),
     responses((status = OK, body = RepoJobs))
 )]
@@ -507,77 +897,441 @@ async fn get_repo_jobs(
     State(app_state): State<AppState>,
     _user: BetaUser,
     axum::extract::Path((owner_login, repo_name)): axum::extract::Path<(String, String)>,
+    axum::extract::Query(page): axum::extract::Query<Page>,
 ) -> Result<Json<RepoJobs>> {
     let conn = &mut app_state.pool.get().await?;
 
-    // Fetch owner and repo records
+    // Fail with not found for unknown repositories rather than returning no jobs
     let owner_record = GithubOwner::get_by_login(conn, &owner_login).await?;
-    let repo_record = GitHubRepo::get_by_owner_and_name(conn, owner_record.id, &repo_name).await?;
-
-    // Fetch GitHub jobs and regular jobs in one query with a join
-    // and create a map from commit ID to a vector of JobResponse
-    let mut commit_jobs_map: std::collections::HashMap<uuid::Uuid, Vec<JobResponse>> =
-        std::collections::HashMap::new();
+    GitHubRepo::get_by_owner_and_name(conn, owner_record.id, &repo_name).await?;
 
-    // Use a join to get both JobGitHub and Job in one query
-    let job_pairs = JobGitHub::get_jobs_for_repo(conn, repo_record.id).await?;
-
-    // Group jobs by commit ID
-    for (github_job, job) in job_pairs {
-        commit_jobs_map
-            .entry(github_job.commit_id)
-            .or_insert_with(Vec::new)
-            .push({
-                // Generate a log URL for this job
-                let log_url = format!("{}/{}", app_state.config.logger_url, job.id);
-                let commit = GitHubCommit::get_by_id(conn, github_job.commit_id)
-                    .await
-                    .unwrap();
-                JobResponse {
-                    github: github_job,
-                    job,
-                    commit,
-                    log_url,
-                }
-            });
-    }
+    let result = crate::job::search::search(
+        conn,
+        &JobFilter {
+            owner: Some(owner_login.clone()),
+            repo: Some(repo_name.clone()),
+            cursor: page.cursor,
+            limit: page.limit,
+            ..Default::default()
+        },
+    )
+    .await?;
+    let page = JobPage::load(conn, result, &app_state.config.logger_url).await?;
 
-    // Simply order by id desc - UUIDv7 is time-based so this gives newest first
-    let commits = GitHubCommit::get_by_repo_ordered(conn, repo_record.id).await?;
-
-    // Map the commits to the final format with their associated jobs
-    // Use remove instead of get+clone to avoid unnecessary copying
-    // Also clone owner_login and repo_name once outside the loop
-    let owner = owner_login.clone();
-    let repo = repo_name.clone();
-
-    let commits = commits
-        .into_iter()
-        .map(move |commit| {
-            let jobs = commit_jobs_map.remove(&commit.id).unwrap_or_default();
-            Commit {
-                owner: owner.clone(),
-                repo: repo.clone(),
-                rev: commit.rev,
-                r#ref: commit.r#ref,
-                author: commit.author,
-                message: commit.message,
-                jobs,
+    // Group the jobs by commit, jobs are newest first so their commits are too
+    let mut commits: Vec<Commit> = Vec::new();
+    for job in page.jobs {
+        match commits.last_mut() {
+            Some(last)
+                if last
+                    .jobs
+                    .first()
+                    .is_some_and(|first| first.commit.id == job.commit.id) =>
+            {
+                last.jobs.push(job)
             }
-        })
-        .collect();
+            _ => commits.push(Commit {
+                owner: owner_login.clone(),
+                repo: repo_name.clone(),
+                rev: job.commit.rev.clone(),
+                r#ref: job.commit.r#ref.clone(),
+                author: job.commit.author.clone(),
+                message: job.commit.message.clone(),
+                jobs: vec![job],
+            }),
+        }
+    }
 
     Ok(Json(RepoJobs {
         owner: owner_login,
         repo: repo_name,
         commits,
+        total: page.total,
+        next_cursor: page.next_cursor,
     }))
 }
 
+/// What to build with a dispatch
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+struct DispatchRequest {
+    /// Branch, tag or commit SHA to build
+    r#ref: String,
+    /// Only create the jobs of these platforms, e.g. `x86_64-linux`
+    #[serde(default)]
+    #[schema(value_type = Option<Vec<String>>)]
+    platforms: Option<Vec<devenv_runner::protocol::Platform>>,
+}
+
+/// The commit built by a dispatch
+#[derive(serde::Serialize, utoipa::ToSchema)]
+struct Dispatched {
+    rev: String,
+    /// Ids of the created jobs
+    jobs: Vec<uuid::Uuid>,
+}
+
+/// Create the jobs of a branch, tag or commit without pushing
+///
+/// Dispatched commits are trusted, and the GitHub identity of signed in users
+/// isn't verified, so only admins dispatch.
+#[utoipa::path(
+    post,
+    path = "/{owner}/{repo}/dispatch",
+    params(
+        ("owner" = String, Path, description = "The repository owner"),
+        ("repo" = String, Path, description = "The repository name")
+    ),
+    request_body = DispatchRequest,
+    responses(
+        (status = 201, description = "Jobs created", body = Dispatched),
+        (status = 400, description = "Nothing to build at the ref, or on the platforms"),
+        (status = 403, description = "The user isn't an admin"),
+        (status = 404, description = "Ref not found")
+    )
+)]
+async fn dispatch(
+    State(app_state): State<AppState>,
+    _user: AdminUser,
+    Path((owner_login, repo_name)): Path<(String, String)>,
+    Json(request): Json<DispatchRequest>,
+) -> Result<Response> {
+    let conn = &mut app_state.pool.get().await?;
+    let owner = GithubOwner::get_by_login(conn, &owner_login).await?;
+    let repo = GitHubRepo::get_by_owner_and_name(conn, owner.id, &repo_name).await?;
+
+    // Get an installation-authenticated client
+    let installation = GithubInstallation::get_for_owner_id(conn, owner.id).await?;
+    let installation_client = JobGitHub::get_installation_client(&app_state, installation.id)?;
+
+    // Resolve branches and tags to the commit they point at
+    let git_ref = request.r#ref.trim_start_matches("refs/heads/").to_string();
+    let head = match installation_client
+        .commits(&owner.login, &repo.name)
+        .get(&git_ref)
+        .await
+    {
+        Ok(head) => head,
+        Err(octocrab::Error::GitHub { source, .. })
+            if matches!(source.status_code.as_u16(), 404 | 422) =>
+        {
+            return Ok(StatusCode::NOT_FOUND.into_response());
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let Some(mut cloud_config) =
+        JobGitHub::fetch_cloud_config(&installation_client, &owner.login, &repo.name, &head.sha)
+            .await?
+    else {
+        return Ok((StatusCode::BAD_REQUEST, "No devenv.nix at the ref").into_response());
+    };
+    if let Err(e) = request
+        .platforms
+        .as_deref()
+        .map_or(Ok(()), |platforms| cloud_config.retain_platforms(platforms))
+    {
+        return Ok((StatusCode::BAD_REQUEST, e).into_response());
+    }
+
+    // Forks' commits can be fetched by SHA through the repository too, so only
+    // branches of the repository itself are trusted
+    let trusted = installation_client
+        .repos(&owner.login, &repo.name)
+        .get_ref(&octocrab::params::repos::Reference::Branch(git_ref.clone()))
+        .await
+        .is_ok();
+
+    let github_commit = GitHubCommit {
+        id: uuid::Uuid::now_v7(),
+        rev: head.sha,
+        r#ref: git_ref,
+        repo_id: repo.id,
+        author: head
+            .author
+            .map(|author| author.login)
+            .unwrap_or_else(|| String::from("Unknown")),
+        message: head.commit.message,
+        trusted,
+    };
+    GitHubCommit::create(conn, github_commit.clone()).await?;
+
+    let jobs = github_commit
+        .create_jobs(
+            app_state.clone(),
+            &cloud_config,
+            crate::job::scheduler::PRIORITY_RETRY,
+            Trigger::Dispatch,
+        )
+        .await?;
+    tracing::info!(
+        "Dispatched {} jobs for {}/{} at {} ({})",
+        jobs.len(),
+        owner.login,
+        repo.name,
+        github_commit.r#ref,
+        github_commit.rev
+    );
+
+    Ok((
+        StatusCode::CREATED,
+        Json(Dispatched {
+            rev: github_commit.rev,
+            jobs: jobs.iter().map(|job| job.job_id).collect(),
+        }),
+    )
+        .into_response())
+}
+
+/// The jobs queued by an approval
+#[derive(serde::Serialize, utoipa::ToSchema)]
+struct Approved {
+    /// Number of jobs that were awaiting approval
+    jobs: usize,
+}
+
+/// Run the jobs of a commit from a fork that await approval
+///
+/// The GitHub identity of signed in users isn't verified, so only admins approve
+/// here. Collaborators approve on GitHub, by label or with `/devenv approve`.
+#[utoipa::path(
+    post,
+    path = "/{owner}/{repo}/{rev}/approve",
+    params(
+        ("owner" = String, Path, description = "The repository owner"),
+        ("repo" = String, Path, description = "The repository name"),
+        ("rev" = String, Path, description = "The commit revision hash")
+    ),
+    responses(
+        (status = OK, description = "Jobs approved", body = Approved),
+        (status = 403, description = "The user isn't an admin")
+    )
+)]
+async fn approve(
+    State(app_state): State<AppState>,
+    user: AdminUser,
+    Path((owner_login, repo_name, rev)): Path<(String, String, String)>,
+) -> Result<Json<Approved>> {
+    let conn = &mut app_state.pool.get().await?;
+    let owner = GithubOwner::get_by_login(conn, &owner_login).await?;
+    let repo = GitHubRepo::get_by_owner_and_name(conn, owner.id, &repo_name).await?;
+
+    let jobs = JobGitHub::approve(&app_state, conn, repo.id, &rev, &user.sub).await?;
+    Ok(Json(Approved { jobs }))
+}
+
+/// Largest secret value accepted, in bytes
+const MAX_SECRET_BYTES: usize = 48 * 1024;
+
+/// Value of a secret to set, which can't be read back
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+struct SecretValue {
+    value: String,
+}
+
+/// Set a secret of an owner, or of one of its repositories
+async fn set_secret(
+    app_state: &AppState,
+    conn: &mut diesel_async::AsyncPgConnection,
+    owner_id: i64,
+    repo_id: Option<i64>,
+    name: &str,
+    value: &str,
+) -> Result<StatusCode> {
+    let Some(cipher) = &app_state.secrets_cipher else {
+        return Ok(StatusCode::SERVICE_UNAVAILABLE);
+    };
+    if !is_valid_secret_name(name)
+        || is_reserved_secret_name(name)
+        || value.len() > MAX_SECRET_BYTES
+    {
+        return Ok(StatusCode::BAD_REQUEST);
+    }
+
+    GitHubSecret::set(conn, cipher, owner_id, repo_id, name, value).await?;
+    tracing::info!(
+        "Set secret {} of owner {} (repo {:?})",
+        name,
+        owner_id,
+        repo_id
+    );
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// List the secrets of an owner
+///
+/// The GitHub identity of signed in users isn't verified, so only admins manage
+/// secrets.
+#[utoipa::path(
+    get,
+    path = "/{owner}/secrets",
+    params(("owner" = String, Path, description = "The repository owner")),
+    responses(
+        (status = OK, body = Vec<SecretInfo>),
+        (status = 403, description = "The user isn't an admin")
+    )
+)]
+async fn list_owner_secrets(
+    State(app_state): State<AppState>,
+    _user: AdminUser,
+    Path(owner_login): Path<String>,
+) -> Result<Json<Vec<SecretInfo>>> {
+    let conn = &mut app_state.pool.get().await?;
+    let owner = GithubOwner::get_by_login(conn, &owner_login).await?;
+    Ok(Json(GitHubSecret::list(conn, owner.id, None).await?))
+}
+
+#[utoipa::path(
+    put,
+    path = "/{owner}/secrets/{name}",
+    params(
+        ("owner" = String, Path, description = "The repository owner"),
+        ("name" = String, Path, description = "Name of the secret")
+    ),
+    request_body = SecretValue,
+    responses(
+        (status = 204, description = "Secret set"),
+        (status = 400, description = "Invalid secret name or value"),
+        (status = 403, description = "The user isn't an admin"),
+        (status = 503, description = "Secrets aren't configured")
+    )
+)]
+async fn set_owner_secret(
+    State(app_state): State<AppState>,
+    _user: AdminUser,
+    Path((owner_login, name)): Path<(String, String)>,
+    Json(secret): Json<SecretValue>,
+) -> Result<StatusCode> {
+    let conn = &mut app_state.pool.get().await?;
+    let owner = GithubOwner::get_by_login(conn, &owner_login).await?;
+    set_secret(&app_state, conn, owner.id, None, &name, &secret.value).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/{owner}/secrets/{name}",
+    params(
+        ("owner" = String, Path, description = "The repository owner"),
+        ("name" = String, Path, description = "Name of the secret")
+    ),
+    responses(
+        (status = 204, description = "Secret deleted"),
+        (status = 403, description = "The user isn't an admin"),
+        (status = 404, description = "Secret not found")
+    )
+)]
+async fn delete_owner_secret(
+    State(app_state): State<AppState>,
+    _user: AdminUser,
+    Path((owner_login, name)): Path<(String, String)>,
+) -> Result<StatusCode> {
+    let conn = &mut app_state.pool.get().await?;
+    let owner = GithubOwner::get_by_login(conn, &owner_login).await?;
+    if !GitHubSecret::delete(conn, owner.id, None, &name).await? {
+        return Ok(StatusCode::NOT_FOUND);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/{owner}/{repo}/secrets",
+    params(
+        ("owner" = String, Path, description = "The repository owner"),
+        ("repo" = String, Path, description = "The repository name")
+    ),
+    responses(
+        (status = OK, body = Vec<SecretInfo>),
+        (status = 403, description = "The user isn't an admin")
+    )
+)]
+async fn list_repo_secrets(
+    State(app_state): State<AppState>,
+    _user: AdminUser,
+    Path((owner_login, repo_name)): Path<(String, String)>,
+) -> Result<Json<Vec<SecretInfo>>> {
+    let conn = &mut app_state.pool.get().await?;
+    let owner = GithubOwner::get_by_login(conn, &owner_login).await?;
+    let repo = GitHubRepo::get_by_owner_and_name(conn, owner.id, &repo_name).await?;
+    Ok(Json(
+        GitHubSecret::list(conn, owner.id, Some(repo.id)).await?,
+    ))
+}
+
+#[utoipa::path(
+    put,
+    path = "/{owner}/{repo}/secrets/{name}",
+    params(
+        ("owner" = String, Path, description = "The repository owner"),
+        ("repo" = String, Path, description = "The repository name"),
+        ("name" = String, Path, description = "Name of the secret")
+    ),
+    request_body = SecretValue,
+    responses(
+        (status = 204, description = "Secret set"),
+        (status = 400, description = "Invalid secret name or value"),
+        (status = 403, description = "The user isn't an admin"),
+        (status = 503, description = "Secrets aren't configured")
+    )
+)]
+async fn set_repo_secret(
+    State(app_state): State<AppState>,
+    _user: AdminUser,
+    Path((owner_login, repo_name, name)): Path<(String, String, String)>,
+    Json(secret): Json<SecretValue>,
+) -> Result<StatusCode> {
+    let conn = &mut app_state.pool.get().await?;
+    let owner = GithubOwner::get_by_login(conn, &owner_login).await?;
+    let repo = GitHubRepo::get_by_owner_and_name(conn, owner.id, &repo_name).await?;
+    set_secret(
+        &app_state,
+        conn,
+        owner.id,
+        Some(repo.id),
+        &name,
+        &secret.value,
+    )
+    .await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/{owner}/{repo}/secrets/{name}",
+    params(
+        ("owner" = String, Path, description = "The repository owner"),
+        ("repo" = String, Path, description = "The repository name"),
+        ("name" = String, Path, description = "Name of the secret")
+    ),
+    responses(
+        (status = 204, description = "Secret deleted"),
+        (status = 403, description = "The user isn't an admin"),
+        (status = 404, description = "Secret not found")
+    )
+)]
+async fn delete_repo_secret(
+    State(app_state): State<AppState>,
+    _user: AdminUser,
+    Path((owner_login, repo_name, name)): Path<(String, String, String)>,
+) -> Result<StatusCode> {
+    let conn = &mut app_state.pool.get().await?;
+    let owner = GithubOwner::get_by_login(conn, &owner_login).await?;
+    let repo = GitHubRepo::get_by_owner_and_name(conn, owner.id, &repo_name).await?;
+    if !GitHubSecret::delete(conn, owner.id, Some(repo.id), &name).await? {
+        return Ok(StatusCode::NOT_FOUND);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
 pub fn router() -> OpenApiRouter<AppState> {
     OpenApiRouter::new()
         .routes(routes!(get_repos))
         .routes(routes!(get_rev))
         .routes(routes!(get_repo_jobs))
+        .routes(routes!(dispatch))
+        .routes(routes!(approve))
+        .routes(routes!(list_owner_secrets))
+        .routes(routes!(set_owner_secret, delete_owner_secret))
+        .routes(routes!(list_repo_secrets))
+        .routes(routes!(set_repo_secret, delete_repo_secret))
         .routes(routes!(webhook))
 }
@@ -0,0 +1,271 @@
+//! Cron expressions of scheduled jobs
+//!
+//! Expressions have the usual five fields: minute, hour, day of month, month
+//! and day of week, evaluated in UTC. Fields take `*`, values, ranges, steps
+//! and lists of those, months and days of the week also take their English
+//! three letter names. `@hourly`, `@daily`, `@weekly`, `@monthly` and
+//! `@yearly` are shorthands for the common schedules.
+//!
+//! Like in cron, a day matches if either the day of month or the day of week
+//! matches when both are restricted.
+
+use chrono::{DateTime, Datelike, Days, TimeDelta, TimeZone, Timelike, Utc};
+use std::fmt;
+use std::str::FromStr;
+
+const MONTH_NAMES: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAY_NAMES: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// How far ahead to look for the next match, long enough for leap days
+const MAX_LOOKAHEAD_DAYS: i64 = 366 * 5;
+
+/// A parsed cron expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cron {
+    expression: String,
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    /// Whether the day of month field was `*`
+    any_day_of_month: bool,
+    /// Whether the day of week field was `*`
+    any_day_of_week: bool,
+}
+
+impl Cron {
+    /// The expression as written in devenv.yaml
+    pub fn as_str(&self) -> &str {
+        &self.expression
+    }
+
+    /// The first time after `after` the expression matches, at the start of a minute
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let limit = after + TimeDelta::days(MAX_LOOKAHEAD_DAYS);
+        let mut time = after.with_second(0)?.with_nanosecond(0)? + TimeDelta::minutes(1);
+        // Skip whole months, days and hours that don't match
+        while time <= limit {
+            if !has(self.months, time.month()) {
+                let (year, month) = match time.month() {
+                    12 => (time.year() + 1, 1),
+                    month => (time.year(), month + 1),
+                };
+                time = Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0).single()?;
+            } else if !self.matches_day(time) {
+                time = (time.date_naive() + Days::new(1))
+                    .and_hms_opt(0, 0, 0)?
+                    .and_utc();
+            } else if !has(self.hours, time.hour()) {
+                time = time.with_minute(0)? + TimeDelta::hours(1);
+            } else if !has(self.minutes, time.minute()) {
+                time += TimeDelta::minutes(1);
+            } else {
+                return Some(time);
+            }
+        }
+        None
+    }
+
+    fn matches_day(&self, time: DateTime<Utc>) -> bool {
+        let day_of_month = has(self.days_of_month, time.day());
+        let day_of_week = has(self.days_of_week, time.weekday().num_days_from_sunday());
+        match (self.any_day_of_month, self.any_day_of_week) {
+            (false, false) => day_of_month || day_of_week,
+            (false, true) => day_of_month,
+            (true, false) => day_of_week,
+            (true, true) => true,
+        }
+    }
+}
+
+impl FromStr for Cron {
+    type Err = String;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let expanded = match expression.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            other => other,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minute, hour, day_of_month, month, day_of_week] = fields[..] else {
+            return Err(format!(
+                "Schedule '{}' must have 5 fields: minute, hour, day of month, month and day of week",
+                expression
+            ));
+        };
+
+        let invalid = |e: String| format!("Invalid schedule '{}': {}", expression, e);
+        let mut days_of_week =
+            parse_field(day_of_week, 0, 7, &WEEKDAY_NAMES, 0).map_err(invalid)?;
+        // Both 0 and 7 are Sunday
+        if has(days_of_week, 7) {
+            days_of_week = (days_of_week | 1) & !(1 << 7);
+        }
+        let cron = Cron {
+            expression: expression.trim().to_string(),
+            minutes: parse_field(minute, 0, 59, &[], 0).map_err(invalid)?,
+            hours: parse_field(hour, 0, 23, &[], 0).map_err(invalid)?,
+            days_of_month: parse_field(day_of_month, 1, 31, &[], 0).map_err(invalid)?,
+            months: parse_field(month, 1, 12, &MONTH_NAMES, 1).map_err(invalid)?,
+            days_of_week,
+            any_day_of_month: day_of_month.starts_with('*'),
+            any_day_of_week: day_of_week.starts_with('*'),
+        };
+
+        // Catch expressions like `0 0 31 2 *` that never run
+        if cron.next_after(DateTime::UNIX_EPOCH).is_none() {
+            return Err(format!("Schedule '{}' never matches", expression));
+        }
+        Ok(cron)
+    }
+}
+
+impl fmt::Display for Cron {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+fn has(set: u64, value: u32) -> bool {
+    set & (1 << value) != 0
+}
+
+/// Parse a field into a bit set of the values it matches
+///
+/// `names` are alternatives to the values starting at `first_name`.
+fn parse_field(
+    field: &str,
+    min: u32,
+    max: u32,
+    names: &[&str],
+    first_name: u32,
+) -> Result<u64, String> {
+    let value = |s: &str| -> Result<u32, String> {
+        let value = match names.iter().position(|name| name.eq_ignore_ascii_case(s)) {
+            Some(index) => index as u32 + first_name,
+            None => s.parse().map_err(|_| format!("'{}' isn't a number", s))?,
+        };
+        if value < min || value > max {
+            return Err(format!("{} isn't between {} and {}", value, min, max));
+        }
+        Ok(value)
+    };
+
+    let mut set = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step
+                    .parse()
+                    .map_err(|_| format!("'{}' isn't a valid step", step))?;
+                if step == 0 {
+                    return Err("steps must be at least 1".to_string());
+                }
+                (range, Some(step))
+            }
+            None => (part, None),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            range => match range.split_once('-') {
+                Some((start, end)) => (value(start)?, value(end)?),
+                // `5/15` runs from 5 on
+                None if step.is_some() => (value(range)?, max),
+                None => (value(range)?, value(range)?),
+            },
+        };
+        if start > end {
+            return Err(format!("range {} is backwards", range));
+        }
+        for value in (start..=end).step_by(step.unwrap_or(1) as usize) {
+            set |= 1 << value;
+        }
+    }
+    Ok(set)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().to_utc()
+    }
+
+    fn next(expression: &str, after: &str) -> String {
+        let cron: Cron = expression.parse().unwrap();
+        cron.next_after(time(after)).unwrap().to_rfc3339()
+    }
+
+    #[test]
+    fn test_next_after() {
+        assert_eq!(
+            next("*/15 * * * *", "2025-07-03T10:07:30Z"),
+            "2025-07-03T10:15:00+00:00"
+        );
+        // Never the time itself, even at the start of a minute
+        assert_eq!(
+            next("0 * * * *", "2025-07-03T10:00:00Z"),
+            "2025-07-03T11:00:00+00:00"
+        );
+        assert_eq!(
+            next("30 2 * * *", "2025-12-31T03:00:00Z"),
+            "2026-01-01T02:30:00+00:00"
+        );
+        assert_eq!(
+            next("0 0 29 2 *", "2025-03-01T00:00:00Z"),
+            "2028-02-29T00:00:00+00:00"
+        );
+    }
+
+    #[test]
+    fn test_names_and_shorthands() {
+        // 2025-07-03 is a Thursday
+        assert_eq!(
+            next("0 9 * * mon-fri", "2025-07-04T10:00:00Z"),
+            "2025-07-07T09:00:00+00:00"
+        );
+        assert_eq!(
+            next("0 0 1 JAN,jul *", "2025-02-01T00:00:00Z"),
+            "2025-07-01T00:00:00+00:00"
+        );
+        assert_eq!(
+            next("@weekly", "2025-07-03T00:00:00Z"),
+            "2025-07-06T00:00:00+00:00"
+        );
+        assert_eq!(
+            next("0 0 * * 7", "2025-07-03T00:00:00Z"),
+            "2025-07-06T00:00:00+00:00"
+        );
+    }
+
+    #[test]
+    fn test_day_of_month_or_day_of_week() {
+        // The 15th or any Monday
+        assert_eq!(
+            next("0 0 15 * 1", "2025-07-03T00:00:00Z"),
+            "2025-07-07T00:00:00+00:00"
+        );
+        assert_eq!(
+            next("0 0 15 * 1", "2025-07-14T01:00:00Z"),
+            "2025-07-15T00:00:00+00:00"
+        );
+    }
+
+    #[test]
+    fn test_invalid_expressions() {
+        assert!("* * * *".parse::<Cron>().is_err());
+        assert!("60 * * * *".parse::<Cron>().is_err());
+        assert!("*/0 * * * *".parse::<Cron>().is_err());
+        assert!("5-1 * * * *".parse::<Cron>().is_err());
+        assert!("0 0 * foo *".parse::<Cron>().is_err());
+        assert!("0 0 31 2 *".parse::<Cron>().is_err());
+    }
+}
@@ -1,2 +1,5 @@
+pub mod cron;
 pub mod model;
+pub mod scheduler;
+pub mod search;
 pub mod serve;
@@ -1,4 +1,5 @@
-use crate::schema::jobs;
+use crate::schema::{job_artifacts, job_dependencies, job_steps, jobs, runners};
+use bytes::Bytes;
 use diesel::deserialize::{FromSql, FromSqlRow};
 use diesel::expression::AsExpression;
 use diesel::prelude::*;
@@ -6,8 +7,12 @@ use diesel::serialize::ToSql;
 use diesel_async::AsyncPgConnection;
 use diesel_async::RunQueryDsl;
 use eyre::Result;
+use futures_util::{Stream, StreamExt};
+use object_store::ObjectStore;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::io::Write;
+use std::sync::Arc;
 use strum_macros::{Display, EnumString};
 use utoipa::ToSchema;
 use uuid::Uuid;
@@ -60,6 +65,50 @@ impl From<devenv_runner::protocol::Platform> for Platform {
     }
 }
 
+/// The event that created a job
+#[derive(
+    Debug,
+    Serialize,
+    Deserialize,
+    AsExpression,
+    FromSqlRow,
+    Display,
+    EnumString,
+    ToSchema,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+)]
+#[diesel(sql_type = diesel::sql_types::Text)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Trigger {
+    Push,
+    PullRequest,
+    /// A schedule declared in `cloud.schedule`
+    Schedule,
+    /// Started through the dispatch API
+    Dispatch,
+}
+
+impl ToSql<diesel::sql_types::Text, diesel::pg::Pg> for Trigger {
+    fn to_sql(
+        &self,
+        out: &mut diesel::serialize::Output<diesel::pg::Pg>,
+    ) -> diesel::serialize::Result {
+        out.write_all(self.to_string().as_bytes())?;
+        Ok(diesel::serialize::IsNull::No)
+    }
+}
+
+impl FromSql<diesel::sql_types::Text, diesel::pg::Pg> for Trigger {
+    fn from_sql(bytes: diesel::pg::PgValue<'_>) -> diesel::deserialize::Result<Self> {
+        let string = <String as FromSql<diesel::sql_types::Text, diesel::pg::Pg>>::from_sql(bytes)?;
+        string.parse().map_err(|_| "Unrecognized trigger".into())
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, AsExpression, FromSqlRow, Clone, ToSchema)]
 #[diesel(sql_type = diesel::sql_types::Text)]
 pub struct JobStatus(pub devenv_runner::protocol::JobStatus);
@@ -77,6 +126,10 @@ impl JobStatus {
         Self(devenv_runner::protocol::JobStatus::Running)
     }
 
+    pub fn awaiting_approval() -> Self {
+        Self(devenv_runner::protocol::JobStatus::AwaitingApproval)
+    }
+
     pub fn complete(completion: devenv_runner::protocol::CompletionStatus) -> Self {
         Self(devenv_runner::protocol::JobStatus::Complete(completion))
     }
@@ -100,6 +153,10 @@ impl JobStatus {
     pub fn skipped() -> Self {
         Self::complete(devenv_runner::protocol::CompletionStatus::Skipped)
     }
+
+    pub fn infrastructure_failure() -> Self {
+        Self::complete(devenv_runner::protocol::CompletionStatus::InfrastructureFailure)
+    }
 }
 
 impl ToSql<diesel::sql_types::Text, diesel::pg::Pg> for JobStatus {
@@ -110,6 +167,9 @@ impl ToSql<diesel::sql_types::Text, diesel::pg::Pg> for JobStatus {
         match self.0 {
             devenv_runner::protocol::JobStatus::Queued => out.write_all(b"queued")?,
             devenv_runner::protocol::JobStatus::Running => out.write_all(b"running")?,
+            devenv_runner::protocol::JobStatus::AwaitingApproval => {
+                out.write_all(b"awaiting_approval")?
+            }
             devenv_runner::protocol::JobStatus::Complete(
                 devenv_runner::protocol::CompletionStatus::Failed,
             ) => out.write_all(b"failed")?,
@@ -125,6 +185,9 @@ impl ToSql<diesel::sql_types::Text, diesel::pg::Pg> for JobStatus {
             devenv_runner::protocol::JobStatus::Complete(
                 devenv_runner::protocol::CompletionStatus::Skipped,
             ) => out.write_all(b"skipped")?,
+            devenv_runner::protocol::JobStatus::Complete(
+                devenv_runner::protocol::CompletionStatus::InfrastructureFailure,
+            ) => out.write_all(b"infrastructure_failure")?,
         }
         Ok(diesel::serialize::IsNull::No)
     }
@@ -136,6 +199,9 @@ impl FromSql<diesel::sql_types::Text, diesel::pg::Pg> for JobStatus {
         match string.as_str() {
             "queued" => Ok(JobStatus(devenv_runner::protocol::JobStatus::Queued)),
             "running" => Ok(JobStatus(devenv_runner::protocol::JobStatus::Running)),
+            "awaiting_approval" => Ok(JobStatus(
+                devenv_runner::protocol::JobStatus::AwaitingApproval,
+            )),
             "failed" => Ok(JobStatus(devenv_runner::protocol::JobStatus::Complete(
                 devenv_runner::protocol::CompletionStatus::Failed,
             ))),
@@ -151,6 +217,11 @@ impl FromSql<diesel::sql_types::Text, diesel::pg::Pg> for JobStatus {
             "skipped" => Ok(JobStatus(devenv_runner::protocol::JobStatus::Complete(
                 devenv_runner::protocol::CompletionStatus::Skipped,
             ))),
+            "infrastructure_failure" => {
+                Ok(JobStatus(devenv_runner::protocol::JobStatus::Complete(
+                    devenv_runner::protocol::CompletionStatus::InfrastructureFailure,
+                )))
+            }
             _ => Err("Unrecognized status".into()),
         }
     }
@@ -169,26 +240,215 @@ pub struct Job {
     pub retried_job_id: Option<uuid::Uuid>,
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub previous_job_id: Option<uuid::Uuid>,
+    pub artifacts: Vec<String>,
+    pub name: String,
+    pub tasks: Option<Vec<String>>,
+    pub priority: i32,
+    pub required_labels: Vec<String>,
+    pub lease_expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub guest_started_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Names of the secrets exposed to the job
+    pub secrets: Vec<String>,
+    pub trigger: Trigger,
+    /// How long the job may run, `None` uses the configured default
+    pub timeout_seconds: Option<i32>,
+    /// When a maintainer approved the job, carried over to its retries
+    pub approved_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 impl Job {
+    /// A queued `build` job on x86_64-linux, for tests to change with struct update syntax
+    #[cfg(test)]
+    pub fn test_default() -> Self {
+        Self {
+            id: Uuid::now_v7(),
+            platform: Platform::X86_64Linux,
+            status: JobStatus::queued(),
+            started_at: None,
+            finished_at: None,
+            runner_id: None,
+            cpus: 2,
+            memory_mb: 4096,
+            retried_job_id: None,
+            created_at: chrono::Utc::now(),
+            previous_job_id: None,
+            artifacts: vec![],
+            name: "build".to_string(),
+            tasks: None,
+            priority: 0,
+            required_labels: vec![],
+            lease_expires_at: None,
+            guest_started_at: None,
+            secrets: vec![],
+            trigger: Trigger::Push,
+            timeout_seconds: None,
+            approved_at: None,
+        }
+    }
+
+    /// Create a queued job that only runs once all jobs in `needs` succeeded
+    ///
+    /// Jobs awaiting approval aren't queued until they're approved.
     pub async fn new(
         conn: &mut AsyncPgConnection,
-        platform: Platform,
-        cpus: Option<i32>,
-        memory_mb: Option<i64>,
+        spec: &crate::runner::cloudconfig::JobSpec,
+        artifacts: &[String],
+        needs: &[Uuid],
+        priority: i32,
+        trigger: Trigger,
+        awaiting_approval: bool,
     ) -> Result<Self, diesel::result::Error> {
-        let job = diesel::insert_into(jobs::table)
-            .values((
-                jobs::id.eq(Uuid::now_v7()),
-                jobs::platform.eq(platform),
-                jobs::status.eq(JobStatus::queued()),
-                jobs::cpus.eq(cpus.unwrap_or(2)),
-                jobs::memory_mb.eq(memory_mb.unwrap_or(256)),
+        let platform: Platform = spec.vm.platform.clone().into();
+        let status = if awaiting_approval {
+            JobStatus::awaiting_approval()
+        } else {
+            JobStatus::queued()
+        };
+        let values = (
+            jobs::id.eq(Uuid::now_v7()),
+            jobs::platform.eq(platform),
+            jobs::status.eq(status),
+            jobs::cpus.eq(spec.vm.cpu_count as i32),
+            jobs::memory_mb.eq(spec.vm.memory_size_mb as i64),
+            jobs::artifacts.eq(artifacts),
+            jobs::name.eq(&spec.name),
+            jobs::tasks.eq(&spec.tasks),
+            jobs::priority.eq(priority),
+            jobs::required_labels.eq(&spec.runs_on),
+            jobs::secrets.eq(&spec.secrets),
+            jobs::trigger.eq(trigger),
+            jobs::timeout_seconds.eq(spec
+                .timeout_seconds
+                .map(|seconds| i32::try_from(seconds).unwrap_or(i32::MAX))),
+        );
+        let needs = needs.to_vec();
+
+        // Insert the job and its dependencies together so it's never claimable too early
+        conn.build_transaction()
+            .run::<_, diesel::result::Error, _>(|conn| {
+                Box::pin(async move {
+                    let job: Self = diesel::insert_into(jobs::table)
+                        .values(values)
+                        .get_result(conn)
+                        .await?;
+                    Self::add_dependencies(conn, job.id, &needs).await?;
+                    Ok(job)
+                })
+            })
+            .await
+    }
+
+    async fn add_dependencies(
+        conn: &mut AsyncPgConnection,
+        job_id: Uuid,
+        needs: &[Uuid],
+    ) -> Result<(), diesel::result::Error> {
+        if needs.is_empty() {
+            return Ok(());
+        }
+        let succeeded: Vec<Uuid> = jobs::table
+            .filter(jobs::id.eq_any(needs))
+            .filter(jobs::status.eq(JobStatus::success()))
+            .select(jobs::id)
+            .load(conn)
+            .await?;
+        let rows: Vec<_> = needs
+            .iter()
+            .map(|depends_on| {
+                (
+                    job_dependencies::job_id.eq(job_id),
+                    job_dependencies::depends_on_job_id.eq(depends_on),
+                    job_dependencies::satisfied.eq(succeeded.contains(depends_on)),
+                )
+            })
+            .collect();
+        diesel::insert_into(job_dependencies::table)
+            .values(rows)
+            .on_conflict_do_nothing()
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Get the ids of the jobs that have to succeed before this one runs
+    pub async fn get_dependencies(
+        conn: &mut AsyncPgConnection,
+        job_id: Uuid,
+    ) -> Result<Vec<Uuid>, diesel::result::Error> {
+        job_dependencies::table
+            .filter(job_dependencies::job_id.eq(job_id))
+            .select(job_dependencies::depends_on_job_id)
+            .load(conn)
+            .await
+    }
+
+    /// Mark queued or unapproved jobs depending on the given job as skipped, returning them
+    pub async fn skip_dependents(
+        conn: &mut AsyncPgConnection,
+        job_id: Uuid,
+    ) -> Result<Vec<Self>, diesel::result::Error> {
+        let dependents = job_dependencies::table
+            .filter(job_dependencies::depends_on_job_id.eq(job_id))
+            .select(job_dependencies::job_id);
+
+        diesel::update(jobs::table)
+            .filter(jobs::id.eq_any(dependents))
+            .filter(jobs::status.eq_any(vec![JobStatus::queued(), JobStatus::awaiting_approval()]))
+            .set((
+                jobs::status.eq(JobStatus::skipped()),
+                jobs::finished_at.eq(chrono::Utc::now()),
             ))
-            .get_result(conn)
+            .get_results(conn)
+            .await
+    }
+
+    /// Record that the given job succeeded, returning the queued jobs that are now unblocked
+    pub async fn release_dependents(
+        conn: &mut AsyncPgConnection,
+        job_id: Uuid,
+    ) -> Result<Vec<Self>, diesel::result::Error> {
+        let dependents: Vec<Uuid> = diesel::update(job_dependencies::table)
+            .filter(job_dependencies::depends_on_job_id.eq(job_id))
+            .set(job_dependencies::satisfied.eq(true))
+            .returning(job_dependencies::job_id)
+            .get_results(conn)
             .await?;
-        Ok(job)
+
+        jobs::table
+            .filter(jobs::id.eq_any(dependents))
+            .filter(jobs::status.eq(JobStatus::queued()))
+            .filter(diesel::dsl::not(jobs::id.eq_any(Self::blocked_job_ids())))
+            .load(conn)
+            .await
+    }
+
+    /// Queue the given jobs that are awaiting approval, returning them
+    pub async fn approve(
+        conn: &mut AsyncPgConnection,
+        job_ids: &[Uuid],
+    ) -> Result<Vec<Self>, diesel::result::Error> {
+        diesel::update(jobs::table)
+            .filter(jobs::id.eq_any(job_ids))
+            .filter(jobs::status.eq(JobStatus::awaiting_approval()))
+            .set((
+                jobs::status.eq(JobStatus::queued()),
+                jobs::approved_at.eq(diesel::dsl::now),
+            ))
+            .get_results(conn)
+            .await
+    }
+
+    /// Ids of jobs with at least one dependency that hasn't succeeded (yet)
+    pub(crate) fn blocked_job_ids() -> diesel::dsl::Select<
+        diesel::dsl::Filter<
+            job_dependencies::table,
+            diesel::dsl::Eq<job_dependencies::satisfied, bool>,
+        >,
+        job_dependencies::job_id,
+    > {
+        job_dependencies::table
+            .filter(job_dependencies::satisfied.eq(false))
+            .select(job_dependencies::job_id)
     }
 
     pub async fn get_by_id(
@@ -261,31 +521,149 @@ impl Job {
     /// Find in-progress jobs that have exceeded the timeout period
     pub async fn find_expired_jobs(
         conn: &mut AsyncPgConnection,
-        timeout_seconds: u64,
+        default_timeout_seconds: u64,
+        max_timeout_seconds: u64,
     ) -> Result<Vec<Job>, diesel::result::Error> {
-        // Find jobs that are in progress and started more than timeout_seconds ago
-        let timeout_duration = chrono::Duration::seconds(timeout_seconds as i64);
-        let cutoff_time = chrono::Utc::now() - timeout_duration;
+        use diesel::sql_types::{BigInt, Bool};
 
-        // Get jobs to time out
+        // Find jobs that are in progress and started more than their timeout ago,
+        // their own timeout capped by the maximum
         jobs::table
             .filter(jobs::status.eq(JobStatus::running()))
-            .filter(jobs::started_at.lt(cutoff_time))
+            .filter(
+                diesel::dsl::sql::<Bool>(
+                    "started_at + LEAST(COALESCE(GREATEST(timeout_seconds, 0), ",
+                )
+                .bind::<BigInt, _>(i64::try_from(default_timeout_seconds).unwrap_or(i64::MAX))
+                .sql("), ")
+                .bind::<BigInt, _>(i64::try_from(max_timeout_seconds).unwrap_or(i64::MAX))
+                .sql(") * interval '1 second' < NOW()"),
+            )
             .load::<Job>(conn)
             .await
     }
 
+    /// Find the jobs the database considers running on a runner
+    pub async fn find_running_for_runner(
+        conn: &mut AsyncPgConnection,
+        runner_id: uuid::Uuid,
+    ) -> Result<Vec<Job>, diesel::result::Error> {
+        jobs::table
+            .filter(jobs::status.eq(JobStatus::running()))
+            .filter(jobs::runner_id.eq(runner_id))
+            .select(Job::as_select())
+            .load(conn)
+            .await
+    }
+
+    /// Extend the leases of the jobs a runner is still executing
+    ///
+    /// Jobs in `started` are recorded as having been picked up by the guest.
+    pub async fn renew_leases(
+        conn: &mut AsyncPgConnection,
+        runner_id: uuid::Uuid,
+        ids: &[Uuid],
+        started: &[Uuid],
+        lease_expires_at: chrono::DateTime<chrono::Utc>,
+    ) -> Result<(), diesel::result::Error> {
+        diesel::update(jobs::table)
+            .filter(jobs::id.eq_any(ids))
+            .filter(jobs::runner_id.eq(runner_id))
+            .filter(jobs::status.eq(JobStatus::running()))
+            .set(jobs::lease_expires_at.eq(lease_expires_at))
+            .execute(conn)
+            .await?;
+        diesel::update(jobs::table)
+            .filter(jobs::id.eq_any(started))
+            .filter(jobs::runner_id.eq(runner_id))
+            .filter(jobs::guest_started_at.is_null())
+            .set(jobs::guest_started_at.eq(chrono::Utc::now()))
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Find running jobs whose runner stopped renewing their lease
+    pub async fn find_lapsed_leases(
+        conn: &mut AsyncPgConnection,
+    ) -> Result<Vec<Job>, diesel::result::Error> {
+        jobs::table
+            .filter(jobs::status.eq(JobStatus::running()))
+            .filter(jobs::lease_expires_at.lt(chrono::Utc::now()))
+            .select(Job::as_select())
+            .load(conn)
+            .await
+    }
+
+    /// Put a job its runner lost back in the queue, unless the guest already started it
+    ///
+    /// Returns false if the guest started the job or it moved on in the meantime.
+    pub async fn requeue(
+        conn: &mut AsyncPgConnection,
+        job_id: uuid::Uuid,
+        runner_id: uuid::Uuid,
+    ) -> Result<bool, diesel::result::Error> {
+        let rows = diesel::update(jobs::table)
+            .filter(jobs::id.eq(job_id))
+            .filter(jobs::status.eq(JobStatus::running()))
+            .filter(jobs::runner_id.eq(runner_id))
+            .filter(jobs::guest_started_at.is_null())
+            .set((
+                jobs::status.eq(JobStatus::queued()),
+                jobs::runner_id.eq(None::<Uuid>),
+                jobs::started_at.eq(None::<chrono::DateTime<chrono::Utc>>),
+                jobs::lease_expires_at.eq(None::<chrono::DateTime<chrono::Utc>>),
+            ))
+            .execute(conn)
+            .await?;
+        Ok(rows == 1)
+    }
+
+    /// Move a running job to a runner, if it was started by a runner using the same token
+    pub async fn adopt(
+        conn: &mut AsyncPgConnection,
+        job_id: uuid::Uuid,
+        runner_id: uuid::Uuid,
+        token_id: uuid::Uuid,
+    ) -> Result<bool, diesel::result::Error> {
+        let rows = diesel::update(jobs::table)
+            .filter(jobs::id.eq(job_id))
+            .filter(jobs::status.eq(JobStatus::running()))
+            .filter(
+                jobs::runner_id.eq_any(
+                    runners::table
+                        .filter(runners::token_id.eq(token_id))
+                        .select(runners::id.nullable()),
+                ),
+            )
+            .set(jobs::runner_id.eq(runner_id))
+            .execute(conn)
+            .await?;
+        Ok(rows == 1)
+    }
+
     pub async fn claim_job_for_runner(
         conn: &mut AsyncPgConnection,
         job_id: uuid::Uuid,
         runner_id: uuid::Uuid,
+        runner_labels: &[String],
+        lease_expires_at: chrono::DateTime<chrono::Utc>,
     ) -> Result<usize, diesel::result::Error> {
         diesel::update(jobs::table)
             .filter(jobs::id.eq(job_id))
             .filter(jobs::status.eq(JobStatus(devenv_runner::protocol::JobStatus::Queued)))
+            .filter(jobs::runner_id.is_null())
+            .filter(jobs::required_labels.is_contained_by(runner_labels))
+            .filter(diesel::dsl::not(jobs::id.eq_any(Self::blocked_job_ids())))
+            .filter(diesel::dsl::not(diesel::dsl::exists(
+                runners::table
+                    .filter(runners::id.eq(runner_id))
+                    .filter(runners::cordoned_at.is_not_null()),
+            )))
             .set((
                 jobs::status.eq(JobStatus(devenv_runner::protocol::JobStatus::Running)),
                 jobs::runner_id.eq(runner_id),
+                jobs::lease_expires_at.eq(lease_expires_at),
             ))
             .execute(conn)
             .await
@@ -305,24 +683,6 @@ impl Job {
         Ok(())
     }
 
-    pub async fn find_queued_job_for_platform(
-        conn: &mut AsyncPgConnection,
-        platform: &Platform,
-    ) -> Result<Option<Job>, diesel::result::Error> {
-        let job = jobs::table
-            .for_update()
-            .skip_locked()
-            .filter(jobs::status.eq(JobStatus(devenv_runner::protocol::JobStatus::Queued)))
-            .filter(jobs::runner_id.is_null())
-            .filter(jobs::platform.eq(platform))
-            .order_by(jobs::id)
-            .first(conn)
-            .await
-            .optional()?;
-
-        Ok(job)
-    }
-
     /// Check if a job can be retried
     pub fn is_retryable(&self) -> bool {
         matches!(
@@ -332,23 +692,181 @@ impl Job {
         )
     }
 
+    /// Number of infrastructure failures in a row, through the previous runs, ending with this job
+    pub async fn infrastructure_failures(
+        &self,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<u32, diesel::result::Error> {
+        let mut failures = 0;
+        let mut run = Some((self.status.clone(), self.previous_job_id));
+        while let Some((status, previous_job_id)) = run {
+            if !matches!(
+                status.0,
+                devenv_runner::protocol::JobStatus::Complete(
+                    devenv_runner::protocol::CompletionStatus::InfrastructureFailure
+                )
+            ) {
+                break;
+            }
+            failures += 1;
+            run = match previous_job_id {
+                Some(id) => Some(
+                    jobs::table
+                        .find(id)
+                        .select((jobs::status, jobs::previous_job_id))
+                        .first(conn)
+                        .await?,
+                ),
+                None => None,
+            };
+        }
+        Ok(failures)
+    }
+
+    /// The status a retry of the job starts in
+    ///
+    /// Jobs of pull requests from forks may have been cancelled or superseded
+    /// while awaiting approval, so their retries await approval until a
+    /// maintainer approved a run of the job.
+    fn retry_status(&self, trusted: bool) -> JobStatus {
+        if !trusted && self.trigger == Trigger::PullRequest && self.approved_at.is_none() {
+            JobStatus::awaiting_approval()
+        } else {
+            JobStatus::queued()
+        }
+    }
+
+    /// Queue the jobs skipped because this job didn't succeed again, along with the ones skipped in turn
+    ///
+    /// Jobs that also depend on another job that didn't succeed stay skipped.
+    /// Returns the jobs that were queued again.
+    async fn requeue_skipped_dependents(
+        &self,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<Vec<Self>, diesel::result::Error> {
+        // Skipped jobs that (transitively) depend on this one
+        let mut skipped = HashSet::new();
+        let mut frontier = vec![self.id];
+        while !frontier.is_empty() {
+            let dependents: Vec<Uuid> = job_dependencies::table
+                .inner_join(jobs::table.on(jobs::id.eq(job_dependencies::job_id)))
+                .filter(job_dependencies::depends_on_job_id.eq_any(&frontier))
+                .filter(jobs::status.eq(JobStatus::skipped()))
+                .filter(jobs::retried_job_id.is_null())
+                .select(jobs::id)
+                .load(conn)
+                .await?;
+            frontier = dependents
+                .into_iter()
+                .filter(|id| skipped.insert(*id))
+                .collect();
+        }
+        if skipped.is_empty() {
+            return Ok(Vec::new());
+        }
+        let skipped_ids: Vec<Uuid> = skipped.iter().copied().collect();
+
+        let dependencies: Vec<(Uuid, Uuid, JobStatus)> = job_dependencies::table
+            .inner_join(jobs::table.on(jobs::id.eq(job_dependencies::depends_on_job_id)))
+            .filter(job_dependencies::job_id.eq_any(&skipped_ids))
+            .select((
+                job_dependencies::job_id,
+                job_dependencies::depends_on_job_id,
+                jobs::status,
+            ))
+            .load(conn)
+            .await?;
+        let requeued: Vec<Uuid> = requeueable(self.id, skipped, &dependencies)
+            .into_iter()
+            .collect();
+
+        diesel::update(jobs::table)
+            .filter(jobs::id.eq_any(&requeued))
+            .filter(jobs::status.eq(JobStatus::skipped()))
+            .set((
+                jobs::status.eq(JobStatus::queued()),
+                jobs::finished_at.eq(None::<chrono::DateTime<chrono::Utc>>),
+            ))
+            .get_results(conn)
+            .await
+    }
+
     /// Retry a non-successful job by creating a new job with the same configuration
-    pub async fn retry(&self, conn: &mut AsyncPgConnection) -> Result<Self, diesel::result::Error> {
+    ///
+    /// The retry depends on the latest retries of the original job's dependencies.
+    /// Queued jobs that were waiting on the original job wait on the retry instead,
+    /// and the ones that were skipped because of it are queued again to do so.
+    /// Retries of unapproved jobs from forks await approval.
+    ///
+    /// Returns the retry and the jobs that were queued again.
+    pub async fn retry(
+        &self,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<(Self, Vec<Self>), diesel::result::Error> {
+        use crate::schema::{github_commit, jobs_github};
+
         // Only completed non-successful jobs can be retried
         if self.is_retryable() {
+            let trusted = jobs_github::table
+                .inner_join(github_commit::table.on(github_commit::id.eq(jobs_github::commit_id)))
+                .filter(jobs_github::job_id.eq(self.id))
+                .select(github_commit::trusted)
+                .first::<bool>(conn)
+                .await
+                .optional()?
+                .unwrap_or(true);
+
             // Create a new job with the same parameters but with a new ID, reset status, and link to original job
             let retried_job = diesel::insert_into(jobs::table)
                 .values((
                     jobs::id.eq(Uuid::now_v7()),
                     jobs::platform.eq(&self.platform),
-                    jobs::status.eq(JobStatus::queued()),
+                    jobs::status.eq(self.retry_status(trusted)),
                     jobs::cpus.eq(self.cpus),
                     jobs::memory_mb.eq(self.memory_mb),
+                    jobs::artifacts.eq(&self.artifacts),
+                    jobs::name.eq(&self.name),
+                    jobs::tasks.eq(&self.tasks),
+                    jobs::priority.eq(super::scheduler::PRIORITY_RETRY),
+                    jobs::required_labels.eq(&self.required_labels),
+                    jobs::secrets.eq(&self.secrets),
+                    jobs::trigger.eq(self.trigger),
+                    jobs::timeout_seconds.eq(self.timeout_seconds),
+                    jobs::approved_at.eq(self.approved_at),
                     jobs::previous_job_id.eq(self.id), // Set the previous_job_id to link to the original job
                 ))
                 .get_result::<Job>(conn)
                 .await?;
 
+            // Carry over dependencies, following each one to its latest retry
+            let mut needs = Vec::new();
+            for mut upstream_id in Self::get_dependencies(conn, self.id).await? {
+                while let Some(retried_id) = jobs::table
+                    .filter(jobs::id.eq(upstream_id))
+                    .select(jobs::retried_job_id)
+                    .first::<Option<Uuid>>(conn)
+                    .await?
+                {
+                    upstream_id = retried_id;
+                }
+                needs.push(upstream_id);
+            }
+            Self::add_dependencies(conn, retried_job.id, &needs).await?;
+
+            let requeued = self.requeue_skipped_dependents(conn).await?;
+            let queued_dependents = jobs::table
+                .filter(jobs::status.eq(JobStatus::queued()))
+                .select(jobs::id);
+            diesel::update(job_dependencies::table)
+                .filter(job_dependencies::depends_on_job_id.eq(self.id))
+                .filter(job_dependencies::job_id.eq_any(queued_dependents))
+                .set((
+                    job_dependencies::depends_on_job_id.eq(retried_job.id),
+                    job_dependencies::satisfied.eq(false),
+                ))
+                .execute(conn)
+                .await?;
+
             // Update the original job to link to the new job
             diesel::update(jobs::table)
                 .filter(jobs::id.eq(self.id))
@@ -356,7 +874,7 @@ impl Job {
                 .execute(conn)
                 .await?;
 
-            Ok(retried_job)
+            Ok((retried_job, requeued))
         } else {
             Err(diesel::result::Error::RollbackTransaction)
         }
@@ -397,7 +915,7 @@ impl Job {
         Ok((job, github_job, commit))
     }
 
-    /// Cancel a job if it's in a cancellable state (queued or running)
+    /// Cancel a job if it's in a cancellable state (queued, running or awaiting approval)
     /// Returns Ok(true) if cancelled, Ok(false) if not cancellable, Err on database error
     /// Uses SELECT FOR UPDATE to prevent race conditions
     pub async fn cancel(
@@ -414,7 +932,8 @@ impl Job {
                     let mut job: Self = jobs.filter(id.eq(job_id)).for_update().first(conn).await?;
 
                     match job.status.0 {
-                        devenv_runner::protocol::JobStatus::Queued => {
+                        devenv_runner::protocol::JobStatus::Queued
+                        | devenv_runner::protocol::JobStatus::AwaitingApproval => {
                             // Cancel the job
                             job.complete(
                                 conn,
@@ -439,12 +958,13 @@ impl Job {
             .await
     }
 
-    /// Check if a job can be cancelled (must be queued or running)
+    /// Check if a job can be cancelled (must be queued, running or awaiting approval)
     pub fn is_cancellable(&self) -> bool {
         matches!(
             self.status.0,
             devenv_runner::protocol::JobStatus::Queued
                 | devenv_runner::protocol::JobStatus::Running
+                | devenv_runner::protocol::JobStatus::AwaitingApproval
         )
     }
 
@@ -462,3 +982,357 @@ impl Job {
         format!("{}/{}", logger_base_url, self.id)
     }
 }
+
+/// A file collected from a job VM, stored in the artifact store
+#[derive(
+    Debug, Queryable, Selectable, Insertable, Deserialize, Serialize, ToSchema, Identifiable, Clone,
+)]
+#[diesel(table_name = job_artifacts)]
+pub struct JobArtifact {
+    pub id: uuid::Uuid,
+    pub job_id: uuid::Uuid,
+    /// Path of the file relative to the project directory
+    pub name: String,
+    pub size_bytes: i64,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl JobArtifact {
+    pub async fn create(
+        conn: &mut AsyncPgConnection,
+        artifact: Self,
+    ) -> Result<Self, diesel::result::Error> {
+        diesel::insert_into(job_artifacts::table)
+            .values(&artifact)
+            .get_result(conn)
+            .await
+    }
+
+    pub async fn get_by_id(
+        conn: &mut AsyncPgConnection,
+        job_id: Uuid,
+        id: Uuid,
+    ) -> Result<Self, diesel::result::Error> {
+        job_artifacts::table
+            .filter(job_artifacts::job_id.eq(job_id))
+            .filter(job_artifacts::id.eq(id))
+            .select(Self::as_select())
+            .first(conn)
+            .await
+    }
+
+    /// Get all artifacts of a job that haven't expired yet
+    pub async fn get_for_job(
+        conn: &mut AsyncPgConnection,
+        job_id: Uuid,
+    ) -> Result<Vec<Self>, diesel::result::Error> {
+        job_artifacts::table
+            .filter(job_artifacts::job_id.eq(job_id))
+            .filter(job_artifacts::expires_at.gt(chrono::Utc::now()))
+            .order_by(job_artifacts::name)
+            .select(Self::as_select())
+            .load(conn)
+            .await
+    }
+
+    /// Find artifacts past their retention period
+    pub async fn find_expired(
+        conn: &mut AsyncPgConnection,
+    ) -> Result<Vec<Self>, diesel::result::Error> {
+        job_artifacts::table
+            .filter(job_artifacts::expires_at.le(chrono::Utc::now()))
+            .select(Self::as_select())
+            .load(conn)
+            .await
+    }
+
+    pub async fn delete(
+        conn: &mut AsyncPgConnection,
+        id: Uuid,
+    ) -> Result<(), diesel::result::Error> {
+        diesel::delete(job_artifacts::table.filter(job_artifacts::id.eq(id)))
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+}
+
+/// A task of a job, as reported by the guest executing it
+#[derive(Debug, Queryable, Selectable, Deserialize, Serialize, ToSchema, Clone)]
+#[diesel(table_name = job_steps)]
+pub struct JobStep {
+    pub id: uuid::Uuid,
+    pub job_id: uuid::Uuid,
+    pub name: String,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    pub finished_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Whether the task succeeded, missing while it's running
+    pub success: Option<bool>,
+    /// How long the task took, as measured by the guest
+    pub duration_ms: Option<i64>,
+}
+
+impl JobStep {
+    /// Record the start of a task of a job executed by the runner
+    ///
+    /// Returns `false` if the job isn't executed by the runner.
+    pub async fn start(
+        conn: &mut AsyncPgConnection,
+        job_id: Uuid,
+        runner_id: Uuid,
+        name: &str,
+    ) -> Result<bool, diesel::result::Error> {
+        let rows = diesel::insert_into(job_steps::table)
+            .values(
+                jobs::table
+                    .filter(jobs::id.eq(job_id))
+                    .filter(jobs::runner_id.eq(runner_id))
+                    .select((
+                        Uuid::now_v7().into_sql::<diesel::sql_types::Uuid>(),
+                        jobs::id,
+                        name.into_sql::<diesel::sql_types::Text>(),
+                    )),
+            )
+            .into_columns((job_steps::id, job_steps::job_id, job_steps::name))
+            .execute(conn)
+            .await?;
+
+        Ok(rows == 1)
+    }
+
+    /// Record the outcome of a running task of a job executed by the runner
+    ///
+    /// Returns `false` if no such task is running.
+    pub async fn finish(
+        conn: &mut AsyncPgConnection,
+        job_id: Uuid,
+        runner_id: Uuid,
+        name: &str,
+        success: bool,
+        duration_ms: i64,
+    ) -> Result<bool, diesel::result::Error> {
+        let rows = diesel::update(job_steps::table)
+            .filter(job_steps::job_id.eq(job_id))
+            .filter(job_steps::name.eq(name))
+            .filter(job_steps::finished_at.is_null())
+            .filter(
+                job_steps::job_id.eq_any(
+                    jobs::table
+                        .filter(jobs::id.eq(job_id))
+                        .filter(jobs::runner_id.eq(runner_id))
+                        .select(jobs::id),
+                ),
+            )
+            .set((
+                job_steps::finished_at.eq(chrono::Utc::now()),
+                job_steps::success.eq(success),
+                job_steps::duration_ms.eq(duration_ms),
+            ))
+            .execute(conn)
+            .await?;
+
+        Ok(rows > 0)
+    }
+
+    /// Get the steps of jobs in the order they started, keyed by job id
+    pub async fn get_for_jobs(
+        conn: &mut AsyncPgConnection,
+        job_ids: &[Uuid],
+    ) -> Result<HashMap<Uuid, Vec<Self>>, diesel::result::Error> {
+        let mut steps: HashMap<Uuid, Vec<Self>> = HashMap::new();
+        for step in job_steps::table
+            .filter(job_steps::job_id.eq_any(job_ids))
+            .order_by(job_steps::id)
+            .select(Self::as_select())
+            .load::<Self>(conn)
+            .await?
+        {
+            steps.entry(step.job_id).or_default().push(step);
+        }
+
+        Ok(steps)
+    }
+}
+
+/// Object storage holding the contents of job artifacts
+///
+/// Artifacts are stored under `{prefix}/{job_id}/{artifact_id}`.
+#[derive(Clone)]
+pub struct ArtifactStore {
+    store: Arc<dyn ObjectStore>,
+    prefix: object_store::path::Path,
+}
+
+impl ArtifactStore {
+    /// Create a store from a URL such as `file:///var/lib/artifacts` or `s3://bucket/artifacts`
+    ///
+    /// Object store options (e.g. `aws_access_key_id`) are read from the environment.
+    pub fn new(url: &url::Url) -> Result<Self> {
+        let options = std::env::vars().map(|(key, value)| (key.to_ascii_lowercase(), value));
+        let (store, prefix) = object_store::parse_url_opts(url, options)?;
+        Ok(Self {
+            store: Arc::from(store),
+            prefix,
+        })
+    }
+
+    fn path(&self, artifact: &JobArtifact) -> object_store::path::Path {
+        self.prefix
+            .child(artifact.job_id.to_string())
+            .child(artifact.id.to_string())
+    }
+
+    /// Upload the contents of an artifact, returning its size in bytes
+    ///
+    /// The upload is aborted once it grows beyond `max_size_bytes`.
+    pub async fn put<S, E>(
+        &self,
+        artifact: &JobArtifact,
+        body: S,
+        max_size_bytes: u64,
+    ) -> Result<u64>
+    where
+        S: Stream<Item = Result<Bytes, E>>,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        let mut body = std::pin::pin!(body);
+        let upload = self.store.put_multipart(&self.path(artifact)).await?;
+        let mut writer = object_store::WriteMultipart::new(upload);
+        let mut size = 0u64;
+
+        while let Some(chunk) = body.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    writer.abort().await.ok();
+                    return Err(e.into());
+                }
+            };
+            size += chunk.len() as u64;
+            if size > max_size_bytes {
+                writer.abort().await.ok();
+                return Err(eyre::eyre!(
+                    "Artifact {} exceeds the maximum size of {} bytes",
+                    artifact.name,
+                    max_size_bytes
+                ));
+            }
+            writer.wait_for_capacity(8).await?;
+            writer.put(chunk);
+        }
+
+        writer.finish().await?;
+        Ok(size)
+    }
+
+    /// Stream the contents of an artifact
+    pub async fn get(
+        &self,
+        artifact: &JobArtifact,
+    ) -> Result<impl Stream<Item = Result<Bytes, object_store::Error>> + use<>> {
+        let result = self.store.get(&self.path(artifact)).await?;
+        Ok(result.into_stream())
+    }
+
+    pub async fn delete(&self, artifact: &JobArtifact) -> Result<()> {
+        match self.store.delete(&self.path(artifact)).await {
+            Ok(()) | Err(object_store::Error::NotFound { .. }) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// The skipped jobs that can be queued again once the job they were skipped for is retried
+///
+/// `dependencies` holds the dependencies of the skipped jobs, with the status of
+/// the job depended on. Jobs depending on a job that didn't succeed other than
+/// `retried_id` or another one queued again stay skipped, and so do the jobs
+/// depending on them.
+fn requeueable(
+    retried_id: Uuid,
+    mut skipped: HashSet<Uuid>,
+    dependencies: &[(Uuid, Uuid, JobStatus)],
+) -> HashSet<Uuid> {
+    loop {
+        let blocked: Vec<Uuid> = dependencies
+            .iter()
+            .filter(|(job_id, depends_on_job_id, status)| {
+                skipped.contains(job_id)
+                    && *depends_on_job_id != retried_id
+                    && !skipped.contains(depends_on_job_id)
+                    && matches!(
+                        &status.0,
+                        devenv_runner::protocol::JobStatus::Complete(completion)
+                            if *completion != devenv_runner::protocol::CompletionStatus::Success
+                    )
+            })
+            .map(|(job_id, _, _)| *job_id)
+            .collect();
+        if blocked.is_empty() {
+            return skipped;
+        }
+        for job_id in blocked {
+            skipped.remove(&job_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(trigger: Trigger, approved_at: Option<chrono::DateTime<chrono::Utc>>) -> Job {
+        Job {
+            status: JobStatus::cancelled(),
+            trigger,
+            approved_at,
+            ..Job::test_default()
+        }
+    }
+
+    #[test]
+    fn test_retry_status() {
+        use devenv_runner::protocol::JobStatus::{AwaitingApproval, Queued};
+
+        // A job from a fork cancelled while awaiting approval doesn't run when retried
+        let unapproved = job(Trigger::PullRequest, None);
+        assert_eq!(unapproved.retry_status(false).0, AwaitingApproval);
+        // unless it was approved before
+        let approved = job(Trigger::PullRequest, Some(chrono::Utc::now()));
+        assert_eq!(approved.retry_status(false).0, Queued);
+        // Jobs of the repository itself never await approval
+        assert_eq!(unapproved.retry_status(true).0, Queued);
+        assert_eq!(job(Trigger::Dispatch, None).retry_status(false).0, Queued);
+    }
+
+    #[test]
+    fn test_requeueable() {
+        use devenv_runner::protocol::CompletionStatus::{Failed, Skipped, Success};
+
+        let [build, test, deploy, lint, docs, check] = [1, 2, 3, 4, 5, 6].map(Uuid::from_u128);
+        let skipped = HashSet::from([test, deploy, docs]);
+        let dependencies = [
+            // test and deploy were skipped because build failed
+            (test, build, JobStatus::complete(Failed)),
+            (deploy, test, JobStatus::complete(Skipped)),
+            (deploy, lint, JobStatus::complete(Success)),
+            // docs also waits on check, which failed too
+            (docs, build, JobStatus::complete(Failed)),
+            (docs, check, JobStatus::complete(Failed)),
+        ];
+        // Retrying build runs test and deploy again, docs still can't run
+        assert_eq!(
+            requeueable(build, skipped.clone(), &dependencies),
+            HashSet::from([test, deploy])
+        );
+
+        // Without a way to run test, deploy stays skipped too
+        let dependencies = [
+            (test, build, JobStatus::complete(Failed)),
+            (test, check, JobStatus::complete(Failed)),
+            (deploy, test, JobStatus::complete(Skipped)),
+        ];
+        assert!(requeueable(build, HashSet::from([test, deploy]), &dependencies).is_empty());
+    }
+}
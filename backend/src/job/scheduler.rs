@@ -0,0 +1,454 @@
+//! Decides which queued job a runner gets next.
+//!
+//! Owners share the runners of a platform according to their weight: the owner
+//! running the fewest jobs relative to its `share_weight` goes first. Within an
+//! owner, jobs with a higher priority start first, then the oldest ones. Owners
+//! that reached their concurrency limit don't get any further jobs.
+//!
+//! Jobs are offered to all runners, so the limit is enforced again when a
+//! runner claims one, see [`claim`].
+
+use crate::schema::{github_commit, github_owner, github_repo, jobs, jobs_github};
+use diesel::prelude::*;
+use diesel_async::AsyncPgConnection;
+use diesel_async::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use super::model::{Job, JobStatus, Platform};
+
+/// Priority of jobs created for pull requests and pushes to other branches
+pub const PRIORITY_DEFAULT: i32 = 0;
+/// Priority of jobs created for pushes to the default branch
+pub const PRIORITY_DEFAULT_BRANCH: i32 = 10;
+/// Priority of jobs retried or dispatched by a user
+pub const PRIORITY_RETRY: i32 = 20;
+
+/// Why a queued job hasn't started yet
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WaitReason {
+    /// Jobs it needs haven't succeeded yet
+    Dependencies,
+    /// The owner is already running as many jobs as it may
+    ConcurrencyLimit,
+    /// Waiting for a runner of the platform to become available
+    Runner,
+}
+
+/// Where a queued job stands
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
+pub struct QueueStatus {
+    /// Position among the jobs of the platform that can start, starting at 1
+    pub position: Option<usize>,
+    pub reason: WaitReason,
+}
+
+/// A queued job that can start as soon as its owner's share allows
+#[derive(Debug, Clone)]
+struct Candidate {
+    job_id: Uuid,
+    owner_id: i64,
+    priority: i32,
+}
+
+/// Scheduling state of an owner
+#[derive(Debug, Clone)]
+struct Share {
+    running: i64,
+    limit: Option<i64>,
+    weight: i64,
+}
+
+impl Share {
+    fn at_limit(&self) -> bool {
+        self.limit.is_some_and(|limit| self.running >= limit)
+    }
+}
+
+/// Order candidates in the sequence they would be started
+///
+/// Candidates of owners that would exceed their limit are left out.
+fn order(candidates: Vec<Candidate>, mut shares: HashMap<i64, Share>) -> Vec<Uuid> {
+    let mut by_owner: BTreeMap<i64, Vec<Candidate>> = BTreeMap::new();
+    for candidate in candidates {
+        by_owner
+            .entry(candidate.owner_id)
+            .or_default()
+            .push(candidate);
+    }
+    // Uuid v7 ids sort by creation time
+    let mut queues: BTreeMap<i64, VecDeque<Candidate>> = by_owner
+        .into_iter()
+        .map(|(owner_id, mut jobs)| {
+            jobs.sort_by(|a, b| b.priority.cmp(&a.priority).then(a.job_id.cmp(&b.job_id)));
+            (owner_id, jobs.into())
+        })
+        .collect();
+
+    let mut ordered = Vec::new();
+    loop {
+        let next_owner = queues
+            .iter()
+            .filter(|(owner_id, _)| !shares[*owner_id].at_limit())
+            .min_by(|(a_id, a_jobs), (b_id, b_jobs)| {
+                let (a, b) = (&shares[*a_id], &shares[*b_id]);
+                // Compare running / weight without dividing
+                (a.running * b.weight)
+                    .cmp(&(b.running * a.weight))
+                    .then(b_jobs[0].priority.cmp(&a_jobs[0].priority))
+                    .then(a_jobs[0].job_id.cmp(&b_jobs[0].job_id))
+            })
+            .map(|(owner_id, _)| *owner_id);
+
+        let Some(owner_id) = next_owner else {
+            break;
+        };
+
+        let queue = queues.get_mut(&owner_id).expect("owner has queued jobs");
+        let candidate = queue.pop_front().expect("owner queues are never empty");
+        if queue.is_empty() {
+            queues.remove(&owner_id);
+        }
+        shares
+            .get_mut(&owner_id)
+            .expect("owner has a share")
+            .running += 1;
+        ordered.push(candidate.job_id);
+    }
+    ordered
+}
+
+/// Load the queued jobs of a platform whose dependencies succeeded
+async fn load_candidates(
+    conn: &mut AsyncPgConnection,
+    platform: &Platform,
+) -> Result<Vec<Candidate>, diesel::result::Error> {
+    let rows: Vec<(Uuid, i64, i32)> = jobs::table
+        .inner_join(jobs_github::table.on(jobs_github::job_id.eq(jobs::id)))
+        .inner_join(github_commit::table.on(github_commit::id.eq(jobs_github::commit_id)))
+        .inner_join(github_repo::table.on(github_repo::id.eq(github_commit::repo_id)))
+        .filter(jobs::status.eq(JobStatus::queued()))
+        .filter(jobs::runner_id.is_null())
+        .filter(jobs::platform.eq(platform))
+        .filter(diesel::dsl::not(jobs::id.eq_any(Job::blocked_job_ids())))
+        .select((jobs::id, github_repo::owner_id, jobs::priority))
+        .load(conn)
+        .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(job_id, owner_id, priority)| Candidate {
+            job_id,
+            owner_id,
+            priority,
+        })
+        .collect())
+}
+
+/// Load the first queued job of each owner on a platform, among those whose
+/// dependencies succeeded and whose required labels are all among `labels`
+///
+/// The next job to start is always the first one of some owner.
+async fn load_heads(
+    conn: &mut AsyncPgConnection,
+    platform: &Platform,
+    labels: &[String],
+) -> Result<Vec<Candidate>, diesel::result::Error> {
+    let rows: Vec<(Uuid, i64, i32)> = jobs::table
+        .inner_join(jobs_github::table.on(jobs_github::job_id.eq(jobs::id)))
+        .inner_join(github_commit::table.on(github_commit::id.eq(jobs_github::commit_id)))
+        .inner_join(github_repo::table.on(github_repo::id.eq(github_commit::repo_id)))
+        .filter(jobs::status.eq(JobStatus::queued()))
+        .filter(jobs::runner_id.is_null())
+        .filter(jobs::platform.eq(platform))
+        .filter(jobs::required_labels.is_contained_by(labels))
+        .filter(diesel::dsl::not(jobs::id.eq_any(Job::blocked_job_ids())))
+        .distinct_on(github_repo::owner_id)
+        .order_by((github_repo::owner_id, jobs::priority.desc(), jobs::id))
+        .select((jobs::id, github_repo::owner_id, jobs::priority))
+        .load(conn)
+        .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(job_id, owner_id, priority)| Candidate {
+            job_id,
+            owner_id,
+            priority,
+        })
+        .collect())
+}
+
+/// Load the scheduling state of the given owners
+async fn load_shares(
+    conn: &mut AsyncPgConnection,
+    owner_ids: &[i64],
+    default_limit: Option<u32>,
+) -> Result<HashMap<i64, Share>, diesel::result::Error> {
+    let running: HashMap<i64, i64> = jobs::table
+        .inner_join(jobs_github::table.on(jobs_github::job_id.eq(jobs::id)))
+        .inner_join(github_commit::table.on(github_commit::id.eq(jobs_github::commit_id)))
+        .inner_join(github_repo::table.on(github_repo::id.eq(github_commit::repo_id)))
+        .filter(jobs::status.eq(JobStatus::running()))
+        .filter(github_repo::owner_id.eq_any(owner_ids))
+        .group_by(github_repo::owner_id)
+        .select((github_repo::owner_id, diesel::dsl::count_star()))
+        .load::<(i64, i64)>(conn)
+        .await?
+        .into_iter()
+        .collect();
+
+    let owners: Vec<(i64, Option<i32>, i32)> = github_owner::table
+        .filter(github_owner::id.eq_any(owner_ids))
+        .select((
+            github_owner::id,
+            github_owner::max_concurrent_jobs,
+            github_owner::share_weight,
+        ))
+        .load(conn)
+        .await?;
+
+    Ok(owners
+        .into_iter()
+        .map(|(owner_id, limit, weight)| {
+            let share = Share {
+                running: running.get(&owner_id).copied().unwrap_or(0),
+                limit: limit.map(i64::from).or(default_limit.map(i64::from)),
+                weight: i64::from(weight.max(1)),
+            };
+            (owner_id, share)
+        })
+        .collect())
+}
+
+/// Load the queued jobs of a platform in the order they would be started
+async fn queue_for_platform(
+    conn: &mut AsyncPgConnection,
+    platform: &Platform,
+    default_limit: Option<u32>,
+) -> Result<Vec<Uuid>, diesel::result::Error> {
+    let candidates = load_candidates(conn, platform).await?;
+    let mut owner_ids: Vec<i64> = candidates.iter().map(|c| c.owner_id).collect();
+    owner_ids.sort_unstable();
+    owner_ids.dedup();
+    let shares = load_shares(conn, &owner_ids, default_limit).await?;
+
+    // Jobs of owners that are gone can't be scheduled
+    let candidates = candidates
+        .into_iter()
+        .filter(|c| shares.contains_key(&c.owner_id))
+        .collect();
+    Ok(order(candidates, shares))
+}
+
+/// Pick the job a runner of the given platform and labels should run next
+pub async fn next_job(
+    conn: &mut AsyncPgConnection,
+    platform: &Platform,
+    labels: &[String],
+    default_limit: Option<u32>,
+) -> Result<Option<Job>, diesel::result::Error> {
+    let candidates = load_heads(conn, platform, labels).await?;
+    let mut owner_ids: Vec<i64> = candidates.iter().map(|c| c.owner_id).collect();
+    owner_ids.sort_unstable();
+    let shares = load_shares(conn, &owner_ids, default_limit).await?;
+
+    let candidates = candidates
+        .into_iter()
+        .filter(|c| shares.contains_key(&c.owner_id))
+        .collect();
+    let Some(job_id) = order(candidates, shares).into_iter().next() else {
+        return Ok(None);
+    };
+    Job::get_by_id(conn, job_id).await.map(Some)
+}
+
+/// Let a runner claim a job, unless another runner did or its owner reached its limit
+///
+/// Claims of a job another runner is claiming skip it rather than wait. The
+/// owner's row stays locked until the job is claimed, so concurrent claims of
+/// the owner's jobs can't all pass its limit. Returns whether the runner got
+/// the job.
+pub async fn claim(
+    conn: &mut AsyncPgConnection,
+    job_id: Uuid,
+    runner_id: Uuid,
+    labels: &[String],
+    lease_expires_at: chrono::DateTime<chrono::Utc>,
+    default_limit: Option<u32>,
+) -> Result<bool, diesel::result::Error> {
+    conn.build_transaction()
+        .run::<_, diesel::result::Error, _>(|conn| {
+            Box::pin(async move {
+                let owner_id: Option<i64> = jobs_github::table
+                    .inner_join(
+                        github_commit::table.on(github_commit::id.eq(jobs_github::commit_id)),
+                    )
+                    .inner_join(github_repo::table.on(github_repo::id.eq(github_commit::repo_id)))
+                    .filter(jobs_github::job_id.eq(job_id))
+                    .select(github_repo::owner_id)
+                    .first(conn)
+                    .await
+                    .optional()?;
+                let Some(owner_id) = owner_id else {
+                    return Ok(false);
+                };
+                let claimable = jobs::table
+                    .find(job_id)
+                    .filter(jobs::status.eq(JobStatus::queued()))
+                    .select(jobs::id)
+                    .for_update()
+                    .skip_locked()
+                    .first::<Uuid>(conn)
+                    .await
+                    .optional()?;
+                if claimable.is_none() {
+                    return Ok(false);
+                }
+
+                github_owner::table
+                    .find(owner_id)
+                    .select(github_owner::id)
+                    .for_update()
+                    .first::<i64>(conn)
+                    .await?;
+
+                let shares = load_shares(conn, &[owner_id], default_limit).await?;
+                if shares.get(&owner_id).is_some_and(Share::at_limit) {
+                    tracing::info!(
+                        "Job {} has to wait for its owner's concurrency limit",
+                        job_id
+                    );
+                    return Ok(false);
+                }
+
+                let rows =
+                    Job::claim_job_for_runner(conn, job_id, runner_id, labels, lease_expires_at)
+                        .await?;
+                Ok(rows == 1)
+            })
+        })
+        .await
+}
+
+/// Explain why a job is still queued, `None` if it isn't queued
+pub async fn queue_status(
+    conn: &mut AsyncPgConnection,
+    job: &Job,
+    default_limit: Option<u32>,
+) -> Result<Option<QueueStatus>, diesel::result::Error> {
+    if job.status.0 != devenv_runner::protocol::JobStatus::Queued {
+        return Ok(None);
+    }
+
+    let blocked = jobs::table
+        .filter(jobs::id.eq(job.id))
+        .filter(jobs::id.eq_any(Job::blocked_job_ids()))
+        .count()
+        .get_result::<i64>(conn)
+        .await?
+        > 0;
+    if blocked {
+        return Ok(Some(QueueStatus {
+            position: None,
+            reason: WaitReason::Dependencies,
+        }));
+    }
+
+    // Position among all jobs of the platform, whichever runners they need
+    let queue = queue_for_platform(conn, &job.platform, default_limit).await?;
+    let status = match queue.iter().position(|id| *id == job.id) {
+        Some(index) => QueueStatus {
+            position: Some(index + 1),
+            reason: WaitReason::Runner,
+        },
+        None => QueueStatus {
+            position: None,
+            reason: WaitReason::ConcurrencyLimit,
+        },
+    };
+    Ok(Some(status))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(n: u128, owner_id: i64, priority: i32) -> Candidate {
+        Candidate {
+            job_id: Uuid::from_u128(n),
+            owner_id,
+            priority,
+        }
+    }
+
+    fn share(running: i64, limit: Option<i64>, weight: i64) -> Share {
+        Share {
+            running,
+            limit,
+            weight,
+        }
+    }
+
+    #[test]
+    fn test_order_alternates_between_owners() {
+        let candidates = vec![
+            candidate(1, 1, PRIORITY_DEFAULT),
+            candidate(2, 1, PRIORITY_DEFAULT),
+            candidate(3, 1, PRIORITY_DEFAULT),
+            candidate(4, 2, PRIORITY_DEFAULT),
+        ];
+        let shares = HashMap::from([(1, share(0, None, 1)), (2, share(0, None, 1))]);
+
+        let ordered = order(candidates, shares);
+        assert_eq!(
+            ordered,
+            [1, 4, 2, 3].map(Uuid::from_u128),
+            "a busy owner must not starve others"
+        );
+    }
+
+    #[test]
+    fn test_order_respects_weights_and_running_jobs() {
+        let candidates = vec![
+            candidate(1, 1, PRIORITY_DEFAULT),
+            candidate(2, 1, PRIORITY_DEFAULT),
+            candidate(3, 2, PRIORITY_DEFAULT),
+            candidate(4, 2, PRIORITY_DEFAULT),
+        ];
+        // Both run a job, but owner 2 has twice the share
+        let shares = HashMap::from([(1, share(1, None, 1)), (2, share(1, None, 2))]);
+
+        let ordered = order(candidates, shares);
+        assert_eq!(ordered, [3, 1, 4, 2].map(Uuid::from_u128));
+    }
+
+    #[test]
+    fn test_order_prefers_priority_within_owner() {
+        let candidates = vec![
+            candidate(1, 1, PRIORITY_DEFAULT),
+            candidate(2, 1, PRIORITY_RETRY),
+            candidate(3, 1, PRIORITY_DEFAULT_BRANCH),
+        ];
+        let shares = HashMap::from([(1, share(0, None, 1))]);
+
+        let ordered = order(candidates, shares);
+        assert_eq!(ordered, [2, 3, 1].map(Uuid::from_u128));
+    }
+
+    #[test]
+    fn test_order_applies_limits() {
+        let candidates = vec![
+            candidate(1, 1, PRIORITY_DEFAULT),
+            candidate(2, 1, PRIORITY_DEFAULT),
+            candidate(3, 2, PRIORITY_DEFAULT),
+        ];
+        let shares = HashMap::from([(1, share(1, Some(2), 1)), (2, share(0, Some(0), 1))]);
+
+        let ordered = order(candidates, shares);
+        assert_eq!(ordered, [1].map(Uuid::from_u128));
+    }
+}
@@ -0,0 +1,194 @@
+//! Searching jobs across repositories
+//!
+//! Jobs are paginated by keyset on their UUIDv7 ids, newest first, so pages
+//! stay stable while new jobs are created.
+
+use super::model::{Job, JobStatus};
+use crate::github::model::{GitHubCommit, JobGitHub};
+use crate::schema::{github_commit, github_owner, github_repo, jobs, jobs_github};
+use chrono::{DateTime, Utc};
+use devenv_runner::protocol::CompletionStatus;
+use diesel::dsl::{InnerJoinOn, IntoBoxed};
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use serde::Deserialize;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+/// Jobs per page unless requested otherwise
+pub const DEFAULT_LIMIT: i64 = 50;
+/// Most jobs returned per page
+pub const MAX_LIMIT: i64 = 200;
+
+/// Status of the jobs to search for
+#[derive(Debug, Clone, Copy, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum StatusFilter {
+    Queued,
+    Running,
+    Failed,
+    Success,
+    Cancelled,
+    TimedOut,
+    Skipped,
+    InfrastructureFailure,
+    AwaitingApproval,
+}
+
+impl From<StatusFilter> for JobStatus {
+    fn from(status: StatusFilter) -> Self {
+        match status {
+            StatusFilter::Queued => JobStatus::queued(),
+            StatusFilter::Running => JobStatus::running(),
+            StatusFilter::Failed => JobStatus::failed(),
+            StatusFilter::Success => JobStatus::success(),
+            StatusFilter::Cancelled => JobStatus::cancelled(),
+            StatusFilter::TimedOut => JobStatus::timed_out(),
+            StatusFilter::Skipped => JobStatus::complete(CompletionStatus::Skipped),
+            StatusFilter::InfrastructureFailure => JobStatus::infrastructure_failure(),
+            StatusFilter::AwaitingApproval => JobStatus::awaiting_approval(),
+        }
+    }
+}
+
+/// Filters of a job search, jobs have to match all given ones
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct JobFilter {
+    /// Login of the repository owner
+    pub owner: Option<String>,
+    /// Name of the repository
+    pub repo: Option<String>,
+    /// Branch of the commit
+    pub branch: Option<String>,
+    /// Revision hash of the commit
+    pub rev: Option<String>,
+    pub status: Option<StatusFilter>,
+    /// Platform of the job, e.g. `x86_64-linux`
+    pub platform: Option<String>,
+    /// Only jobs created at or after this time
+    pub since: Option<DateTime<Utc>>,
+    /// Only jobs created before this time
+    pub until: Option<DateTime<Utc>>,
+    /// Id of the last job of the previous page
+    pub cursor: Option<Uuid>,
+    /// Maximum number of jobs per page
+    pub limit: Option<i64>,
+}
+
+/// Position in a list of jobs
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct Page {
+    /// Id of the last job of the previous page
+    pub cursor: Option<Uuid>,
+    /// Maximum number of jobs per page
+    pub limit: Option<i64>,
+}
+
+/// A page of jobs matching a search
+pub struct SearchResult {
+    pub jobs: Vec<(Job, JobGitHub, GitHubCommit)>,
+    /// Number of jobs matching the filters across all pages
+    pub total: i64,
+    /// Cursor of the next page, `None` on the last page
+    pub next_cursor: Option<Uuid>,
+}
+
+type JobsWithCommits = InnerJoinOn<
+    InnerJoinOn<
+        InnerJoinOn<
+            InnerJoinOn<
+                jobs::table,
+                jobs_github::table,
+                diesel::dsl::Eq<jobs_github::job_id, jobs::id>,
+            >,
+            github_commit::table,
+            diesel::dsl::Eq<github_commit::id, jobs_github::commit_id>,
+        >,
+        github_repo::table,
+        diesel::dsl::Eq<github_repo::id, github_commit::repo_id>,
+    >,
+    github_owner::table,
+    diesel::dsl::Eq<github_owner::id, github_repo::owner_id>,
+>;
+
+/// Jobs joined with their commit, repository and owner, narrowed down by the filter
+fn filtered(filter: &JobFilter) -> IntoBoxed<'static, JobsWithCommits, Pg> {
+    let mut query = jobs::table
+        .inner_join(jobs_github::table.on(jobs_github::job_id.eq(jobs::id)))
+        .inner_join(github_commit::table.on(github_commit::id.eq(jobs_github::commit_id)))
+        .inner_join(github_repo::table.on(github_repo::id.eq(github_commit::repo_id)))
+        .inner_join(github_owner::table.on(github_owner::id.eq(github_repo::owner_id)))
+        .into_boxed();
+
+    if let Some(owner) = &filter.owner {
+        query = query.filter(github_owner::login.eq(owner.clone()));
+    }
+    if let Some(repo) = &filter.repo {
+        query = query.filter(github_repo::name.eq(repo.clone()));
+    }
+    if let Some(branch) = &filter.branch {
+        query = query.filter(github_commit::git_ref.eq(branch.clone()));
+    }
+    if let Some(rev) = &filter.rev {
+        query = query.filter(github_commit::rev.eq(rev.clone()));
+    }
+    if let Some(status) = filter.status {
+        query = query.filter(jobs::status.eq(JobStatus::from(status)));
+    }
+    if let Some(platform) = &filter.platform {
+        query = query.filter(jobs::platform.eq(platform.clone()));
+    }
+    if let Some(since) = filter.since {
+        query = query.filter(jobs::created_at.ge(since));
+    }
+    if let Some(until) = filter.until {
+        query = query.filter(jobs::created_at.lt(until));
+    }
+
+    query
+}
+
+/// Get a page of the jobs matching the filter, newest first
+pub async fn search(
+    conn: &mut AsyncPgConnection,
+    filter: &JobFilter,
+) -> Result<SearchResult, diesel::result::Error> {
+    let limit = filter.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let total = filtered(filter)
+        .select(diesel::dsl::count_star())
+        .first::<i64>(conn)
+        .await?;
+
+    let mut query = filtered(filter);
+    if let Some(cursor) = filter.cursor {
+        query = query.filter(jobs::id.lt(cursor));
+    }
+    // One more job than requested tells whether there's a next page
+    let mut page = query
+        .select((
+            Job::as_select(),
+            JobGitHub::as_select(),
+            GitHubCommit::as_select(),
+        ))
+        .order_by(jobs::id.desc())
+        .limit(limit + 1)
+        .load::<(Job, JobGitHub, GitHubCommit)>(conn)
+        .await?;
+
+    let next_cursor = if page.len() as i64 > limit {
+        page.truncate(limit as usize);
+        page.last().map(|(job, _, _)| job.id)
+    } else {
+        None
+    };
+
+    Ok(SearchResult {
+        jobs: page,
+        total,
+        next_cursor,
+    })
+}
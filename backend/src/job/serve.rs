@@ -1,20 +1,22 @@
-use crate::auth::BetaUser;
+use crate::auth::{BetaUser, RunnerAuth};
 use crate::config::AppState;
 use crate::error::Result;
 use crate::github::model::SourceControlIntegration;
 use axum::{
     Json,
-    extract::{Path, State},
-    http::StatusCode,
-    response::IntoResponse,
+    body::Body,
+    extract::{Path, Query, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
 };
 use utoipa_axum::{router::OpenApiRouter, routes};
 
 use super::model;
 
 use devenv_runner::protocol::CompletionStatus;
+use diesel::OptionalExtension;
 use serde::{Deserialize, Serialize};
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
 
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct JobResponse {
@@ -23,6 +25,93 @@ pub struct JobResponse {
     pub commit: crate::github::model::GitHubCommit,
     #[schema(format = "uri")]
     pub log_url: String,
+    /// Why the job is still queued, only included when fetching a single job
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub queue: Option<super::scheduler::QueueStatus>,
+    /// Tasks the guest executed so far, in the order they started
+    #[serde(default)]
+    pub steps: Vec<model::JobStep>,
+}
+
+impl JobResponse {
+    pub fn new(
+        job: model::Job,
+        github: crate::github::model::JobGitHub,
+        commit: crate::github::model::GitHubCommit,
+        steps: Vec<model::JobStep>,
+        logger_url: &str,
+    ) -> Self {
+        let log_url = job.log_url(logger_url);
+        Self {
+            job,
+            github,
+            commit,
+            log_url,
+            queue: None,
+            steps,
+        }
+    }
+}
+
+/// A page of jobs
+#[derive(Debug, Serialize, ToSchema)]
+pub struct JobPage {
+    pub jobs: Vec<JobResponse>,
+    /// Number of jobs matching the filters across all pages
+    pub total: i64,
+    /// Pass as `cursor` to get the next page, missing on the last page
+    pub next_cursor: Option<uuid::Uuid>,
+}
+
+impl JobPage {
+    /// Build the page of a search result, loading the steps of its jobs
+    pub async fn load(
+        conn: &mut diesel_async::AsyncPgConnection,
+        result: super::search::SearchResult,
+        logger_url: &str,
+    ) -> Result<Self, diesel::result::Error> {
+        let job_ids: Vec<uuid::Uuid> = result.jobs.iter().map(|(job, _, _)| job.id).collect();
+        let mut steps = model::JobStep::get_for_jobs(conn, &job_ids).await?;
+
+        Ok(Self {
+            jobs: result
+                .jobs
+                .into_iter()
+                .map(|(job, github, commit)| {
+                    let steps = steps.remove(&job.id).unwrap_or_default();
+                    JobResponse::new(job, github, commit, steps, logger_url)
+                })
+                .collect(),
+            total: result.total,
+            next_cursor: result.next_cursor,
+        })
+    }
+}
+
+/// Search jobs
+///
+/// Returns the jobs matching all filters, newest first
+#[utoipa::path(
+    get,
+    path = "/",
+    responses(
+        (status = 200, description = "Jobs found", body = JobPage),
+        (status = 400, description = "Invalid filter")
+    ),
+    params(super::search::JobFilter)
+)]
+#[tracing::instrument(skip_all)]
+async fn list_jobs(
+    _user: BetaUser,
+    State(app_state): State<AppState>,
+    Query(filter): Query<super::search::JobFilter>,
+) -> Result<Json<JobPage>> {
+    let conn = &mut app_state.pool.get().await?;
+    let result = super::search::search(conn, &filter).await?;
+
+    Ok(Json(
+        JobPage::load(conn, result, &app_state.config.logger_url).await?,
+    ))
 }
 
 /// Get details for a specific job
@@ -52,24 +141,35 @@ async fn get_job(
     // Generate a log URL for this job - using the logger service
     let log_url = job.log_url(&app_state.config.logger_url);
 
+    let queue =
+        super::scheduler::queue_status(conn, &job, app_state.config.job.max_concurrent_per_owner)
+            .await?;
+
+    let steps = model::JobStep::get_for_jobs(conn, &[id])
+        .await?
+        .remove(&id)
+        .unwrap_or_default();
+
     Ok(Json(JobResponse {
         job,
         github,
         commit,
         log_url,
+        queue,
+        steps,
     }))
 }
 
 /// Cancel a job
 ///
-/// Cancels a job that is currently in progress or queued
+/// Cancels a job that is currently in progress, queued or awaiting approval
 #[utoipa::path(
     post,
     path = "/{id}/cancel",
     responses(
         (status = 200, description = "Job cancelled successfully"),
         (status = 404, description = "Job not found"),
-        (status = 400, description = "Job cannot be cancelled (neither queued nor in progress)")
+        (status = 400, description = "Job cannot be cancelled (neither queued, in progress nor awaiting approval)")
     ),
     params(
         ("id" = uuid::Uuid, Path, description = "The unique identifier of the job"),
@@ -173,7 +273,7 @@ async fn retry_job(
     }
 
     // Create the retry job in a transaction
-    let retried_job = conn
+    let (retried_job, requeued) = conn
         .build_transaction()
         .run::<_, diesel::result::Error, _>(|conn| Box::pin(async move { job.retry(conn).await }))
         .await
@@ -188,22 +288,244 @@ async fn retry_job(
     )
     .await
     .map_err(|e| color_eyre::eyre::eyre!("Job created but GitHub check run failed: {}", e))?;
+    for job in &requeued {
+        crate::github::model::JobGitHub::recreate_check_run(&app_state, conn, job).await;
+    }
 
     // Generate log URL and notify runners
     let log_url = retried_job.log_url(&app_state.config.logger_url);
     crate::runner::serve::notify_runners_about_job(&app_state, &retried_job).await;
 
+    let queue = super::scheduler::queue_status(
+        conn,
+        &retried_job,
+        app_state.config.job.max_concurrent_per_owner,
+    )
+    .await?;
+
     Ok(Json(JobResponse {
         job: retried_job,
         github: job_github,
         commit,
         log_url,
+        queue,
+        steps: Vec::new(),
     }))
 }
 
+#[derive(Debug, Deserialize, IntoParams)]
+struct ArtifactUpload {
+    /// Path of the file relative to the project directory
+    name: String,
+}
+
+/// Upload an artifact
+///
+/// Called by the runner for every file the job collected
+#[utoipa::path(
+    put,
+    path = "/{id}/artifacts",
+    request_body(content = Vec<u8>, content_type = "application/octet-stream"),
+    responses(
+        (status = 200, description = "Artifact stored", body = model::JobArtifact),
+        (status = 401, description = "Missing or invalid runner token"),
+        (status = 403, description = "Job is not run by this runner"),
+        (status = 404, description = "Job not found"),
+        (status = 400, description = "Job is not running or the name is invalid")
+    ),
+    params(
+        ("id" = uuid::Uuid, Path, description = "The unique identifier of the job"),
+        ArtifactUpload,
+    )
+)]
+#[tracing::instrument(skip_all)]
+async fn upload_artifact(
+    State(app_state): State<AppState>,
+    Path(id): Path<uuid::Uuid>,
+    Query(upload): Query<ArtifactUpload>,
+    RunnerAuth(token): RunnerAuth,
+    body: Body,
+) -> Result<Response> {
+    let conn = &mut app_state.pool.get().await?;
+
+    let job = match model::Job::get_by_id(conn, id).await {
+        Ok(job) => job,
+        Err(diesel::result::Error::NotFound) => return Ok(StatusCode::NOT_FOUND.into_response()),
+        Err(e) => return Err(e.into()),
+    };
+    // Only the runner executing the job may upload its artifacts
+    let runner = match job.runner_id {
+        Some(runner_id) => crate::runner::model::Runner::get_by_id(conn, runner_id)
+            .await
+            .optional()?,
+        None => None,
+    };
+    if runner.and_then(|runner| runner.token_id) != Some(token.id) {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+    if job.status.0 != devenv_runner::protocol::JobStatus::Running {
+        return Ok(StatusCode::BAD_REQUEST.into_response());
+    }
+
+    let name = upload.name.trim_start_matches("./");
+    if name.is_empty() || name.starts_with('/') || name.split('/').any(|part| part == "..") {
+        return Ok(StatusCode::BAD_REQUEST.into_response());
+    }
+
+    let now = chrono::Utc::now();
+    let mut artifact = model::JobArtifact {
+        id: uuid::Uuid::now_v7(),
+        job_id: id,
+        name: name.to_string(),
+        size_bytes: 0,
+        created_at: now,
+        expires_at: now + chrono::Duration::days(app_state.config.artifacts.retention_days as i64),
+    };
+
+    let size = app_state
+        .artifact_store
+        .put(
+            &artifact,
+            body.into_data_stream(),
+            app_state.config.artifacts.max_size_mb * 1024 * 1024,
+        )
+        .await?;
+    artifact.size_bytes = size as i64;
+
+    let artifact = model::JobArtifact::create(conn, artifact).await?;
+    tracing::info!(
+        "Stored artifact {} ({} bytes) for job {}",
+        artifact.name,
+        artifact.size_bytes,
+        id
+    );
+
+    Ok(Json(artifact).into_response())
+}
+
+/// List the artifacts of a job
+#[utoipa::path(
+    get,
+    path = "/{id}/artifacts",
+    responses(
+        (status = 200, description = "Artifacts of the job", body = Vec<model::JobArtifact>),
+    ),
+    params(
+        ("id" = uuid::Uuid, Path, description = "The unique identifier of the job"),
+    )
+)]
+async fn get_artifacts(
+    _user: BetaUser,
+    State(app_state): State<AppState>,
+    Path(id): Path<uuid::Uuid>,
+) -> Result<Json<Vec<model::JobArtifact>>> {
+    let conn = &mut app_state.pool.get().await?;
+    let artifacts = model::JobArtifact::get_for_job(conn, id).await?;
+    Ok(Json(artifacts))
+}
+
+/// Download an artifact
+#[utoipa::path(
+    get,
+    path = "/{id}/artifacts/{artifact_id}",
+    responses(
+        (status = 200, description = "Artifact contents", body = Vec<u8>, content_type = "application/octet-stream"),
+        (status = 404, description = "Artifact not found or expired")
+    ),
+    params(
+        ("id" = uuid::Uuid, Path, description = "The unique identifier of the job"),
+        ("artifact_id" = uuid::Uuid, Path, description = "The unique identifier of the artifact"),
+    )
+)]
+async fn download_artifact(
+    _user: BetaUser,
+    State(app_state): State<AppState>,
+    Path((id, artifact_id)): Path<(uuid::Uuid, uuid::Uuid)>,
+) -> Result<Response> {
+    let conn = &mut app_state.pool.get().await?;
+
+    let artifact = match model::JobArtifact::get_by_id(conn, id, artifact_id).await {
+        Ok(artifact) if artifact.expires_at > chrono::Utc::now() => artifact,
+        Ok(_) | Err(diesel::result::Error::NotFound) => {
+            return Ok(StatusCode::NOT_FOUND.into_response());
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let stream = app_state.artifact_store.get(&artifact).await?;
+    let file_name = artifact
+        .name
+        .rsplit('/')
+        .next()
+        .unwrap_or(&artifact.name)
+        .replace('"', "");
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+            (header::CONTENT_LENGTH, artifact.size_bytes.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{file_name}\""),
+            ),
+        ],
+        Body::from_stream(stream),
+    )
+        .into_response())
+}
+
+// Task that periodically deletes artifacts past their retention period
+async fn artifact_cleanup(app_state: AppState) {
+    let interval = tokio::time::Duration::from_secs(3600); // Check every hour
+    let mut interval_timer = tokio::time::interval(interval);
+
+    loop {
+        interval_timer.tick().await;
+
+        let conn = &mut match app_state.pool.get().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                tracing::error!(
+                    "Failed to get database connection for artifact cleanup: {}",
+                    e
+                );
+                continue;
+            }
+        };
+
+        let expired = match model::JobArtifact::find_expired(conn).await {
+            Ok(expired) => expired,
+            Err(e) => {
+                tracing::error!("Failed to query expired artifacts: {}", e);
+                continue;
+            }
+        };
+
+        for artifact in expired {
+            if let Err(e) = app_state.artifact_store.delete(&artifact).await {
+                tracing::error!("Failed to delete artifact {}: {}", artifact.id, e);
+                continue;
+            }
+            if let Err(e) = model::JobArtifact::delete(conn, artifact.id).await {
+                tracing::error!("Failed to delete artifact record {}: {}", artifact.id, e);
+            }
+        }
+    }
+}
+
+// Start the artifact cleanup task with the AppState
+pub fn start_artifact_cleanup(app_state: AppState) {
+    tokio::spawn(async move {
+        artifact_cleanup(app_state).await;
+    });
+}
+
 pub fn router() -> OpenApiRouter<AppState> {
     OpenApiRouter::new()
+        .routes(routes!(list_jobs))
         .routes(routes!(get_job))
         .routes(routes!(cancel_job))
         .routes(routes!(retry_job))
+        .routes(routes!(upload_artifact, get_artifacts))
+        .routes(routes!(download_artifact))
 }
@@ -1,10 +1,50 @@
+use crate::job::cron::Cron;
 use crate::runner::model::Platform;
 use devenv_runner::protocol::{Platform as RunnerPlatform, VM};
 use serde::Deserialize;
+use std::collections::BTreeMap;
 
-/// A collection of VM configurations parsed from a devenv.yaml file.
+/// Name of the job created when devenv.yaml doesn't declare any jobs
+pub const DEFAULT_JOB_NAME: &str = "devenv";
+
+/// A single job to create, running on one platform.
+#[derive(Debug, Clone)]
+pub struct JobSpec {
+    /// Name of the job as declared in devenv.yaml
+    pub name: String,
+    /// The VM the job runs in
+    pub vm: VM,
+    /// Tasks to run, `None` uses the configured defaults
+    pub tasks: Option<Vec<String>>,
+    /// Indices of the jobs (in `FinalCloud::jobs`) that have to succeed first
+    pub needs: Vec<usize>,
+    /// Labels a runner needs to have to run the job
+    pub runs_on: Vec<String>,
+    /// Names of the secrets exposed to the job
+    pub secrets: Vec<String>,
+    /// How long the job may run, `None` uses the configured default
+    pub timeout_seconds: Option<u32>,
+}
+
+/// A cron schedule to run the jobs of the default branch on
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    pub cron: Cron,
+    /// Whether missed ticks run once, `None` uses the configured default
+    pub catch_up: Option<bool>,
+}
+
+/// A collection of jobs parsed from a devenv.yaml file.
+///
+/// Jobs are ordered so that every job comes after the jobs it needs.
 #[derive(Debug)]
-pub struct FinalCloud(Vec<VM>);
+pub struct FinalCloud {
+    jobs: Vec<JobSpec>,
+    artifacts: Vec<String>,
+    schedule: Vec<Schedule>,
+    cancel_superseded: Option<bool>,
+    skip_drafts: bool,
+}
 
 impl FinalCloud {
     /// Create a new `FinalCloud` from a devenv.yaml string.
@@ -13,6 +53,26 @@ impl FinalCloud {
     /// memory and CPU settings. If the string is empty or doesn't contain platform
     /// definitions, default platforms (x86_64-linux and aarch64-darwin) are used.
     ///
+    /// Jobs only run on runners having all labels listed in `cloud.runs-on`,
+    /// which jobs can override. The same goes for the secrets listed in
+    /// `cloud.secrets`, which are exposed to the jobs as environment variables.
+    ///
+    /// The cron expressions listed in `cloud.schedule` run all jobs on the
+    /// default branch, in UTC, in addition to the runs for pushes. Schedules
+    /// given as `{ cron, catch-up }` decide whether their missed ticks run.
+    ///
+    /// Jobs time out after `cloud.timeout` (e.g. `30m` or `3h`), which
+    /// platforms and jobs can override.
+    ///
+    /// Jobs of a branch or pull request are cancelled once a newer commit is
+    /// pushed to it, unless `cloud.cancel-superseded` is false. The default
+    /// branch only does so if it's set to true. Draft pull
+    /// requests only run once ready for review if `cloud.skip-drafts` is true.
+    ///
+    /// Named jobs can be declared under `cloud.jobs`, each running on every platform
+    /// unless overridden. A job listing other jobs in `needs` only runs once those
+    /// succeeded on the same platform, or on all platforms if they don't run on it.
+    ///
     /// # Arguments
     /// * `devenv_config_str` - A string containing YAML configuration, can be empty
     ///
@@ -27,10 +87,29 @@ impl FinalCloud {
     /// cloud:
     ///   memory: 4gb
     ///   cpus: 2
+    ///   timeout: 30m
     ///   platforms:
     ///     - x86_64-linux
     ///     - name: aarch64-darwin
     ///       memory: 8gb
+    ///       timeout: 1h
+    ///   artifacts:
+    ///     - result/**
+    ///   runs-on: [region-eu]
+    ///   schedule:
+    ///     - "0 3 * * *"
+    ///     - cron: "@weekly"
+    ///       catch-up: true
+    ///   jobs:
+    ///     lint:
+    ///       tasks: [devenv:lint]
+    ///     build:
+    ///       needs: [lint]
+    ///       runs-on: [region-eu, hugepages]
+    ///       timeout: 3h
+    ///     deploy:
+    ///       needs: [build]
+    ///       secrets: [DEPLOY_TOKEN]
     /// "#;
     /// let vm_configs = FinalCloud::new(yaml).unwrap();
     ///
@@ -57,6 +136,7 @@ impl FinalCloud {
         let cloud_memory_str = cloud.memory.as_deref().unwrap_or(DEFAULT_MEMORY);
         let cloud_memory_mb = parse_memory(cloud_memory_str)?;
         let cloud_cpus = cloud.cpus.unwrap_or(DEFAULT_CPUS);
+        let cloud_timeout = cloud.timeout.as_deref().map(parse_timeout).transpose()?;
 
         // If no platforms provided, default to the two allowed ones.
         let platforms_raw = cloud.platforms.as_ref().map_or_else(
@@ -69,57 +149,244 @@ impl FinalCloud {
             |p| p.clone(),
         );
 
-        // Process each platform configuration
-        let vms = platforms_raw.into_iter()
-            .map(|platform_config| {
-                let (name, memory_opt, cpus_opt) = match platform_config {
-                    PlatformConfig::Simple(name) => (name, None, None),
-                    PlatformConfig::Detailed { name, memory, cpus } => (name, memory, cpus),
-                };
+        let vms = resolve_vms(platforms_raw, cloud_memory_mb, cloud_cpus, cloud_timeout)?;
+
+        // Artifact globs are matched inside the checkout, so they have to stay within it
+        let artifacts = cloud.artifacts.clone().unwrap_or_default();
+        for pattern in &artifacts {
+            if pattern.starts_with('/') || pattern.split('/').any(|part| part == "..") {
+                return Err(format!(
+                    "Artifact pattern '{}' must be relative to the project directory",
+                    pattern
+                ));
+            }
+        }
 
-                // Validate platform name and convert to enum
-                let platform = match name.as_str() {
-                    "x86_64-linux" => Platform::X86_64Linux,
-                    "aarch64-darwin" => Platform::AArch64Darwin,
-                    _ => return Err(format!(
-                        "Platform '{}' is not supported. Only 'x86_64-linux' and 'aarch64-darwin' are allowed",
-                        name
-                    )),
+        let runs_on = cloud.runs_on.clone().unwrap_or_default();
+        validate_labels(&runs_on)?;
+
+        let secrets = cloud.secrets.clone().unwrap_or_default();
+        validate_secrets(&secrets)?;
+
+        let cancel_superseded = cloud.cancel_superseded;
+        let skip_drafts = cloud.skip_drafts.unwrap_or(false);
+
+        let schedule = cloud
+            .schedule
+            .iter()
+            .flatten()
+            .map(|schedule| {
+                let (expression, catch_up) = match schedule {
+                    ScheduleConfig::Simple(expression) => (expression, None),
+                    ScheduleConfig::Detailed { cron, catch_up } => (cron, *catch_up),
                 };
+                Ok(Schedule {
+                    cron: expression.parse()?,
+                    catch_up,
+                })
+            })
+            .collect::<Result<Vec<Schedule>, String>>()?;
+
+        // Without declared jobs, run a single job on every platform
+        let Some(cloud_jobs) = &cloud.jobs else {
+            let jobs = vms
+                .into_iter()
+                .map(|(vm, timeout_seconds)| JobSpec {
+                    name: DEFAULT_JOB_NAME.to_string(),
+                    vm,
+                    tasks: None,
+                    needs: vec![],
+                    runs_on: runs_on.clone(),
+                    secrets: secrets.clone(),
+                    timeout_seconds,
+                })
+                .collect();
+            return Ok(FinalCloud {
+                jobs,
+                artifacts,
+                schedule,
+                cancel_superseded,
+                skip_drafts,
+            });
+        };
 
-                // Get platform memory, using platform override or cloud default
-                let memory_mb = if let Some(mem_str) = memory_opt {
-                    parse_memory(&mem_str)?
-                } else {
-                    cloud_memory_mb
+        if cloud_jobs.is_empty() {
+            return Err("cloud.jobs must declare at least one job".to_string());
+        }
+        for (name, job) in cloud_jobs {
+            if !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                return Err(format!(
+                    "Job name '{}' may only contain letters, digits, '-' and '_'",
+                    name
+                ));
+            }
+            if let Some(need) = job
+                .needs
+                .iter()
+                .find(|need| !cloud_jobs.contains_key(*need))
+            {
+                return Err(format!("Job '{}' needs unknown job '{}'", name, need));
+            }
+            if let Some(labels) = &job.runs_on {
+                validate_labels(labels)?;
+            }
+            if let Some(job_secrets) = &job.secrets {
+                validate_secrets(job_secrets)?;
+            }
+        }
+
+        // Expand jobs in dependency order, so the jobs they need already have an index
+        let mut jobs: Vec<JobSpec> = Vec::new();
+        let mut remaining: BTreeMap<&str, &CloudJob> = cloud_jobs
+            .iter()
+            .map(|(name, job)| (name.as_str(), job))
+            .collect();
+        while !remaining.is_empty() {
+            let ready: Vec<&str> = remaining
+                .iter()
+                .filter(|(_, job)| {
+                    job.needs
+                        .iter()
+                        .all(|need| !remaining.contains_key(need.as_str()))
+                })
+                .map(|(name, _)| *name)
+                .collect();
+
+            if ready.is_empty() {
+                let names: Vec<&str> = remaining.keys().copied().collect();
+                return Err(format!(
+                    "Jobs have circular dependencies: {}",
+                    names.join(", ")
+                ));
+            }
+
+            for name in ready {
+                let job = remaining.remove(name).expect("ready job is remaining");
+                let job_timeout = job.timeout.as_deref().map(parse_timeout).transpose()?;
+                // The job's timeout takes precedence over the cloud-level platforms' ones
+                let job_vms = match &job.platforms {
+                    Some(platforms) => resolve_vms(
+                        platforms.clone(),
+                        cloud_memory_mb,
+                        cloud_cpus,
+                        job_timeout.or(cloud_timeout),
+                    )?,
+                    None => vms
+                        .iter()
+                        .map(|(vm, timeout)| (vm.clone(), job_timeout.or(*timeout)))
+                        .collect(),
                 };
 
-                // Get platform CPUs, using platform override or cloud default
-                let cpus = cpus_opt.unwrap_or(cloud_cpus);
-
-                // Convert directly to VM struct
-                Ok(VM {
-                    cpu_count: cpus as usize,
-                    memory_size_mb: memory_mb as u64,
-                    platform: match platform {
-                        Platform::X86_64Linux => RunnerPlatform::X86_64Linux,
-                        Platform::AArch64Darwin => RunnerPlatform::AArch64Darwin,
-                    },
+                for (vm, timeout_seconds) in job_vms {
+                    // Prefer the needed job on the same platform, otherwise wait for all of them
+                    let mut needs = Vec::new();
+                    for need in &job.needs {
+                        let upstream: Vec<(usize, &JobSpec)> = jobs
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, spec)| spec.name == *need)
+                            .collect();
+                        let same_platform: Vec<usize> = upstream
+                            .iter()
+                            .filter(|(_, spec)| spec.vm.platform == vm.platform)
+                            .map(|(index, _)| *index)
+                            .collect();
+                        if same_platform.is_empty() {
+                            needs.extend(upstream.iter().map(|(index, _)| *index));
+                        } else {
+                            needs.extend(same_platform);
+                        }
+                    }
+
+                    jobs.push(JobSpec {
+                        name: name.to_string(),
+                        vm,
+                        tasks: job.tasks.clone(),
+                        needs,
+                        runs_on: job.runs_on.clone().unwrap_or_else(|| runs_on.clone()),
+                        secrets: job.secrets.clone().unwrap_or_else(|| secrets.clone()),
+                        timeout_seconds,
+                    });
+                }
+            }
+        }
+
+        Ok(FinalCloud {
+            jobs,
+            artifacts,
+            schedule,
+            cancel_superseded,
+            skip_drafts,
+        })
+    }
+
+    /// Returns the jobs contained in this FinalCloud, in dependency order
+    pub fn jobs(&self) -> &[JobSpec] {
+        &self.jobs
+    }
+
+    /// Returns the VMs of all jobs contained in this FinalCloud
+    pub fn vms(&self) -> Vec<VM> {
+        self.jobs.iter().map(|job| job.vm.clone()).collect()
+    }
+
+    /// Returns the glob patterns of files to collect as artifacts after each job
+    pub fn artifacts(&self) -> &[String] {
+        &self.artifacts
+    }
+
+    /// Only keep the jobs running on one of the platforms
+    ///
+    /// Kept jobs no longer wait for the jobs they need on other platforms.
+    pub fn retain_platforms(&mut self, platforms: &[RunnerPlatform]) -> Result<(), String> {
+        // New index of every job, `None` for dropped ones
+        let mut kept = 0;
+        let indices: Vec<Option<usize>> = self
+            .jobs
+            .iter()
+            .map(|job| {
+                platforms.contains(&job.vm.platform).then(|| {
+                    kept += 1;
+                    kept - 1
                 })
             })
-            .collect::<Result<Vec<VM>, String>>()?;
+            .collect();
+        if kept == 0 {
+            return Err("No jobs run on the requested platforms".to_string());
+        }
+
+        let jobs = std::mem::take(&mut self.jobs);
+        self.jobs = jobs
+            .into_iter()
+            .zip(&indices)
+            .filter(|(_, index)| index.is_some())
+            .map(|(mut job, _)| {
+                job.needs = job.needs.iter().filter_map(|&need| indices[need]).collect();
+                job
+            })
+            .collect();
+        Ok(())
+    }
 
-        Ok(FinalCloud(vms))
+    /// Returns the cron schedules to run the jobs of the default branch on
+    pub fn schedule(&self) -> &[Schedule] {
+        &self.schedule
     }
 
-    /// Returns the VMs contained in this FinalCloud
-    pub fn vms(&self) -> &[VM] {
-        &self.0
+    /// Whether a newer commit on the same branch cancels the unfinished jobs of older ones
+    ///
+    /// Unless configured otherwise, every commit of the default branch runs to
+    /// completion, while only the latest one of other branches does.
+    pub fn cancel_superseded(&self, default_branch: bool) -> bool {
+        self.cancel_superseded.unwrap_or(!default_branch)
     }
 
-    /// Converts FinalCloud into a Vec<VM>
-    pub fn into_vms(self) -> Vec<VM> {
-        self.0
+    /// Whether pull requests only run once they're no longer drafts
+    pub fn skip_drafts(&self) -> bool {
+        self.skip_drafts
     }
 }
 
@@ -144,9 +411,85 @@ struct Cloud {
     #[serde(default)]
     cpus: Option<u32>,
 
+    /// Default timeout for all jobs (e.g., "30m" or "3h")
+    #[serde(default)]
+    timeout: Option<String>,
+
     /// List of platform configurations
     #[serde(default)]
     platforms: Option<Vec<PlatformConfig>>,
+
+    /// Glob patterns of files to collect as artifacts (e.g., "result/**")
+    #[serde(default)]
+    artifacts: Option<Vec<String>>,
+
+    /// Labels a runner needs to have to run the jobs
+    #[serde(default, rename = "runs-on")]
+    runs_on: Option<Vec<String>>,
+
+    /// Names of the secrets exposed to the jobs
+    #[serde(default)]
+    secrets: Option<Vec<String>>,
+
+    /// Cron expressions to run the jobs of the default branch on
+    #[serde(default)]
+    schedule: Option<Vec<ScheduleConfig>>,
+
+    /// Whether newer commits cancel the unfinished jobs of older ones, defaults to
+    /// true except on the default branch
+    #[serde(default, rename = "cancel-superseded")]
+    cancel_superseded: Option<bool>,
+
+    /// Whether draft pull requests wait until they're ready for review, defaults to false
+    #[serde(default, rename = "skip-drafts")]
+    skip_drafts: Option<bool>,
+
+    /// Named jobs forming a pipeline
+    #[serde(default)]
+    jobs: Option<BTreeMap<String, CloudJob>>,
+}
+
+/// A named job in the cloud configuration.
+#[derive(Debug, Deserialize, Clone)]
+struct CloudJob {
+    /// Platforms to run the job on, defaults to the cloud platforms
+    #[serde(default)]
+    platforms: Option<Vec<PlatformConfig>>,
+
+    /// Tasks to run instead of the defaults
+    #[serde(default)]
+    tasks: Option<Vec<String>>,
+
+    /// Names of the jobs that have to succeed before this one runs
+    #[serde(default)]
+    needs: Vec<String>,
+
+    /// Runner labels to require instead of the cloud-level ones
+    #[serde(default, rename = "runs-on")]
+    runs_on: Option<Vec<String>>,
+
+    /// Secrets to expose instead of the cloud-level ones
+    #[serde(default)]
+    secrets: Option<Vec<String>>,
+
+    /// Timeout instead of the cloud-level one
+    #[serde(default)]
+    timeout: Option<String>,
+}
+
+/// Configuration for a schedule in the cloud configuration.
+///
+/// Either just the cron expression, or the expression along with whether
+/// missed ticks run.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(untagged)]
+enum ScheduleConfig {
+    Simple(String),
+    Detailed {
+        cron: String,
+        #[serde(default, rename = "catch-up")]
+        catch_up: Option<bool>,
+    },
 }
 
 /// Configuration for a platform in the cloud configuration.
@@ -172,9 +515,100 @@ enum PlatformConfig {
         /// Optional CPU count
         #[serde(default)]
         cpus: Option<u32>,
+
+        /// Optional timeout (e.g., "30m" or "3h")
+        #[serde(default)]
+        timeout: Option<String>,
     },
 }
 
+/// Resolves platform configurations into VMs and their timeouts, applying the
+/// cloud-level defaults.
+fn resolve_vms(
+    platforms: Vec<PlatformConfig>,
+    cloud_memory_mb: u32,
+    cloud_cpus: u32,
+    cloud_timeout: Option<u32>,
+) -> Result<Vec<(VM, Option<u32>)>, String> {
+    platforms.into_iter()
+        .map(|platform_config| {
+            let (name, memory_opt, cpus_opt, timeout_opt) = match platform_config {
+                PlatformConfig::Simple(name) => (name, None, None, None),
+                PlatformConfig::Detailed { name, memory, cpus, timeout } => (name, memory, cpus, timeout),
+            };
+
+            // Validate platform name and convert to enum
+            let platform = match name.as_str() {
+                "x86_64-linux" => Platform::X86_64Linux,
+                "aarch64-darwin" => Platform::AArch64Darwin,
+                _ => return Err(format!(
+                    "Platform '{}' is not supported. Only 'x86_64-linux' and 'aarch64-darwin' are allowed",
+                    name
+                )),
+            };
+
+            // Get platform memory, using platform override or cloud default
+            let memory_mb = if let Some(mem_str) = memory_opt {
+                parse_memory(&mem_str)?
+            } else {
+                cloud_memory_mb
+            };
+
+            // Get platform CPUs, using platform override or cloud default
+            let cpus = cpus_opt.unwrap_or(cloud_cpus);
+
+            // Get platform timeout, using platform override or cloud default
+            let timeout = match timeout_opt {
+                Some(timeout_str) => Some(parse_timeout(&timeout_str)?),
+                None => cloud_timeout,
+            };
+
+            // Convert directly to VM struct
+            let vm = VM {
+                cpu_count: cpus as usize,
+                memory_size_mb: memory_mb as u64,
+                platform: match platform {
+                    Platform::X86_64Linux => RunnerPlatform::X86_64Linux,
+                    Platform::AArch64Darwin => RunnerPlatform::AArch64Darwin,
+                },
+            };
+            Ok((vm, timeout))
+        })
+        .collect::<Result<Vec<_>, String>>()
+}
+
+/// Checks that runner labels only use characters runners can advertise.
+fn validate_labels(labels: &[String]) -> Result<(), String> {
+    for label in labels {
+        if !devenv_runner::protocol::is_valid_label(label) {
+            return Err(format!(
+                "Runner label '{}' may only contain letters, digits, '-', '_', '.', ':' and '='",
+                label
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Checks that secret names can be used as environment variables and aren't reserved.
+fn validate_secrets(names: &[String]) -> Result<(), String> {
+    for name in names {
+        if !crate::github::secrets::is_valid_name(name) {
+            return Err(format!(
+                "Secret name '{}' may only contain letters, digits and '_', and can't start with a digit",
+                name
+            ));
+        }
+        if crate::github::secrets::is_reserved_name(name) {
+            return Err(format!(
+                "Secret name '{}' is reserved for the environment of the job",
+                name
+            ));
+        }
+    }
+    Ok(())
+}
+
 /// Parses a memory string into megabytes.
 ///
 /// The string must end with either "mb" or "gb" (case-insensitive),
@@ -199,6 +633,35 @@ fn parse_memory(s: &str) -> Result<u32, String> {
     }
 }
 
+/// Parses a timeout string into seconds.
+///
+/// The string must end with "s", "m" or "h" (case-insensitive), e.g. "90s",
+/// "45m" or "3h". Timeouts are stored as `INT4`, longer ones are rejected.
+fn parse_timeout(s: &str) -> Result<u32, String> {
+    let s = s.trim().to_lowercase();
+
+    let (num_str, unit_seconds) = if let Some(num_str) = s.strip_suffix('h') {
+        (num_str, 3600)
+    } else if let Some(num_str) = s.strip_suffix('m') {
+        (num_str, 60)
+    } else if let Some(num_str) = s.strip_suffix('s') {
+        (num_str, 1)
+    } else {
+        return Err(format!("Timeout must end with 's', 'm' or 'h': {}", s));
+    };
+    let num: u32 = num_str
+        .trim()
+        .parse()
+        .map_err(|_| format!("Invalid timeout: {}", s))?;
+    match num.checked_mul(unit_seconds) {
+        Some(0) | None => Err(format!("Invalid timeout: {}", s)),
+        Some(seconds) if i32::try_from(seconds).is_err() => {
+            Err(format!("Timeout is too long: {}", s))
+        }
+        Some(seconds) => Ok(seconds),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -278,4 +741,340 @@ cloud:
             assert!(e.contains("Platform '"));
         }
     }
+
+    #[test]
+    fn test_final_cloud_artifacts() {
+        let yaml_str = r#"
+cloud:
+  artifacts:
+    - result/**
+    - coverage.xml
+        "#;
+
+        let cloud = FinalCloud::new(yaml_str).expect("Failed to create VM configs");
+        assert_eq!(cloud.artifacts(), ["result/**", "coverage.xml"]);
+
+        // No artifacts are collected unless configured
+        let cloud = FinalCloud::new("").expect("Failed to create VM configs");
+        assert!(cloud.artifacts().is_empty());
+
+        // Patterns escaping the project directory are rejected
+        let yaml_str = r#"
+cloud:
+  artifacts:
+    - ../secrets/**
+        "#;
+        assert!(FinalCloud::new(yaml_str).is_err());
+    }
+
+    #[test]
+    fn test_final_cloud_jobs() {
+        let yaml_str = r#"
+cloud:
+  jobs:
+    deploy:
+      needs: [build]
+      platforms: [x86_64-linux]
+    build:
+      needs: [lint]
+    lint:
+      tasks: [devenv:lint]
+      platforms: [x86_64-linux]
+        "#;
+
+        let cloud = FinalCloud::new(yaml_str).expect("Failed to create VM configs");
+        let jobs = cloud.jobs();
+        let names: Vec<&str> = jobs.iter().map(|job| job.name.as_str()).collect();
+        assert_eq!(names, ["lint", "build", "build", "deploy"]);
+
+        // Jobs without platforms run on the cloud defaults
+        assert!(matches!(jobs[1].vm.platform, RunnerPlatform::X86_64Linux));
+        assert!(matches!(jobs[2].vm.platform, RunnerPlatform::AArch64Darwin));
+
+        assert_eq!(jobs[0].tasks, Some(vec!["devenv:lint".to_string()]));
+        assert_eq!(jobs[1].tasks, None);
+
+        // lint only runs on Linux, so both builds wait for it
+        assert!(jobs[0].needs.is_empty());
+        assert_eq!(jobs[1].needs, [0]);
+        assert_eq!(jobs[2].needs, [0]);
+
+        // deploy waits for the build on its own platform
+        assert_eq!(jobs[3].needs, [1]);
+    }
+
+    #[test]
+    fn test_final_cloud_default_job() {
+        let cloud = FinalCloud::new("").expect("Failed to create VM configs");
+        assert_eq!(cloud.jobs().len(), 2);
+        for job in cloud.jobs() {
+            assert_eq!(job.name, DEFAULT_JOB_NAME);
+            assert!(job.needs.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_final_cloud_reject_invalid_needs() {
+        let unknown = r#"
+cloud:
+  jobs:
+    build:
+      needs: [lint]
+        "#;
+        let result = FinalCloud::new(unknown);
+        assert!(result.is_err_and(|e| e.contains("unknown job 'lint'")));
+
+        let circular = r#"
+cloud:
+  jobs:
+    build:
+      needs: [test]
+    test:
+      needs: [build]
+        "#;
+        let result = FinalCloud::new(circular);
+        assert!(result.is_err_and(|e| e.contains("circular")));
+    }
+
+    #[test]
+    fn test_final_cloud_runs_on() {
+        let yaml_str = r#"
+cloud:
+  platforms: [x86_64-linux]
+  runs-on: [region=eu]
+  jobs:
+    build: {}
+    bench:
+      runs-on: [region=eu, hugepages]
+        "#;
+
+        let cloud = FinalCloud::new(yaml_str).expect("Failed to create VM configs");
+        let jobs = cloud.jobs();
+        assert_eq!(jobs[0].name, "bench");
+        assert_eq!(jobs[0].runs_on, ["region=eu", "hugepages"]);
+        assert_eq!(jobs[1].runs_on, ["region=eu"]);
+
+        // Jobs run on any runner unless labels are required
+        let cloud = FinalCloud::new("").expect("Failed to create VM configs");
+        assert!(cloud.jobs().iter().all(|job| job.runs_on.is_empty()));
+
+        let invalid = r#"
+cloud:
+  runs-on: ["large memory"]
+        "#;
+        let result = FinalCloud::new(invalid);
+        assert!(result.is_err_and(|e| e.contains("Runner label 'large memory'")));
+    }
+
+    #[test]
+    fn test_final_cloud_secrets() {
+        let yaml_str = r#"
+cloud:
+  platforms: [x86_64-linux]
+  secrets: [CACHIX_AUTH_TOKEN]
+  jobs:
+    build: {}
+    deploy:
+      needs: [build]
+      secrets: [DEPLOY_TOKEN]
+        "#;
+
+        let cloud = FinalCloud::new(yaml_str).expect("Failed to create VM configs");
+        let jobs = cloud.jobs();
+        assert_eq!(jobs[0].name, "build");
+        assert_eq!(jobs[0].secrets, ["CACHIX_AUTH_TOKEN"]);
+        assert_eq!(jobs[1].secrets, ["DEPLOY_TOKEN"]);
+
+        // No secrets are exposed unless listed
+        let cloud = FinalCloud::new("").expect("Failed to create VM configs");
+        assert!(cloud.jobs().iter().all(|job| job.secrets.is_empty()));
+
+        let invalid = r#"
+cloud:
+  secrets: [DEPLOY-TOKEN]
+        "#;
+        let result = FinalCloud::new(invalid);
+        assert!(result.is_err_and(|e| e.contains("Secret name 'DEPLOY-TOKEN'")));
+
+        let reserved = r#"
+cloud:
+  jobs:
+    build:
+      secrets: [LD_PRELOAD]
+        "#;
+        let result = FinalCloud::new(reserved);
+        assert!(result.is_err_and(|e| e.contains("'LD_PRELOAD' is reserved")));
+    }
+
+    #[test]
+    fn test_final_cloud_schedule() {
+        let yaml_str = r#"
+cloud:
+  schedule: ["0 3 * * *", "@weekly"]
+        "#;
+
+        let cloud = FinalCloud::new(yaml_str).expect("Failed to create VM configs");
+        let schedule: Vec<&str> = cloud
+            .schedule()
+            .iter()
+            .map(|schedule| schedule.cron.as_str())
+            .collect();
+        assert_eq!(schedule, ["0 3 * * *", "@weekly"]);
+        assert!(cloud.schedule().iter().all(|s| s.catch_up.is_none()));
+
+        let yaml_str = r#"
+cloud:
+  schedule:
+    - "0 3 * * *"
+    - cron: "@weekly"
+      catch-up: true
+        "#;
+        let cloud = FinalCloud::new(yaml_str).expect("Failed to create VM configs");
+        let schedule: Vec<(&str, Option<bool>)> = cloud
+            .schedule()
+            .iter()
+            .map(|schedule| (schedule.cron.as_str(), schedule.catch_up))
+            .collect();
+        assert_eq!(schedule, [("0 3 * * *", None), ("@weekly", Some(true))]);
+
+        let cloud = FinalCloud::new("").expect("Failed to create VM configs");
+        assert!(cloud.schedule().is_empty());
+
+        let invalid = r#"
+cloud:
+  schedule: ["every day"]
+        "#;
+        let result = FinalCloud::new(invalid);
+        assert!(result.is_err_and(|e| e.contains("Schedule 'every day'")));
+    }
+
+    #[test]
+    fn test_final_cloud_retain_platforms() {
+        let yaml_str = r#"
+cloud:
+  jobs:
+    build: {}
+    deploy:
+      needs: [build]
+        "#;
+
+        let mut cloud = FinalCloud::new(yaml_str).expect("Failed to create VM configs");
+        cloud
+            .retain_platforms(&[RunnerPlatform::AArch64Darwin])
+            .expect("Failed to retain platforms");
+        let jobs = cloud.jobs();
+        assert_eq!(jobs.len(), 2);
+        assert!(
+            jobs.iter()
+                .all(|job| job.vm.platform == RunnerPlatform::AArch64Darwin)
+        );
+        assert_eq!(jobs[1].name, "deploy");
+        assert_eq!(jobs[1].needs, [0]);
+
+        let yaml_str = r#"
+cloud:
+  platforms: [x86_64-linux]
+        "#;
+        let mut cloud = FinalCloud::new(yaml_str).expect("Failed to create VM configs");
+        assert!(
+            cloud
+                .retain_platforms(&[RunnerPlatform::AArch64Darwin])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_final_cloud_cancel_superseded() {
+        let cloud = FinalCloud::new("").expect("Failed to create VM configs");
+        assert!(cloud.cancel_superseded(false));
+        assert!(!cloud.cancel_superseded(true));
+
+        let yaml_str = r#"
+cloud:
+  cancel-superseded: false
+        "#;
+        let cloud = FinalCloud::new(yaml_str).expect("Failed to create VM configs");
+        assert!(!cloud.cancel_superseded(false));
+
+        let yaml_str = r#"
+cloud:
+  cancel-superseded: true
+        "#;
+        let cloud = FinalCloud::new(yaml_str).expect("Failed to create VM configs");
+        assert!(cloud.cancel_superseded(true));
+    }
+
+    #[test]
+    fn test_final_cloud_skip_drafts() {
+        let cloud = FinalCloud::new("").expect("Failed to create VM configs");
+        assert!(!cloud.skip_drafts());
+
+        let yaml_str = r#"
+cloud:
+  skip-drafts: true
+        "#;
+        let cloud = FinalCloud::new(yaml_str).expect("Failed to create VM configs");
+        assert!(cloud.skip_drafts());
+    }
+
+    #[test]
+    fn test_parse_timeout() {
+        assert_eq!(parse_timeout("90s"), Ok(90));
+        assert_eq!(parse_timeout("45m"), Ok(2700));
+        assert_eq!(parse_timeout(" 3H "), Ok(10800));
+        assert!(parse_timeout("0m").is_err());
+        assert!(parse_timeout("300").is_err());
+        assert!(parse_timeout("1h30m").is_err());
+        // Beyond what the database can store
+        assert!(parse_timeout("1000000h").is_err());
+        assert!(parse_timeout("2147483648s").is_err());
+        assert_eq!(parse_timeout("2147483647s"), Ok(i32::MAX as u32));
+    }
+
+    #[test]
+    fn test_final_cloud_timeouts() {
+        let yaml_str = r#"
+cloud:
+  timeout: 30m
+  platforms:
+    - x86_64-linux
+    - name: aarch64-darwin
+      timeout: 1h
+  jobs:
+    lint: {}
+    build:
+      timeout: 3h
+    deploy:
+      platforms:
+        - name: x86_64-linux
+          timeout: 5m
+        "#;
+
+        let cloud = FinalCloud::new(yaml_str).expect("Failed to create VM configs");
+        let timeouts: Vec<(&str, RunnerPlatform, Option<u32>)> = cloud
+            .jobs()
+            .iter()
+            .map(|job| {
+                (
+                    job.name.as_str(),
+                    job.vm.platform.clone(),
+                    job.timeout_seconds,
+                )
+            })
+            .collect();
+        assert_eq!(
+            timeouts,
+            [
+                ("build", RunnerPlatform::X86_64Linux, Some(10800)),
+                ("build", RunnerPlatform::AArch64Darwin, Some(10800)),
+                ("deploy", RunnerPlatform::X86_64Linux, Some(300)),
+                ("lint", RunnerPlatform::X86_64Linux, Some(1800)),
+                ("lint", RunnerPlatform::AArch64Darwin, Some(3600)),
+            ]
+        );
+
+        // Without a timeout, the configured default applies
+        let cloud = FinalCloud::new("").expect("Failed to create VM configs");
+        assert!(cloud.jobs().iter().all(|job| job.timeout_seconds.is_none()));
+    }
 }
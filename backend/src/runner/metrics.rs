@@ -0,0 +1,122 @@
+//! Prometheus gauges describing the capacity and utilization of connected runners.
+//!
+//! Every gauge is labelled by runner id and platform, and removed again when the
+//! runner disconnects.
+
+use devenv_runner::protocol::RunnerMetrics;
+use prometheus::{GaugeVec, IntGaugeVec, register_gauge_vec, register_int_gauge_vec};
+use std::sync::LazyLock;
+use uuid::Uuid;
+
+const LABELS: &[&str] = &["runner", "platform"];
+
+struct Gauges {
+    cpus: IntGaugeVec,
+    used_cpus: IntGaugeVec,
+    memory_mb: IntGaugeVec,
+    used_memory_mb: IntGaugeVec,
+    cpu_utilization: GaugeVec,
+    memory_utilization: GaugeVec,
+    active_jobs: IntGaugeVec,
+    running_jobs: IntGaugeVec,
+    queued_jobs: IntGaugeVec,
+}
+
+impl Gauges {
+    fn register() -> Self {
+        let int_gauge = |name: &str, help: &str| {
+            register_int_gauge_vec!(name, help, LABELS).expect("runner gauge can be registered")
+        };
+        let gauge = |name: &str, help: &str| {
+            register_gauge_vec!(name, help, LABELS).expect("runner gauge can be registered")
+        };
+
+        Self {
+            cpus: int_gauge("devenv_runner_cpus", "CPUs available to the runner"),
+            used_cpus: int_gauge("devenv_runner_used_cpus", "CPUs allocated to jobs"),
+            memory_mb: int_gauge("devenv_runner_memory_mb", "Memory available to the runner"),
+            used_memory_mb: int_gauge("devenv_runner_used_memory_mb", "Memory allocated to jobs"),
+            cpu_utilization: gauge(
+                "devenv_runner_cpu_utilization_percent",
+                "Share of the runner's CPUs allocated to jobs",
+            ),
+            memory_utilization: gauge(
+                "devenv_runner_memory_utilization_percent",
+                "Share of the runner's memory allocated to jobs",
+            ),
+            active_jobs: int_gauge("devenv_runner_active_jobs", "Jobs not completed yet"),
+            running_jobs: int_gauge("devenv_runner_running_jobs", "Jobs running in a VM"),
+            queued_jobs: int_gauge("devenv_runner_queued_jobs", "Jobs waiting for their VM"),
+        }
+    }
+}
+
+static GAUGES: LazyLock<Gauges> = LazyLock::new(Gauges::register);
+
+/// Export the latest metrics of a runner
+pub fn record(runner_id: &Uuid, metrics: &RunnerMetrics) {
+    let runner_id = runner_id.to_string();
+    let platform = metrics.platform.to_string();
+    let labels = [runner_id.as_str(), platform.as_str()];
+    let gauges = &*GAUGES;
+
+    gauges
+        .cpus
+        .with_label_values(&labels)
+        .set(metrics.cpu_count as i64);
+    gauges
+        .used_cpus
+        .with_label_values(&labels)
+        .set(metrics.used_cpu_count as i64);
+    gauges
+        .memory_mb
+        .with_label_values(&labels)
+        .set(metrics.memory_size_mb as i64);
+    gauges
+        .used_memory_mb
+        .with_label_values(&labels)
+        .set(metrics.used_memory_mb as i64);
+    gauges
+        .cpu_utilization
+        .with_label_values(&labels)
+        .set(metrics.cpu_utilization_percent.into());
+    gauges
+        .memory_utilization
+        .with_label_values(&labels)
+        .set(metrics.memory_utilization_percent.into());
+    gauges
+        .active_jobs
+        .with_label_values(&labels)
+        .set(metrics.active_jobs as i64);
+    gauges
+        .running_jobs
+        .with_label_values(&labels)
+        .set(metrics.running_jobs as i64);
+    gauges
+        .queued_jobs
+        .with_label_values(&labels)
+        .set(metrics.queued_jobs as i64);
+}
+
+/// Stop exporting the metrics of a disconnected runner
+pub fn remove(runner_id: &Uuid, metrics: &RunnerMetrics) {
+    let runner_id = runner_id.to_string();
+    let platform = metrics.platform.to_string();
+    let labels = [runner_id.as_str(), platform.as_str()];
+    let gauges = &*GAUGES;
+
+    for gauge in [
+        &gauges.cpus,
+        &gauges.used_cpus,
+        &gauges.memory_mb,
+        &gauges.used_memory_mb,
+        &gauges.active_jobs,
+        &gauges.running_jobs,
+        &gauges.queued_jobs,
+    ] {
+        gauge.remove_label_values(&labels).ok();
+    }
+    for gauge in [&gauges.cpu_utilization, &gauges.memory_utilization] {
+        gauge.remove_label_values(&labels).ok();
+    }
+}
@@ -1,3 +1,4 @@
 pub mod cloudconfig;
+pub mod metrics;
 pub mod model;
 pub mod serve;
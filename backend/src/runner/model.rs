@@ -1,10 +1,14 @@
-use crate::schema::runners;
+use crate::schema::{runner_labels, runner_tokens, runners};
+use devenv_runner::protocol::RunnerMetrics;
 use diesel::prelude::*;
 use diesel_async::AsyncPgConnection;
 use diesel_async::RunQueryDsl;
 use diesel_async::pooled_connection::deadpool::Pool;
 use eyre::Result;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use utoipa::ToSchema;
 use uuid::Uuid;
 
 // Import the Job model and types from job module
@@ -17,24 +21,101 @@ pub struct Runner {
     pub last_seen_at: chrono::DateTime<chrono::Utc>,
     pub platform: Platform,
     pub created_at: chrono::DateTime<chrono::Utc>,
+    pub token_id: Option<uuid::Uuid>,
+    /// Latest metrics reported by the runner
+    pub metrics: Option<serde_json::Value>,
+    /// When the runner stopped being offered new jobs
+    pub cordoned_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 impl Runner {
-    pub async fn new(
+    /// Register a connecting runner, reusing its row when it presents a known id
+    ///
+    /// Returns `None` if the id belongs to a runner registered with another token.
+    pub async fn register(
         pool: &Pool<AsyncPgConnection>,
+        runner_id: Option<Uuid>,
         platform: Platform,
-    ) -> Result<Self, diesel::result::Error> {
+        token_id: Uuid,
+        labels: &[String],
+    ) -> Result<Option<Self>, diesel::result::Error> {
         let conn = &mut pool.get().await.unwrap();
-        let runner = diesel::insert_into(runners::table)
-            .values((
-                runners::id.eq(Uuid::now_v7()),
-                runners::last_seen_at.eq(chrono::Utc::now()),
-                runners::platform.eq(platform),
-            ))
-            .get_result(conn)
-            .await?;
+        let runner_id = runner_id.unwrap_or_else(Uuid::now_v7);
+        let labels = labels.to_vec();
+
+        conn.build_transaction()
+            .run::<_, diesel::result::Error, _>(|conn| {
+                Box::pin(async move {
+                    let existing_token: Option<Option<Uuid>> = runners::table
+                        .filter(runners::id.eq(runner_id))
+                        .select(runners::token_id)
+                        .for_update()
+                        .first(conn)
+                        .await
+                        .optional()?;
 
-        Ok(runner)
+                    let runner: Self = match existing_token {
+                        Some(existing_token) if existing_token != Some(token_id) => {
+                            return Ok(None);
+                        }
+                        Some(_) => {
+                            diesel::update(runners::table)
+                                .filter(runners::id.eq(runner_id))
+                                .set((
+                                    runners::last_seen_at.eq(chrono::Utc::now()),
+                                    runners::platform.eq(platform),
+                                ))
+                                .get_result(conn)
+                                .await?
+                        }
+                        None => {
+                            diesel::insert_into(runners::table)
+                                .values((
+                                    runners::id.eq(runner_id),
+                                    runners::last_seen_at.eq(chrono::Utc::now()),
+                                    runners::platform.eq(platform),
+                                    runners::token_id.eq(token_id),
+                                ))
+                                .get_result(conn)
+                                .await?
+                        }
+                    };
+
+                    // Labels may have changed since the runner last connected
+                    diesel::delete(runner_labels::table)
+                        .filter(runner_labels::runner_id.eq(runner.id))
+                        .execute(conn)
+                        .await?;
+                    let rows: Vec<_> = labels
+                        .iter()
+                        .map(|label| {
+                            (
+                                runner_labels::runner_id.eq(runner.id),
+                                runner_labels::label.eq(label),
+                            )
+                        })
+                        .collect();
+                    diesel::insert_into(runner_labels::table)
+                        .values(rows)
+                        .on_conflict_do_nothing()
+                        .execute(conn)
+                        .await?;
+                    Ok(Some(runner))
+                })
+            })
+            .await
+    }
+
+    pub async fn get_labels(
+        conn: &mut AsyncPgConnection,
+        runner_id: &Uuid,
+    ) -> Result<Vec<String>, diesel::result::Error> {
+        runner_labels::table
+            .filter(runner_labels::runner_id.eq(runner_id))
+            .order_by(runner_labels::label)
+            .select(runner_labels::label)
+            .load(conn)
+            .await
     }
 
     pub async fn disconnected(
@@ -51,6 +132,87 @@ impl Runner {
         Ok(())
     }
 
+    /// Persist the latest metrics reported by a runner
+    pub async fn save_metrics(
+        conn: &mut AsyncPgConnection,
+        runner_id: Uuid,
+        metrics: &RunnerMetrics,
+    ) -> Result<(), diesel::result::Error> {
+        let metrics = serde_json::to_value(metrics)
+            .map_err(|e| diesel::result::Error::SerializationError(Box::new(e)))?;
+        diesel::update(runners::table)
+            .filter(runners::id.eq(runner_id))
+            .set((
+                runners::metrics.eq(metrics),
+                runners::last_seen_at.eq(chrono::Utc::now()),
+            ))
+            .execute(conn)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Get the runners with the given ids along with their labels
+    pub async fn get_with_labels(
+        conn: &mut AsyncPgConnection,
+        runner_ids: &[Uuid],
+    ) -> Result<Vec<(Self, Vec<String>)>, diesel::result::Error> {
+        let runners = runners::table
+            .filter(runners::id.eq_any(runner_ids))
+            .order_by(runners::created_at)
+            .select(Self::as_select())
+            .load::<Self>(conn)
+            .await?;
+
+        let mut labels: HashMap<Uuid, Vec<String>> = HashMap::new();
+        for (runner_id, label) in runner_labels::table
+            .filter(runner_labels::runner_id.eq_any(runner_ids))
+            .order_by(runner_labels::label)
+            .select((runner_labels::runner_id, runner_labels::label))
+            .load::<(Uuid, String)>(conn)
+            .await?
+        {
+            labels.entry(runner_id).or_default().push(label);
+        }
+
+        Ok(runners
+            .into_iter()
+            .map(|runner| {
+                let labels = labels.remove(&runner.id).unwrap_or_default();
+                (runner, labels)
+            })
+            .collect())
+    }
+
+    /// Stop or resume offering new jobs to a runner
+    ///
+    /// Returns `false` if the runner doesn't exist.
+    pub async fn set_cordoned(
+        conn: &mut AsyncPgConnection,
+        runner_id: Uuid,
+        cordoned: bool,
+    ) -> Result<bool, diesel::result::Error> {
+        let cordoned_at = cordoned.then(chrono::Utc::now);
+        let rows = diesel::update(runners::table)
+            .filter(runners::id.eq(runner_id))
+            .set(runners::cordoned_at.eq(cordoned_at))
+            .execute(conn)
+            .await?;
+
+        Ok(rows == 1)
+    }
+
+    pub async fn is_cordoned(
+        conn: &mut AsyncPgConnection,
+        runner_id: &Uuid,
+    ) -> Result<bool, diesel::result::Error> {
+        runners::table
+            .filter(runners::id.eq(runner_id))
+            .select(runners::cordoned_at.is_not_null())
+            .first(conn)
+            .await
+    }
+
     pub async fn get_platform(
         conn: &mut AsyncPgConnection,
         runner_id: &Uuid,
@@ -62,16 +224,151 @@ impl Runner {
             .await
     }
 
-    pub async fn find_matching_platforms(
+    pub async fn get_by_id(
+        conn: &mut AsyncPgConnection,
+        runner_id: Uuid,
+    ) -> Result<Self, diesel::result::Error> {
+        runners::table
+            .filter(runners::id.eq(runner_id))
+            .select(Self::as_select())
+            .first(conn)
+            .await
+    }
+
+    /// Get the ids of the runners that authenticated with a token
+    pub async fn find_by_token(
+        conn: &mut AsyncPgConnection,
+        token_id: Uuid,
+    ) -> Result<Vec<Uuid>, diesel::result::Error> {
+        runners::table
+            .filter(runners::token_id.eq(token_id))
+            .select(runners::id)
+            .load::<Uuid>(conn)
+            .await
+    }
+
+    /// Find the uncordoned runners of a platform that have all the required labels
+    pub async fn find_matching(
         conn: &mut AsyncPgConnection,
         runner_ids: &[Uuid],
         platform: &str,
+        required_labels: &[String],
     ) -> Result<Vec<Uuid>, diesel::result::Error> {
-        runners::table
+        let matching_platform = runners::table
             .filter(runners::id.eq_any(runner_ids))
             .filter(runners::platform.eq(platform))
+            .filter(runners::cordoned_at.is_null())
             .select(runners::id)
             .load::<Uuid>(conn)
+            .await?;
+        if required_labels.is_empty() {
+            return Ok(matching_platform);
+        }
+
+        let mut labels: HashMap<Uuid, HashSet<String>> = HashMap::new();
+        for (runner_id, label) in runner_labels::table
+            .filter(runner_labels::runner_id.eq_any(&matching_platform))
+            .filter(runner_labels::label.eq_any(required_labels))
+            .select((runner_labels::runner_id, runner_labels::label))
+            .load::<(Uuid, String)>(conn)
+            .await?
+        {
+            labels.entry(runner_id).or_default().insert(label);
+        }
+
+        Ok(matching_platform
+            .into_iter()
+            .filter(|runner_id| {
+                labels.get(runner_id).is_some_and(|labels| {
+                    required_labels.iter().all(|label| labels.contains(label))
+                })
+            })
+            .collect())
+    }
+}
+
+/// A registration token runners authenticate with
+#[derive(Debug, Queryable, Selectable, Serialize, Deserialize, ToSchema, Clone)]
+#[diesel(table_name = runner_tokens)]
+pub struct RunnerToken {
+    pub id: uuid::Uuid,
+    pub name: String,
+    #[serde(skip)]
+    pub token_hash: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub revoked_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl RunnerToken {
+    /// Prefix making runner tokens recognizable, e.g. by secret scanners
+    const PREFIX: &str = "devenv_runner_";
+
+    fn hash(token: &str) -> String {
+        hex::encode(Sha256::digest(token.as_bytes()))
+    }
+
+    /// Create a new token, returning it along with its plaintext value
+    ///
+    /// The plaintext is not stored and can't be retrieved later.
+    pub async fn create(
+        conn: &mut AsyncPgConnection,
+        name: &str,
+    ) -> Result<(Self, String), diesel::result::Error> {
+        let secret: [u8; 32] = rand::random();
+        let token = format!("{}{}", Self::PREFIX, hex::encode(secret));
+
+        let runner_token = diesel::insert_into(runner_tokens::table)
+            .values((
+                runner_tokens::id.eq(Uuid::now_v7()),
+                runner_tokens::name.eq(name),
+                runner_tokens::token_hash.eq(Self::hash(&token)),
+            ))
+            .returning(Self::as_returning())
+            .get_result(conn)
+            .await?;
+
+        Ok((runner_token, token))
+    }
+
+    /// Look up an unrevoked token by its plaintext value and record its use
+    pub async fn authenticate(
+        conn: &mut AsyncPgConnection,
+        token: &str,
+    ) -> Result<Option<Self>, diesel::result::Error> {
+        if !token.starts_with(Self::PREFIX) {
+            return Ok(None);
+        }
+
+        diesel::update(runner_tokens::table)
+            .filter(runner_tokens::token_hash.eq(Self::hash(token)))
+            .filter(runner_tokens::revoked_at.is_null())
+            .set(runner_tokens::last_used_at.eq(chrono::Utc::now()))
+            .returning(Self::as_returning())
+            .get_result(conn)
+            .await
+            .optional()
+    }
+
+    pub async fn list(conn: &mut AsyncPgConnection) -> Result<Vec<Self>, diesel::result::Error> {
+        runner_tokens::table
+            .order_by(runner_tokens::created_at.desc())
+            .select(Self::as_select())
+            .load(conn)
             .await
     }
+
+    /// Revoke a token, returning false if it doesn't exist or was already revoked
+    pub async fn revoke(
+        conn: &mut AsyncPgConnection,
+        id: Uuid,
+    ) -> Result<bool, diesel::result::Error> {
+        let rows = diesel::update(runner_tokens::table)
+            .filter(runner_tokens::id.eq(id))
+            .filter(runner_tokens::revoked_at.is_null())
+            .set(runner_tokens::revoked_at.eq(chrono::Utc::now()))
+            .execute(conn)
+            .await?;
+        Ok(rows == 1)
+    }
 }
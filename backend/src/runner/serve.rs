@@ -1,30 +1,38 @@
+use crate::auth::{AdminUser, RunnerAuth};
 use crate::config::AppState;
+use crate::error::Result;
 use crate::github::model::{JobGitHub, SourceControlIntegration};
-use axum::extract::State;
-use axum::response::IntoResponse;
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
 use axum_typed_websockets::{Message, TextJsonCodec, WebSocket, WebSocketUpgrade};
-use devenv_runner::protocol::{ClientMessage, ServerMessage, VM};
+use devenv_runner::protocol::{ClientMessage, RunnerMetrics, ServerMessage, VM};
 use futures_util::stream::StreamExt;
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::{RwLock, mpsc};
+use utoipa::ToSchema;
 use utoipa_axum::{router::OpenApiRouter, routes};
 
-use super::model::Runner;
+use super::model::{Runner, RunnerToken};
 // Use job model types from the job module
-use crate::job::model::{Job, JobStatus};
+use crate::job::model::{Job, JobStatus, JobStep};
 
 // Shared state for tracking runner connections
 #[derive(Clone)]
 pub struct RunnerState {
     // Map of runner_id to a channel for sending messages to that runner
     runners: Arc<RwLock<HashMap<uuid::Uuid, mpsc::Sender<ServerMessage>>>>,
+    // Map of runner_id to the latest metrics reported by that runner
+    metrics: Arc<RwLock<HashMap<uuid::Uuid, RunnerMetrics>>>,
 }
 
 impl RunnerState {
     pub fn new() -> Self {
         Self {
             runners: Arc::new(RwLock::new(HashMap::new())),
+            metrics: Arc::new(RwLock::new(HashMap::new())),
         }
     }
 
@@ -38,6 +46,52 @@ impl RunnerState {
     pub async fn unregister(&self, runner_id: &uuid::Uuid) {
         let mut runners = self.runners.write().await;
         runners.remove(runner_id);
+        drop(runners);
+        self.forget_metrics(runner_id).await;
+    }
+
+    // Unregister a runner's connection, unless it already reconnected with a new one
+    async fn unregister_connection(
+        &self,
+        runner_id: &uuid::Uuid,
+        tx: &mpsc::WeakSender<ServerMessage>,
+    ) {
+        let mut runners = self.runners.write().await;
+        let is_current = tx.upgrade().is_some_and(|tx| {
+            runners
+                .get(runner_id)
+                .is_some_and(|current| current.same_channel(&tx))
+        });
+        if is_current {
+            runners.remove(runner_id);
+            drop(runners);
+            self.forget_metrics(runner_id).await;
+        }
+    }
+
+    // Keep the latest metrics of a runner and export them
+    async fn record_metrics(&self, runner_id: uuid::Uuid, metrics: RunnerMetrics) {
+        super::metrics::record(&runner_id, &metrics);
+        let mut snapshots = self.metrics.write().await;
+        snapshots.insert(runner_id, metrics);
+    }
+
+    // Drop the metrics of a disconnected runner
+    async fn forget_metrics(&self, runner_id: &uuid::Uuid) {
+        let mut snapshots = self.metrics.write().await;
+        if let Some(metrics) = snapshots.remove(runner_id) {
+            super::metrics::remove(runner_id, &metrics);
+        }
+    }
+
+    /// Get the connected runners along with their latest metrics, if reported yet
+    pub async fn connected(&self) -> Vec<(uuid::Uuid, Option<RunnerMetrics>)> {
+        let runners = self.runners.read().await;
+        let snapshots = self.metrics.read().await;
+        runners
+            .keys()
+            .map(|runner_id| (*runner_id, snapshots.get(runner_id).cloned()))
+            .collect()
     }
 
     // Try to send a message to a runner
@@ -64,7 +118,7 @@ impl RunnerState {
         }
     }
 
-    /// Broadcast job availability to runners with matching platform
+    /// Broadcast job availability to runners with matching platform and labels
     pub async fn broadcast_job_available(
         &self,
         job: &crate::job::model::Job,
@@ -91,17 +145,23 @@ impl RunnerState {
         // Get platform as string for database query
         let job_platform_str = job.platform.to_string();
         let platform_for_logging = job_platform_str.clone();
+        let required_labels = job.required_labels.clone();
 
         // Spawn a task to find runners with matching platform and broadcast the job
         let notification_clone = notification.clone();
         let runner_state = self.clone();
         tokio::spawn(async move {
             if let Ok(conn) = pool_clone.get().await {
-                // Find runners with matching platform
+                // Find runners with matching platform and labels
                 let mut conn = conn;
 
-                if let Ok(matching_runners) =
-                    Runner::find_matching_platforms(&mut conn, &runner_ids, &job_platform_str).await
+                if let Ok(matching_runners) = Runner::find_matching(
+                    &mut conn,
+                    &runner_ids,
+                    &job_platform_str,
+                    &required_labels,
+                )
+                .await
                 {
                     let mut broadcast_count = 0;
 
@@ -142,9 +202,17 @@ impl RunnerState {
     }
 }
 
-#[utoipa::path(get, path = "/ws", responses((status = OK, body = ())))]
+#[utoipa::path(
+    get,
+    path = "/ws",
+    responses(
+        (status = OK, body = ()),
+        (status = 401, description = "Missing or invalid runner token")
+    )
+)]
 #[tracing::instrument(skip_all)]
 async fn handler(
+    RunnerAuth(token): RunnerAuth,
     ws: WebSocketUpgrade<ServerMessage, ClientMessage, TextJsonCodec>,
     headers: axum::http::HeaderMap,
     State(app_state): State<AppState>,
@@ -163,41 +231,100 @@ async fn handler(
         .parse()
         .unwrap_or(crate::job::model::Platform::X86_64Linux);
 
-    // Create a new runner with the platform information
-    let runner_result = Runner::new(&app_state.pool, platform).await;
+    // Labels advertise capabilities beyond the platform, e.g. "region=eu,hugepages"
+    let labels: Vec<String> = headers
+        .get("X-Runner-Labels")
+        .and_then(|value| value.to_str().ok())
+        .map(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|label| devenv_runner::protocol::is_valid_label(label))
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default();
 
-    if let Err(e) = runner_result {
-        tracing::error!("Failed to create runner: {}", e);
-        return axum::http::StatusCode::INTERNAL_SERVER_ERROR.into_response();
-    }
+    // Runners keep their id across reconnects, so their running jobs can be reconciled
+    let requested_id = headers
+        .get("X-Runner-Id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<uuid::Uuid>().ok());
+
+    // Register the runner with the platform information
+    let runner_result =
+        Runner::register(&app_state.pool, requested_id, platform, token.id, &labels).await;
 
-    let runner = runner_result.unwrap();
+    let runner = match runner_result {
+        Ok(Some(runner)) => runner,
+        Ok(None) => {
+            tracing::warn!(
+                "Runner id {:?} is registered with another token than {}",
+                requested_id,
+                token.name
+            );
+            return StatusCode::FORBIDDEN.into_response();
+        }
+        Err(e) => {
+            tracing::error!("Failed to create runner: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
     let runner_id = runner.id;
+    let cordoned = runner.cordoned_at.is_some();
     tracing::debug!(
-        "Created runner {} with platform {}",
+        "Created runner {} with platform {} and labels [{}] using token {}",
         runner_id,
-        platform_str
+        platform_str,
+        labels.join(", "),
+        token.name
     );
 
     // Return the WebSocketUpgrade
-    ws.on_upgrade(move |socket| spawn_runner(socket, app_state, runner_id, runner_state))
-        .into_response()
+    ws.on_upgrade(move |socket| {
+        spawn_runner(
+            socket,
+            app_state,
+            runner_id,
+            token.id,
+            labels,
+            cordoned,
+            runner_state,
+        )
+    })
+    .into_response()
 }
 
+/// How often the latest metrics of a runner are written to the database
+const METRICS_PERSIST_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
 async fn spawn_runner(
     mut socket: WebSocket<ServerMessage, ClientMessage, TextJsonCodec>,
     app_state: AppState,
     runner_id: uuid::Uuid,
+    token_id: uuid::Uuid,
+    labels: Vec<String>,
+    cordoned: bool,
     runner_state: RunnerState,
 ) {
     // Create a channel for sending messages to this specific runner
     let (tx, mut rx) = mpsc::channel::<ServerMessage>(32);
 
-    // Register the runner in the shared state
+    // Register the runner in the shared state, only keeping a weak sender so
+    // unregistering the runner closes the channel
+    let weak_tx = tx.downgrade();
     runner_state.register(runner_id, tx).await;
 
+    // Runners forget being cordoned when reconnecting
+    if cordoned {
+        socket.send(Message::Item(ServerMessage::Cordon)).await.ok();
+    }
+
     // Send initial job check on connection
-    check_and_send_job(&mut socket, &app_state, &runner_id).await;
+    check_and_send_job(&mut socket, &app_state, &runner_id, &labels).await;
+
+    // Persist the first metrics report right away
+    let mut metrics_persisted_at = tokio::time::Instant::now() - METRICS_PERSIST_INTERVAL;
 
     loop {
         tokio::select! {
@@ -209,10 +336,47 @@ async fn spawn_runner(
                             ClientMessage::ClaimJob { id, vm } => {
                                 let conn = &mut app_state.pool.get().await.unwrap();
                                 tracing::info!("Runner {} claiming job {}", runner_id, id);
-                                let rows = Job::claim_job_for_runner(conn, id, runner_id)
+                                // Jobs are broadcast to all runners, so the owner's limit is enforced here
+                                let claimed = crate::job::scheduler::claim(
+                                    conn,
+                                    id,
+                                    runner_id,
+                                    &labels,
+                                    lease_expires_at(&app_state),
+                                    app_state.config.job.max_concurrent_per_owner,
+                                )
+                                .await
+                                .unwrap_or_else(|e| {
+                                    tracing::error!("Failed to claim job {}: {}", id, e);
+                                    false
+                                });
+                                if claimed {
+                                    let config = match <JobGitHub as SourceControlIntegration>::job_config(
+                                        &app_state,
+                                        id,
+                                    )
                                     .await
-                                    .unwrap_or(0);
-                                if rows == 1 {
+                                    {
+                                        Ok(config) => config,
+                                        Err(e) => {
+                                            tracing::error!("Failed to build config for job {}: {}", id, e);
+                                            // Such as failing to mint its token, which isn't the job's fault
+                                            let status = devenv_runner::protocol::JobStatus::Complete(
+                                                devenv_runner::protocol::CompletionStatus::InfrastructureFailure,
+                                            );
+                                            Job::update_job_status(conn, id, &JobStatus(status.clone()))
+                                                .await
+                                                .ok();
+                                            <JobGitHub as SourceControlIntegration>::update_status(
+                                                app_state.clone(),
+                                                status,
+                                                id,
+                                            )
+                                            .await
+                                            .ok();
+                                            continue;
+                                        }
+                                    };
                                     <JobGitHub as SourceControlIntegration>::update_status(
                                         app_state.clone(),
                                         devenv_runner::protocol::JobStatus::Running,
@@ -222,38 +386,112 @@ async fn spawn_runner(
                                     .ok();
                                     // Get the logger URL for this job
                                     let logger_url = format!("{}/{}", app_state.config.logger_url, id);
+                                    let artifacts_url = app_state
+                                        .config
+                                        .base_url
+                                        .join(&format!("api/v1/job/{id}/artifacts"))
+                                        .expect("valid artifacts url");
 
                                     socket
                                         .send(Message::Item(ServerMessage::JobClaimed {
                                             id,
                                             vm,
                                             log_url: std::str::FromStr::from_str(&logger_url).unwrap(),
+                                            artifacts_url,
+                                            config,
                                         }))
                                         .await
                                         .ok();
                                 }
                             }
                             ClientMessage::UpdateJobStatus { id, status } => {
-                                // TODO: authorize job_id against runner
                                 let conn = &mut app_state.pool.get().await.unwrap();
-                                Job::update_job_status(conn, id, &JobStatus(status.clone()))
+                                let accepted = match Job::get_by_id(conn, id).await {
+                                    Ok(job) if job.runner_id != Some(runner_id) => {
+                                        tracing::warn!(
+                                            "Runner {} tried to update job {} it doesn't own",
+                                            runner_id,
+                                            id
+                                        );
+                                        false
+                                    }
+                                    // Cancelled or timed out jobs were already completed by the backend
+                                    Ok(job) if matches!(job.status.0, devenv_runner::protocol::JobStatus::Complete(_)) => {
+                                        tracing::debug!("Ignoring status update for completed job {}", id);
+                                        false
+                                    }
+                                    Ok(_) => true,
+                                    Err(e) => {
+                                        tracing::warn!("Runner {} updated unknown job {}: {}", runner_id, id, e);
+                                        false
+                                    }
+                                };
+
+                                if accepted {
+                                    Job::update_job_status(conn, id, &JobStatus(status.clone()))
+                                        .await
+                                        .ok();
+
+                                    <JobGitHub as SourceControlIntegration>::update_status(
+                                        app_state.clone(),
+                                        status,
+                                        id,
+                                    )
                                     .await
                                     .ok();
+                                }
 
-                                <JobGitHub as SourceControlIntegration>::update_status(
-                                    app_state.clone(),
-                                    status,
-                                    id,
+                                // Check for new jobs only after a job status update (VM finished)
+                                check_and_send_job(&mut socket, &app_state, &runner_id, &labels).await;
+                            }
+                            ClientMessage::ReconcileJobs { ids } => {
+                                reconcile_jobs(&mut socket, &app_state, runner_id, token_id, ids).await;
+                            }
+                            ClientMessage::Heartbeat { jobs } => {
+                                let conn = &mut app_state.pool.get().await.unwrap();
+                                let ids: Vec<uuid::Uuid> = jobs.iter().map(|job| job.id).collect();
+                                let started: Vec<uuid::Uuid> =
+                                    jobs.iter().filter(|job| job.started).map(|job| job.id).collect();
+                                if let Err(e) = Job::renew_leases(
+                                    conn,
+                                    runner_id,
+                                    &ids,
+                                    &started,
+                                    lease_expires_at(&app_state),
                                 )
                                 .await
-                                .ok();
-
-                                // Check for new jobs only after a job status update (VM finished)
-                                check_and_send_job(&mut socket, &app_state, &runner_id).await;
+                                {
+                                    tracing::error!("Failed to renew leases of runner {}: {}", runner_id, e);
+                                }
+                            }
+                            ClientMessage::TaskStarted { id, name } => {
+                                let conn = &mut app_state.pool.get().await.unwrap();
+                                match JobStep::start(conn, id, runner_id, &name).await {
+                                    Ok(true) => tracing::debug!("Job {} started task {}", id, name),
+                                    Ok(false) => tracing::warn!(
+                                        "Runner {} reported a task of job {} it doesn't own",
+                                        runner_id,
+                                        id
+                                    ),
+                                    Err(e) => tracing::error!("Failed to record task {} of job {}: {}", name, id, e),
+                                }
+                            }
+                            ClientMessage::TaskFinished { id, name, success, duration_ms } => {
+                                let conn = &mut app_state.pool.get().await.unwrap();
+                                match JobStep::finish(conn, id, runner_id, &name, success, duration_ms as i64).await {
+                                    Ok(true) => tracing::debug!("Job {} finished task {}", id, name),
+                                    Ok(false) => tracing::warn!(
+                                        "Runner {} finished task {} of job {} which isn't running",
+                                        runner_id,
+                                        name,
+                                        id
+                                    ),
+                                    Err(e) => tracing::error!("Failed to record outcome of task {} of job {}: {}", name, id, e),
+                                }
                             }
                             ClientMessage::RequestJob => {
                                 // Runner has capacity and is requesting a job
-                                check_and_send_job(&mut socket, &app_state, &runner_id).await;
+                                check_and_send_job(&mut socket, &app_state, &runner_id, &labels).await;
                             }
                             ClientMessage::ReportMetrics(metrics) => {
                                 // Log runner metrics as a tracing event
@@ -272,6 +510,16 @@ async fn spawn_runner(
                                     max_instances = ?metrics.max_instances,
                                     "Runner metrics report"
                                 );
+
+                                // Metrics are reported every second, the database only needs a recent snapshot
+                                if metrics_persisted_at.elapsed() >= METRICS_PERSIST_INTERVAL {
+                                    metrics_persisted_at = tokio::time::Instant::now();
+                                    let conn = &mut app_state.pool.get().await.unwrap();
+                                    if let Err(e) = Runner::save_metrics(conn, runner_id, &metrics).await {
+                                        tracing::error!("Failed to save metrics of runner {}: {}", runner_id, e);
+                                    }
+                                }
+                                runner_state.record_metrics(runner_id, metrics).await;
                             }
                         }
                     }
@@ -301,18 +549,174 @@ async fn spawn_runner(
     }
 
     // Unregister the runner when the connection closes
-    runner_state.unregister(&runner_id).await;
+    runner_state
+        .unregister_connection(&runner_id, &weak_tx)
+        .await;
+    if let Err(e) = Runner::disconnected(runner_id, &app_state.pool).await {
+        tracing::error!("Failed to mark runner {} as disconnected: {}", runner_id, e);
+    }
+}
+
+/// When the lease of a job claimed now expires without heartbeats
+fn lease_expires_at(app_state: &AppState) -> chrono::DateTime<chrono::Utc> {
+    chrono::Utc::now() + chrono::Duration::seconds(app_state.config.job.lease_seconds as i64)
+}
+
+/// Recover a job its runner lost
+///
+/// Jobs the guest didn't start yet are queued again, others are failed with an
+/// infrastructure error as they may have had side effects, which retries them
+/// as new jobs.
+async fn recover_lost_job(app_state: &AppState, job: &Job, reason: &str) {
+    let Some(runner_id) = job.runner_id else {
+        return;
+    };
+    let conn = &mut app_state.pool.get().await.unwrap();
+
+    match Job::requeue(conn, job.id, runner_id).await {
+        Ok(true) => {
+            tracing::info!("Requeueing job {}: {}", job.id, reason);
+            <JobGitHub as SourceControlIntegration>::update_status(
+                app_state.clone(),
+                devenv_runner::protocol::JobStatus::Queued,
+                job.id,
+            )
+            .await
+            .ok();
+            notify_runners_about_job(app_state, job).await;
+        }
+        Ok(false) => {
+            tracing::warn!("Failing job {}: {}", job.id, reason);
+            let mut job = job.clone();
+            if let Err(e) = job
+                .complete(
+                    conn,
+                    devenv_runner::protocol::CompletionStatus::InfrastructureFailure,
+                )
+                .await
+            {
+                tracing::error!("Failed to fail lost job {}: {}", job.id, e);
+                return;
+            }
+            if let Err(e) =
+                JobGitHub::fail_with_infrastructure_error(app_state, job.id, reason).await
+            {
+                tracing::error!(
+                    "Failed to report infrastructure error for job {}: {}",
+                    job.id,
+                    e
+                );
+            }
+        }
+        Err(e) => tracing::error!("Failed to requeue job {}: {}", job.id, e),
+    }
+}
+
+/// Reconcile the jobs a runner reports to be executing with the database
+///
+/// Jobs the runner still executes are kept or adopted, jobs it lost are
+/// recovered and jobs that shouldn't run anymore are cancelled on the runner.
+async fn reconcile_jobs(
+    socket: &mut WebSocket<ServerMessage, ClientMessage, TextJsonCodec>,
+    app_state: &AppState,
+    runner_id: uuid::Uuid,
+    token_id: uuid::Uuid,
+    ids: Vec<uuid::Uuid>,
+) {
+    let conn = &mut app_state.pool.get().await.unwrap();
+
+    let running = match Job::find_running_for_runner(conn, runner_id).await {
+        Ok(running) => running,
+        Err(e) => {
+            tracing::error!("Failed to load running jobs of runner {}: {}", runner_id, e);
+            return;
+        }
+    };
+
+    // The runner lost these jobs, e.g. because it restarted
+    for job in running.iter().filter(|job| !ids.contains(&job.id)) {
+        recover_lost_job(
+            app_state,
+            job,
+            &format!("runner {} no longer executes it", runner_id),
+        )
+        .await;
+    }
+
+    let mut alive_ids = Vec::new();
+
+    for id in ids {
+        let job = match Job::get_by_id(conn, id).await {
+            Ok(job) => job,
+            Err(e) => {
+                tracing::warn!("Runner {} reported unknown job {}: {}", runner_id, id, e);
+                continue;
+            }
+        };
+        let alive = job.status.0 == devenv_runner::protocol::JobStatus::Running;
+
+        if alive && job.runner_id == Some(runner_id) {
+            tracing::debug!("Runner {} is still executing job {}", runner_id, id);
+            alive_ids.push(id);
+        } else if alive
+            && Job::adopt(conn, id, runner_id, token_id)
+                .await
+                .unwrap_or(false)
+        {
+            tracing::info!("Runner {} adopted job {}", runner_id, id);
+            alive_ids.push(id);
+        } else {
+            tracing::info!(
+                "Runner {} executes job {} which shouldn't run, cancelling",
+                runner_id,
+                id
+            );
+            socket
+                .send(Message::Item(ServerMessage::JobCancelled { id }))
+                .await
+                .ok();
+        }
+    }
+
+    // The runner was away for a while, so its leases may be about to lapse
+    if let Err(e) = Job::renew_leases(
+        conn,
+        runner_id,
+        &alive_ids,
+        &[],
+        lease_expires_at(app_state),
+    )
+    .await
+    {
+        tracing::error!("Failed to renew leases of runner {}: {}", runner_id, e);
+    }
 }
 
-/// Check for available jobs and send it to a runner if compatible with the runner's platform
+/// Check for available jobs and send it to a runner if compatible with the runner's platform and labels
 /// Return true if a job was found and sent
 async fn check_and_send_job(
     socket: &mut WebSocket<ServerMessage, ClientMessage, TextJsonCodec>,
     app_state: &AppState,
     runner_id: &uuid::Uuid,
+    labels: &[String],
 ) -> bool {
-    // Get the runner's platform
     let conn = &mut app_state.pool.get().await.unwrap();
+
+    // Cordoned runners aren't offered new jobs
+    match Runner::is_cordoned(conn, runner_id).await {
+        Ok(false) => {}
+        Ok(true) => return false,
+        Err(e) => {
+            tracing::error!(
+                "Failed to check whether runner {} is cordoned: {}",
+                runner_id,
+                e
+            );
+            return false;
+        }
+    }
+
+    // Get the runner's platform
     let runner_platform_result = Runner::get_platform(conn, runner_id).await;
 
     if let Err(e) = runner_platform_result {
@@ -326,8 +730,14 @@ async fn check_and_send_job(
         .parse()
         .unwrap_or(crate::job::model::Platform::X86_64Linux);
 
-    // Look for jobs matching the runner's platform
-    let job_result = Job::find_queued_job_for_platform(conn, &runner_platform).await;
+    // Let the scheduler pick among the jobs matching the runner's platform and labels
+    let job_result = crate::job::scheduler::next_job(
+        conn,
+        &runner_platform,
+        labels,
+        app_state.config.job.max_concurrent_per_owner,
+    )
+    .await;
 
     if let Ok(Some(job)) = job_result {
         // Create and send job notification
@@ -357,10 +767,12 @@ async fn job_timeout_checker(app_state: AppState, runner_state: RunnerState) {
         interval_timer.tick().await;
 
         let timeout_seconds = app_state.config.job.timeout_seconds;
+        let max_timeout_seconds = app_state.config.job.max_timeout_seconds;
 
         // Find jobs that have exceeded their timeout
         let conn = &mut app_state.pool.get().await.unwrap();
-        let expired_jobs_result = Job::find_expired_jobs(conn, timeout_seconds).await;
+        let expired_jobs_result =
+            Job::find_expired_jobs(conn, timeout_seconds, max_timeout_seconds).await;
 
         if let Ok(expired_jobs) = expired_jobs_result {
             for job in expired_jobs {
@@ -406,9 +818,375 @@ async fn job_timeout_checker(app_state: AppState, runner_state: RunnerState) {
     }
 }
 
+#[derive(serde::Deserialize, ToSchema)]
+struct CreateRunnerToken {
+    name: String,
+}
+
+#[derive(serde::Serialize, ToSchema)]
+struct CreatedRunnerToken {
+    #[serde(flatten)]
+    runner_token: RunnerToken,
+    /// The plaintext token, only returned once
+    token: String,
+}
+
+/// Create a runner registration token
+#[utoipa::path(
+    post,
+    path = "/tokens",
+    request_body = CreateRunnerToken,
+    responses(
+        (status = 200, description = "Token created", body = CreatedRunnerToken),
+        (status = 400, description = "Token name is empty"),
+        (status = 403, description = "Admin access required")
+    )
+)]
+#[tracing::instrument(skip_all)]
+async fn create_token(
+    _user: AdminUser,
+    State(app_state): State<AppState>,
+    Json(request): Json<CreateRunnerToken>,
+) -> Result<Response> {
+    let name = request.name.trim();
+    if name.is_empty() {
+        return Ok(StatusCode::BAD_REQUEST.into_response());
+    }
+
+    let conn = &mut app_state.pool.get().await?;
+    let (runner_token, token) = RunnerToken::create(conn, name).await?;
+    tracing::info!(
+        "Created runner token {} ({})",
+        runner_token.name,
+        runner_token.id
+    );
+
+    Ok(Json(CreatedRunnerToken {
+        runner_token,
+        token,
+    })
+    .into_response())
+}
+
+/// List runner registration tokens
+#[utoipa::path(
+    get,
+    path = "/tokens",
+    responses(
+        (status = 200, description = "Runner tokens", body = Vec<RunnerToken>),
+        (status = 403, description = "Admin access required")
+    )
+)]
+#[tracing::instrument(skip_all)]
+async fn list_tokens(
+    _user: AdminUser,
+    State(app_state): State<AppState>,
+) -> Result<Json<Vec<RunnerToken>>> {
+    let conn = &mut app_state.pool.get().await?;
+    Ok(Json(RunnerToken::list(conn).await?))
+}
+
+/// Revoke a runner registration token and disconnect the runners using it
+#[utoipa::path(
+    delete,
+    path = "/tokens/{id}",
+    responses(
+        (status = 204, description = "Token revoked"),
+        (status = 403, description = "Admin access required"),
+        (status = 404, description = "Token not found or already revoked")
+    ),
+    params(
+        ("id" = uuid::Uuid, Path, description = "The unique identifier of the token"),
+    )
+)]
+#[tracing::instrument(skip_all)]
+async fn revoke_token(
+    _user: AdminUser,
+    State(app_state): State<AppState>,
+    Path(id): Path<uuid::Uuid>,
+) -> Result<StatusCode> {
+    let conn = &mut app_state.pool.get().await?;
+    if !RunnerToken::revoke(conn, id).await? {
+        return Ok(StatusCode::NOT_FOUND);
+    }
+
+    // Dropping the runners' channels closes their connections
+    for runner_id in Runner::find_by_token(conn, id).await? {
+        app_state.runner_state.unregister(&runner_id).await;
+    }
+    tracing::info!("Revoked runner token {}", id);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// A connected runner and its latest reported metrics
+#[derive(serde::Serialize, ToSchema)]
+struct RunnerOverview {
+    id: uuid::Uuid,
+    platform: crate::job::model::Platform,
+    labels: Vec<String>,
+    /// Version of the runner, if it reported one
+    version: Option<String>,
+    last_seen_at: chrono::DateTime<chrono::Utc>,
+    /// When the runner stopped being offered new jobs
+    cordoned_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Capacity, utilization and jobs of the runner, missing until its first report
+    metrics: Option<RunnerMetrics>,
+}
+
+/// List connected runners with their capacity and utilization
+#[utoipa::path(
+    get,
+    path = "/",
+    responses(
+        (status = 200, description = "Connected runners", body = Vec<RunnerOverview>),
+        (status = 403, description = "Admin access required")
+    )
+)]
+#[tracing::instrument(skip_all)]
+async fn list_runners(
+    _user: AdminUser,
+    State(app_state): State<AppState>,
+) -> Result<Json<Vec<RunnerOverview>>> {
+    let mut connected: HashMap<uuid::Uuid, Option<RunnerMetrics>> = app_state
+        .runner_state
+        .connected()
+        .await
+        .into_iter()
+        .collect();
+    let runner_ids: Vec<uuid::Uuid> = connected.keys().cloned().collect();
+
+    let conn = &mut app_state.pool.get().await?;
+    let runners = Runner::get_with_labels(conn, &runner_ids).await?;
+
+    Ok(Json(
+        runners
+            .into_iter()
+            .map(|(runner, labels)| {
+                let metrics = connected.remove(&runner.id).flatten();
+                RunnerOverview {
+                    id: runner.id,
+                    platform: runner.platform,
+                    labels,
+                    version: metrics.as_ref().and_then(|metrics| metrics.version.clone()),
+                    last_seen_at: runner.last_seen_at,
+                    cordoned_at: runner.cordoned_at,
+                    metrics,
+                }
+            })
+            .collect(),
+    ))
+}
+
+/// Cordon a runner so it's no longer offered new jobs
+#[utoipa::path(
+    post,
+    path = "/{id}/cordon",
+    responses(
+        (status = 204, description = "Runner cordoned"),
+        (status = 403, description = "Admin access required"),
+        (status = 404, description = "Runner not found")
+    ),
+    params(
+        ("id" = uuid::Uuid, Path, description = "The unique identifier of the runner"),
+    )
+)]
+#[tracing::instrument(skip_all)]
+async fn cordon_runner(
+    _user: AdminUser,
+    State(app_state): State<AppState>,
+    Path(id): Path<uuid::Uuid>,
+) -> Result<StatusCode> {
+    let conn = &mut app_state.pool.get().await?;
+    if !Runner::set_cordoned(conn, id, true).await? {
+        return Ok(StatusCode::NOT_FOUND);
+    }
+
+    app_state
+        .runner_state
+        .try_send_to(&id, ServerMessage::Cordon)
+        .await;
+    tracing::info!("Cordoned runner {}", id);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Uncordon a runner so it's offered new jobs again
+#[utoipa::path(
+    post,
+    path = "/{id}/uncordon",
+    responses(
+        (status = 204, description = "Runner uncordoned"),
+        (status = 403, description = "Admin access required"),
+        (status = 404, description = "Runner not found")
+    ),
+    params(
+        ("id" = uuid::Uuid, Path, description = "The unique identifier of the runner"),
+    )
+)]
+#[tracing::instrument(skip_all)]
+async fn uncordon_runner(
+    _user: AdminUser,
+    State(app_state): State<AppState>,
+    Path(id): Path<uuid::Uuid>,
+) -> Result<StatusCode> {
+    let conn = &mut app_state.pool.get().await?;
+    if !Runner::set_cordoned(conn, id, false).await? {
+        return Ok(StatusCode::NOT_FOUND);
+    }
+
+    // The runner requests a job once it's uncordoned
+    app_state
+        .runner_state
+        .try_send_to(&id, ServerMessage::Uncordon)
+        .await;
+    tracing::info!("Uncordoned runner {}", id);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Drain a runner, letting it finish its jobs before it disconnects
+///
+/// The runner stays cordoned, so it isn't offered new jobs if it reconnects.
+#[utoipa::path(
+    post,
+    path = "/{id}/drain",
+    responses(
+        (status = 204, description = "Runner draining"),
+        (status = 403, description = "Admin access required"),
+        (status = 404, description = "Runner not found")
+    ),
+    params(
+        ("id" = uuid::Uuid, Path, description = "The unique identifier of the runner"),
+    )
+)]
+#[tracing::instrument(skip_all)]
+async fn drain_runner(
+    _user: AdminUser,
+    State(app_state): State<AppState>,
+    Path(id): Path<uuid::Uuid>,
+) -> Result<StatusCode> {
+    let conn = &mut app_state.pool.get().await?;
+    if !Runner::set_cordoned(conn, id, true).await? {
+        return Ok(StatusCode::NOT_FOUND);
+    }
+
+    app_state
+        .runner_state
+        .try_send_to(&id, ServerMessage::Drain)
+        .await;
+    tracing::info!("Draining runner {}", id);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Evict a runner, cancelling its jobs before it disconnects
+///
+/// The runner stays cordoned, so it isn't offered new jobs if it reconnects.
+#[utoipa::path(
+    post,
+    path = "/{id}/evict",
+    responses(
+        (status = 204, description = "Runner evicted"),
+        (status = 403, description = "Admin access required"),
+        (status = 404, description = "Runner not found")
+    ),
+    params(
+        ("id" = uuid::Uuid, Path, description = "The unique identifier of the runner"),
+    )
+)]
+#[tracing::instrument(skip_all)]
+async fn evict_runner(
+    _user: AdminUser,
+    State(app_state): State<AppState>,
+    Path(id): Path<uuid::Uuid>,
+) -> Result<StatusCode> {
+    let conn = &mut app_state.pool.get().await?;
+    if !Runner::set_cordoned(conn, id, true).await? {
+        return Ok(StatusCode::NOT_FOUND);
+    }
+
+    for job in Job::find_running_for_runner(conn, id).await? {
+        if let (true, _) = Job::cancel(conn, job.id).await? {
+            <JobGitHub as SourceControlIntegration>::update_status(
+                app_state.clone(),
+                devenv_runner::protocol::JobStatus::Complete(
+                    devenv_runner::protocol::CompletionStatus::Cancelled,
+                ),
+                job.id,
+            )
+            .await
+            .ok();
+        }
+    }
+
+    // The runner stops the VMs of its jobs and exits once they're gone
+    app_state
+        .runner_state
+        .try_send_to(&id, ServerMessage::Evict)
+        .await;
+    tracing::info!("Evicted runner {}", id);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
 pub fn router() -> OpenApiRouter<AppState> {
     // We'll use the RunnerState from AppState instead of creating it here
-    OpenApiRouter::new().routes(routes!(handler))
+    OpenApiRouter::new()
+        .routes(routes!(list_runners))
+        .routes(routes!(cordon_runner))
+        .routes(routes!(uncordon_runner))
+        .routes(routes!(drain_runner))
+        .routes(routes!(evict_runner))
+        .routes(routes!(handler))
+        .routes(routes!(create_token, list_tokens))
+        .routes(routes!(revoke_token))
+}
+
+// Task that periodically recovers jobs whose runner stopped heartbeating
+async fn job_lease_checker(app_state: AppState, runner_state: RunnerState) {
+    let mut interval_timer = tokio::time::interval(tokio::time::Duration::from_secs(15));
+
+    loop {
+        interval_timer.tick().await;
+
+        let lapsed = {
+            let conn = &mut app_state.pool.get().await.unwrap();
+            Job::find_lapsed_leases(conn).await
+        };
+        let lapsed = match lapsed {
+            Ok(lapsed) => lapsed,
+            Err(e) => {
+                tracing::error!("Failed to find jobs with lapsed leases: {}", e);
+                continue;
+            }
+        };
+
+        for job in lapsed {
+            let Some(runner_id) = job.runner_id else {
+                continue;
+            };
+            // The runner may still be connected but stuck, it mustn't continue the job
+            runner_state
+                .try_send_to(&runner_id, ServerMessage::JobCancelled { id: job.id })
+                .await;
+            recover_lost_job(
+                &app_state,
+                &job,
+                &format!("the lease of runner {} lapsed", runner_id),
+            )
+            .await;
+        }
+    }
+}
+
+// Start the job lease checker task with the AppState
+pub fn start_job_lease_checker(app_state: AppState) {
+    let runner_state = app_state.runner_state.clone();
+
+    tokio::spawn(async move {
+        job_lease_checker(app_state, runner_state).await;
+    });
 }
 
 // Start the job timeout checker task with the AppState
@@ -423,6 +1201,13 @@ pub fn start_job_timeout_checker(app_state: AppState) {
 
 // Public function to notify compatible runners about a new job
 pub async fn notify_runners_about_job(app_state: &AppState, job: &crate::job::model::Job) {
+    // Runners never see jobs awaiting approval
+    if matches!(
+        job.status.0,
+        devenv_runner::protocol::JobStatus::AwaitingApproval
+    ) {
+        return;
+    }
     app_state
         .runner_state
         .broadcast_job_available(job, &app_state.pool)
@@ -14,6 +14,7 @@ diesel::table! {
         git_ref -> Text,
         author -> Text,
         message -> Text,
+        trusted -> Bool,
     }
 }
 
@@ -39,6 +40,8 @@ diesel::table! {
         name -> Text,
         instance_id -> Int4,
         is_user -> Bool,
+        max_concurrent_jobs -> Nullable<Int4>,
+        share_weight -> Int4,
     }
 }
 
@@ -53,6 +56,63 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    github_secrets (id) {
+        id -> Uuid,
+        owner_id -> Int8,
+        repo_id -> Nullable<Int8>,
+        name -> Text,
+        nonce -> Bytea,
+        ciphertext -> Bytea,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    github_schedules (id) {
+        id -> Uuid,
+        repo_id -> Int8,
+        cron -> Text,
+        branch -> Text,
+        next_run_at -> Timestamptz,
+        last_run_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+        catch_up -> Nullable<Bool>,
+    }
+}
+
+diesel::table! {
+    job_artifacts (id) {
+        id -> Uuid,
+        job_id -> Uuid,
+        name -> Text,
+        size_bytes -> Int8,
+        created_at -> Timestamptz,
+        expires_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    job_dependencies (job_id, depends_on_job_id) {
+        job_id -> Uuid,
+        depends_on_job_id -> Uuid,
+        satisfied -> Bool,
+    }
+}
+
+diesel::table! {
+    job_steps (id) {
+        id -> Uuid,
+        job_id -> Uuid,
+        name -> Text,
+        started_at -> Timestamptz,
+        finished_at -> Nullable<Timestamptz>,
+        success -> Nullable<Bool>,
+        duration_ms -> Nullable<Int8>,
+    }
+}
+
 diesel::table! {
     jobs (id) {
         id -> Uuid,
@@ -66,6 +126,17 @@ diesel::table! {
         retried_job_id -> Nullable<Uuid>,
         created_at -> Timestamptz,
         previous_job_id -> Nullable<Uuid>,
+        artifacts -> Array<Text>,
+        name -> Text,
+        tasks -> Nullable<Array<Text>>,
+        priority -> Int4,
+        required_labels -> Array<Text>,
+        lease_expires_at -> Nullable<Timestamptz>,
+        guest_started_at -> Nullable<Timestamptz>,
+        secrets -> Array<Text>,
+        trigger -> Text,
+        timeout_seconds -> Nullable<Int4>,
+        approved_at -> Nullable<Timestamptz>,
     }
 }
 
@@ -77,12 +148,33 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    runner_tokens (id) {
+        id -> Uuid,
+        name -> Text,
+        token_hash -> Text,
+        created_at -> Timestamptz,
+        last_used_at -> Nullable<Timestamptz>,
+        revoked_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    runner_labels (runner_id, label) {
+        runner_id -> Uuid,
+        label -> Text,
+    }
+}
+
 diesel::table! {
     runners (id) {
         id -> Uuid,
         last_seen_at -> Timestamptz,
         platform -> Text,
         created_at -> Timestamptz,
+        token_id -> Nullable<Uuid>,
+        metrics -> Nullable<Jsonb>,
+        cordoned_at -> Nullable<Timestamptz>,
     }
 }
 
@@ -90,8 +182,15 @@ diesel::joinable!(github_commit -> github_repo (repo_id));
 diesel::joinable!(github_installation -> github_owner (owner_id));
 diesel::joinable!(github_owner -> github_instance (instance_id));
 diesel::joinable!(github_repo -> github_owner (owner_id));
+diesel::joinable!(github_schedules -> github_repo (repo_id));
+diesel::joinable!(github_secrets -> github_owner (owner_id));
+diesel::joinable!(github_secrets -> github_repo (repo_id));
+diesel::joinable!(job_artifacts -> jobs (job_id));
+diesel::joinable!(job_steps -> jobs (job_id));
 diesel::joinable!(jobs_github -> github_commit (commit_id));
 diesel::joinable!(jobs_github -> jobs (job_id));
+diesel::joinable!(runner_labels -> runners (runner_id));
+diesel::joinable!(runners -> runner_tokens (token_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
     accounts,
@@ -100,7 +199,14 @@ diesel::allow_tables_to_appear_in_same_query!(
     github_instance,
     github_owner,
     github_repo,
+    github_schedules,
+    github_secrets,
+    job_artifacts,
+    job_dependencies,
+    job_steps,
     jobs,
     jobs_github,
+    runner_labels,
+    runner_tokens,
     runners,
 );
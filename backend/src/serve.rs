@@ -47,8 +47,15 @@ pub fn router() -> OpenApiRouter<AppState> {
 }
 
 async fn serve(app_state: AppState) -> Result<()> {
-    // Start the job timeout checker
+    // Start the job timeout and lease checkers
     crate::runner::serve::start_job_timeout_checker(app_state.clone());
+    crate::runner::serve::start_job_lease_checker(app_state.clone());
+
+    // Start the runs of the schedules declared in devenv.yaml
+    crate::github::schedule::start_schedule_checker(app_state.clone());
+
+    // Start the expired artifact cleanup
+    crate::job::serve::start_artifact_cleanup(app_state.clone());
 
     let addr = format!("0.0.0.0:{}", app_state.config.port);
     tracing::info!("Listening on {}", addr);
@@ -118,6 +118,9 @@ getStatusInfo status =
         Api.JobStatusJobStatusOneOf Api.JobStatusOneOfQueued ->
             ( "Queued", "bg-gray-200 dark:bg-dark-surface", Icons.queued [] )
 
+        Api.JobStatusJobStatusOneOf3 Api.JobStatusOneOf3AwaitingApproval ->
+            ( "Awaiting Approval", "bg-yellow-100 dark:bg-yellow-900/30", Icons.queued [] )
+
         Api.JobStatusJobStatusOneOf2 statusOneOf ->
             case statusOneOf.complete of
                 Api.CompletionStatusSuccess ->
@@ -135,6 +138,9 @@ getStatusInfo status =
                 Api.CompletionStatusSkipped ->
                     ( "Skipped", "bg-gray-200 dark:bg-dark-surface", Icons.skipped [] )
 
+                Api.CompletionStatusInfrastructureFailure ->
+                    ( "Infrastructure Error", "bg-yellow-100 dark:bg-yellow-900/30", Icons.failed [] )
+
 
 {-| Returns a readable string for a platform
 -}
@@ -233,12 +239,18 @@ viewJob owner repo now cancelResponse msgWrapper model errorMessage jobResponse
                         Api.CompletionStatusSkipped ->
                             "Skipped"
 
+                        Api.CompletionStatusInfrastructureFailure ->
+                            "Infrastructure Error"
+
                 Api.JobStatusJobStatusOneOf Api.JobStatusOneOfQueued ->
                     "Queued"
 
                 Api.JobStatusJobStatusOneOf1 Api.JobStatusOneOf1Running ->
                     "Running"
 
+                Api.JobStatusJobStatusOneOf3 Api.JobStatusOneOf3AwaitingApproval ->
+                    "Awaiting Approval"
+
         isQueued =
             statusText == "Queued"
 
@@ -251,7 +263,7 @@ viewJob owner repo now cancelResponse msgWrapper model errorMessage jobResponse
         durationText =
             formatJobDuration job.startedAt job.finishedAt now
 
-        -- Show cancel button for running and queued jobs
+        -- Show cancel button for running, queued and awaiting jobs
         showCancelButton =
             case job.status of
                 Api.JobStatusJobStatusOneOf1 Api.JobStatusOneOf1Running ->
@@ -260,6 +272,9 @@ viewJob owner repo now cancelResponse msgWrapper model errorMessage jobResponse
                 Api.JobStatusJobStatusOneOf Api.JobStatusOneOfQueued ->
                     True
 
+                Api.JobStatusJobStatusOneOf3 Api.JobStatusOneOf3AwaitingApproval ->
+                    True
+
                 _ ->
                     False
 
@@ -271,7 +286,7 @@ viewJob owner repo now cancelResponse msgWrapper model errorMessage jobResponse
                         Api.CompletionStatusSuccess ->
                             False
 
-                        -- Show retry for all other completion statuses (Failed, Cancelled, TimedOut, Skipped)
+                        -- Show retry for all other completion statuses (Failed, Cancelled, TimedOut, Skipped, InfrastructureFailure)
                         _ ->
                             True
 
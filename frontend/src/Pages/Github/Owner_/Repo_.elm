@@ -52,6 +52,7 @@ page user shared route =
 
 type alias Model =
     { repoJobs : WebData Api.RepoJobs
+    , moreJobs : WebData Api.RepoJobs
     , owner : String
     , repo : String
     , commitJobsModel : Components.CommitJobs.Model
@@ -61,6 +62,7 @@ type alias Model =
 init : Route { owner : String, repo : String } -> () -> ( Model, Effect Msg )
 init route () =
     ( { repoJobs = RemoteData.Loading
+      , moreJobs = RemoteData.NotAsked
       , owner = route.params.owner
       , repo = route.params.repo
       , commitJobsModel = Components.CommitJobs.init
@@ -71,12 +73,22 @@ init route () =
 
 getJobsForRepo : String -> String -> Effect Msg
 getJobsForRepo owner repo =
-    Api.getRepoJobs owner repo
+    Api.getRepoJobs owner repo Nothing Nothing
         |> Effect.sendApi (RemoteData.fromResult >> RepoJobsResponse)
 
 
+{-| Get the page of jobs older than the cursor
+-}
+getMoreJobsForRepo : String -> String -> Uuid -> Effect Msg
+getMoreJobsForRepo owner repo cursor =
+    Api.getRepoJobs owner repo (Just cursor) Nothing
+        |> Effect.sendApi (RemoteData.fromResult >> MoreJobsResponse)
+
+
 type Msg
     = RepoJobsResponse (WebData Api.RepoJobs)
+    | LoadMore
+    | MoreJobsResponse (WebData Api.RepoJobs)
     | Refresh
     | CommitJobsMsg Components.CommitJobs.Msg
     | ScrollComplete -- When scrolling to an element is complete
@@ -88,11 +100,46 @@ update msg model =
     case msg of
         RepoJobsResponse response ->
             let
-                -- Always keep targetJobId for direct opening on initial load
-                updatedModel =
-                    { model | repoJobs = response }
+                -- Refreshes only get the newest page, keep the older jobs loaded before
+                repoJobs =
+                    case ( model.repoJobs, response ) of
+                        ( RemoteData.Success loaded, RemoteData.Success newest ) ->
+                            RemoteData.Success
+                                { newest
+                                    | commits = mergeCommits model.owner model.repo newest.commits loaded.commits
+                                    , nextCursor = loaded.nextCursor
+                                }
+
+                        _ ->
+                            response
+            in
+            ( { model | repoJobs = repoJobs }, Effect.none )
+
+        LoadMore ->
+            case RemoteData.toMaybe model.repoJobs |> Maybe.andThen .nextCursor of
+                Just cursor ->
+                    ( { model | moreJobs = RemoteData.Loading }
+                    , getMoreJobsForRepo model.owner model.repo cursor
+                    )
+
+                Nothing ->
+                    ( model, Effect.none )
+
+        MoreJobsResponse response ->
+            let
+                repoJobs =
+                    case ( model.repoJobs, response ) of
+                        ( RemoteData.Success loaded, RemoteData.Success older ) ->
+                            RemoteData.Success
+                                { loaded
+                                    | commits = mergeCommits model.owner model.repo loaded.commits older.commits
+                                    , nextCursor = older.nextCursor
+                                }
+
+                        _ ->
+                            model.repoJobs
             in
-            ( updatedModel, Effect.none )
+            ( { model | repoJobs = repoJobs, moreJobs = response }, Effect.none )
 
         Refresh ->
             -- Don't clear existing view states or target job ID when refreshing
@@ -170,6 +217,56 @@ update msg model =
 
 
 
+{-| Merge the commits of two pages of jobs, preferring the jobs of the newer one
+
+A commit whose jobs span both pages is shown once, with all of them.
+
+-}
+mergeCommits : String -> String -> List Api.Commit -> List Api.Commit -> List Api.Commit
+mergeCommits owner repo newer older =
+    let
+        jobsById commits =
+            commits
+                |> List.concatMap .jobs
+                |> List.map (\jobResponse -> ( Uuid.toString jobResponse.job.id, jobResponse ))
+                |> Dict.fromList
+
+        -- Job ids are time ordered, so the newest jobs come first
+        jobs =
+            Dict.union (jobsById newer) (jobsById older)
+                |> Dict.values
+                |> List.reverse
+    in
+    List.foldr
+        (\jobResponse commits ->
+            case commits of
+                commit :: rest ->
+                    if List.any (\other -> other.commit.id == jobResponse.commit.id) (List.take 1 commit.jobs) then
+                        { commit | jobs = jobResponse :: commit.jobs } :: rest
+
+                    else
+                        toCommit owner repo jobResponse :: commits
+
+                [] ->
+                    [ toCommit owner repo jobResponse ]
+        )
+        []
+        jobs
+
+
+toCommit : String -> String -> Api.JobResponse -> Api.Commit
+toCommit owner repo jobResponse =
+    { author = jobResponse.commit.author
+    , jobs = [ jobResponse ]
+    , message = jobResponse.commit.message
+    , owner = owner
+    , ref = jobResponse.commit.ref
+    , repo = repo
+    , rev = jobResponse.commit.rev
+    }
+
+
+
 -- Helper function to scroll to an element by ID with an offset
 
 
@@ -237,18 +334,40 @@ viewRepoJobs params shared model selectedJobId repoJobs =
                 ]
 
         _ ->
+            let
+                commitViews =
+                    List.map
+                        (\commit ->
+                            -- For each commit, use the CommitJobs component
+                            Html.map CommitJobsMsg
+                                (Components.CommitJobs.commitJobsView
+                                    { owner = params.owner, repo = params.repo, rev = commit.rev }
+                                    shared
+                                    model.commitJobsModel
+                                    selectedJobId
+                                    commit
+                                )
+                        )
+                        repoJobs.commits
+            in
             div [ class "space-y-4" ]
-                (List.map
-                    (\commit ->
-                        -- For each commit, use the CommitJobs component
-                        Html.map CommitJobsMsg
-                            (Components.CommitJobs.commitJobsView
-                                { owner = params.owner, repo = params.repo, rev = commit.rev }
-                                shared
-                                model.commitJobsModel
-                                selectedJobId
-                                commit
-                            )
-                    )
-                    repoJobs.commits
-                )
+                (commitViews ++ [ viewLoadMore model repoJobs ])
+
+
+viewLoadMore : Model -> Api.RepoJobs -> Html Msg
+viewLoadMore model repoJobs =
+    case repoJobs.nextCursor of
+        Just _ ->
+            div [ class "flex justify-center" ]
+                [ Button.new
+                    { label = "Load more"
+                    , action = Button.Click LoadMore
+                    , icon = Nothing
+                    }
+                    |> Button.asSecondary
+                    |> Button.withLoadingResponse model.moreJobs
+                    |> Button.view
+                ]
+
+        Nothing ->
+            text ""
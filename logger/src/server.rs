@@ -1,6 +1,7 @@
 use axum::{
-    BoxError, Router,
-    extract::Path,
+    BoxError, Json, Router,
+    extract::{Path, Query},
+    http::StatusCode,
     response::{
         IntoResponse, Result,
         sse::{Event, KeepAlive, Sse},
@@ -24,6 +25,13 @@ pub struct AppState {
     pub db: Arc<Db>,
 }
 
+/// Lines returned by the tail of a log unless requested otherwise
+const DEFAULT_TAIL_LINES: usize = 100;
+/// Most lines returned by the tail of a log
+const MAX_TAIL_LINES: usize = 1000;
+/// How long the tail of a log waits for the rest of it to come in
+const TAIL_WAIT: std::time::Duration = std::time::Duration::from_secs(5);
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct LogWithLine {
     message: String,
@@ -32,6 +40,14 @@ struct LogWithLine {
     line: u64,
 }
 
+#[derive(Debug, Deserialize)]
+struct TailQuery {
+    lines: Option<usize>,
+    /// Wait until the whole log is in, for jobs that completed
+    #[serde(default)]
+    wait: bool,
+}
+
 pub fn create_app(state: Arc<AppState>) -> Router {
     let cors = CorsLayer::new()
         .allow_origin(cors::Any)
@@ -46,6 +62,7 @@ pub fn create_app(state: Arc<AppState>) -> Router {
     Router::new()
         .route("/{uuid}", post(post_logs))
         .route("/{uuid}", get(get_logs))
+        .route("/{uuid}/tail", get(get_tail))
         .layer(cors)
         .with_state(state)
 }
@@ -97,6 +114,22 @@ async fn post_logs(
         }
     }
 
+    // Mark the log as complete once all of its lines are durable
+    let write_options = slatedb::config::WriteOptions {
+        await_durable: true,
+    };
+    if let Err(e) = db
+        .put_with_options(
+            &stream::end_key(uuid),
+            &line_counter.to_be_bytes(),
+            &slatedb::config::PutOptions::default(),
+            &write_options,
+        )
+        .await
+    {
+        eprintln!("Failed to mark the end of the log of {uuid}: {e:?}");
+    }
+
     let elapsed = start_time.elapsed();
     eprintln!(
         "Received {} logs in {:?} ({:.0} logs/sec)",
@@ -106,6 +139,102 @@ async fn post_logs(
     );
 }
 
+/// Return the last lines of a log
+///
+/// Unless asked to wait for the rest of the log, these are the last lines
+/// written so far.
+async fn get_tail(
+    state: axum::extract::State<Arc<AppState>>,
+    Path(uuid): axum::extract::Path<Uuid>,
+    Query(query): Query<TailQuery>,
+) -> Result<Json<Vec<LogWithLine>>, StatusCode> {
+    let lines = query
+        .lines
+        .unwrap_or(DEFAULT_TAIL_LINES)
+        .clamp(1, MAX_TAIL_LINES);
+
+    let db = &state.db;
+    let mut end = read_end(db, uuid).await?;
+    if query.wait {
+        let deadline = tokio::time::Instant::now() + TAIL_WAIT;
+        while end.is_none() && tokio::time::Instant::now() < deadline {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            end = read_end(db, uuid).await?;
+        }
+    }
+    let last = match end {
+        Some(last) => last,
+        None => last_line(db, uuid).await?,
+    };
+
+    // Only the lines of the tail are read, starting from the end
+    let first = last.saturating_sub(lines as u64) + 1;
+    let key = stream::LogLineKey::from_parts(uuid, first);
+    let mut db_iter = db
+        .scan(key.as_bytes()..key.range_max())
+        .await
+        .map_err(|e| {
+            eprintln!("Failed to scan logs of {uuid}: {e:?}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let mut tail = Vec::with_capacity(lines);
+    while tail.len() < lines {
+        let Ok(Some(item)) = db_iter.next().await else {
+            break;
+        };
+        match serde_json::from_slice::<LogWithLine>(&item.value) {
+            Ok(log) => tail.push(log),
+            Err(e) => eprintln!("Failed to parse stored log: {e:?}"),
+        }
+    }
+
+    Ok(Json(tail))
+}
+
+/// Number of lines of a complete log, `None` while it's still coming in
+async fn read_end(db: &Db, uuid: Uuid) -> Result<Option<u64>, StatusCode> {
+    let end = db.get(stream::end_key(uuid)).await.map_err(|e| {
+        eprintln!("Failed to read the end of the log of {uuid}: {e:?}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(end.and_then(|end| Some(u64::from_be_bytes(end.as_ref().try_into().ok()?))))
+}
+
+/// Number of the last line of a log still coming in
+///
+/// Lines are numbered from 1 without gaps, so the last one is found by
+/// probing for lines rather than reading all of them.
+async fn last_line(db: &Db, uuid: Uuid) -> Result<u64, StatusCode> {
+    if !has_line(db, uuid, 1).await? {
+        return Ok(0);
+    }
+    // The line at `low` exists, the one at `high` doesn't
+    let (mut low, mut high) = (1, 2);
+    while has_line(db, uuid, high).await? {
+        low = high;
+        high *= 2;
+    }
+    while high - low > 1 {
+        let middle = low + (high - low) / 2;
+        if has_line(db, uuid, middle).await? {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    Ok(low)
+}
+
+async fn has_line(db: &Db, uuid: Uuid, line: u64) -> Result<bool, StatusCode> {
+    let key = stream::LogLineKey::from_parts(uuid, line);
+    let value = db.get(key.as_bytes()).await.map_err(|e| {
+        eprintln!("Failed to read line {line} of the log of {uuid}: {e:?}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(value.is_some())
+}
+
 async fn get_logs(
     state: axum::extract::State<Arc<AppState>>,
     Path(uuid): axum::extract::Path<Uuid>,
@@ -53,6 +53,13 @@ impl std::fmt::Display for LogLineKey {
     }
 }
 
+/// Key of the number of lines of a log, written once all of them are
+///
+/// It sorts after the lines of the log, so scans of the lines skip it.
+pub(crate) fn end_key(uuid: Uuid) -> Bytes {
+    format!("{uuid}/end").into()
+}
+
 pub(crate) struct DbFetcher {
     db: Arc<Db>,
     uuid: Uuid,
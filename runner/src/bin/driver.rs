@@ -1,15 +1,19 @@
 use color_eyre::eyre::{Result, WrapErr, eyre};
 use devenv::{Config, Devenv, DevenvOptions, GlobalOptions};
 use devenv_runner::protocol::JobConfig;
-use devenv_runner::vsock::{self, VsockWriter};
+use devenv_runner::vsock::{self, JobReporter, VsockWriter};
+use devenv_tasks::RunMode;
+use gix::refs::transaction::{Change, LogChange, PreviousValue, RefEdit};
 use gix::remote::fetch::Shallow;
 #[cfg(target_os = "linux")]
 use nix::unistd::{Gid, Uid, setgid, setuid};
 #[cfg(target_os = "linux")]
 use pid1::Pid1Settings;
+use std::io::Read;
 use std::num::NonZeroU32;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::sync::Mutex;
 use tokio_shutdown::Shutdown;
 use tracing_subscriber::prelude::*;
@@ -21,6 +25,9 @@ const PROJECT_DIR: &str = "/home/devenv";
 #[cfg(target_os = "macos")]
 const PROJECT_DIR: &str = "/Users/admin/devenv";
 
+// Size of the chunks artifacts are streamed to the host in
+const ARTIFACT_CHUNK_SIZE: usize = 64 * 1024;
+
 #[tokio::main]
 async fn main() -> Result<()> {
     color_eyre::install()?;
@@ -162,16 +169,33 @@ async fn main() -> Result<()> {
         .wrap_err_with(|| format!("Failed to create {} as devenv user", project_dir.display()))?;
 
     // Clone or update the repository as devenv user
-    clone_repository(&job_config, &project_dir)?;
+    // A repository that fails to clone isn't a failure of the job's tasks
+    if let Err(e) = clone_repository(&job_config, &project_dir) {
+        tracing::error!("Failed to clone the repository: {:?}", e);
+        let mut reporter_guard = reporter_arc.lock().await;
+        if let Err(e) = reporter_guard.report_setup_failed(format!("{:#}", e)).await {
+            tracing::error!("Failed to report setup failure to host: {:?}", e);
+        }
+        drop(reporter_guard);
+        shutdown();
+        return Err(e);
+    }
 
     // Set up and run devenv
-    let job_result = run_devenv(&job_config, &project_dir).await;
+    let job_result = run_devenv(&job_config, &project_dir, &reporter_arc).await;
 
     // Log the error if devenv failed
     if let Err(e) = &job_result {
         tracing::error!("Devenv execution failed: {:?}", e);
     }
 
+    // Upload artifacts, even for failed jobs since they often help debugging
+    if !job_config.artifacts.is_empty() {
+        if let Err(e) = upload_artifacts(&job_config, &project_dir, &reporter_arc).await {
+            tracing::error!("Failed to upload artifacts: {:?}", e);
+        }
+    }
+
     // Report job completion status
     {
         let mut reporter_guard = reporter_arc.lock().await;
@@ -181,24 +205,7 @@ async fn main() -> Result<()> {
     }
 
     // Cleanly shutdown the VM regardless of success or failure
-    tracing::info!("Shutting down VM");
-
-    #[cfg(target_os = "linux")]
-    unsafe {
-        // Sync filesystem data before shutdown
-        libc::sync();
-        // Initiate system shutdown
-        libc::reboot(libc::RB_HALT_SYSTEM);
-    }
-
-    #[cfg(target_os = "macos")]
-    {
-        // On macOS, use the shutdown command
-        std::process::Command::new("sudo")
-            .args(&["shutdown", "-h", "now"])
-            .spawn()
-            .ok();
-    }
+    shutdown();
 
     // Return the result
     job_result
@@ -240,6 +247,59 @@ fn clone_repository(job_config: &JobConfig, project_dir: &PathBuf) -> Result<()>
         }
     }
 
+    // The ref may have moved on since the job was created, so the revision of the
+    // job is fetched by id. Servers that don't allow that get the ref fetched instead.
+    let mut prepare_checkout = match &job_config.git_rev {
+        Some(git_rev) => match fetch_repository(job_config, project_dir, Some(git_rev)) {
+            Ok(prepare_checkout) => prepare_checkout,
+            Err(e) => {
+                tracing::warn!(
+                    "Failed to fetch revision {}, fetching the ref instead: {:#}",
+                    git_rev,
+                    e
+                );
+                fetch_repository(job_config, project_dir, None)?
+            }
+        },
+        None => fetch_repository(job_config, project_dir, None)?,
+    };
+
+    // Check out the commit the job was created for, wherever the ref points now
+    if let Some(git_rev) = &job_config.git_rev {
+        let id = gix::ObjectId::from_hex(git_rev.as_bytes())
+            .wrap_err_with(|| format!("Invalid git revision '{}'", git_rev))?;
+        let repo = prepare_checkout.repo();
+        repo.find_commit(id)
+            .wrap_err_with(|| format!("Revision {} wasn't fetched", git_rev))?;
+        repo.edit_reference(RefEdit {
+            change: Change::Update {
+                log: LogChange::default(),
+                expected: PreviousValue::Any,
+                new: gix::refs::Target::Object(id),
+            },
+            name: "HEAD".try_into().wrap_err("Invalid HEAD reference name")?,
+            deref: false,
+        })
+        .wrap_err_with(|| format!("Failed to point HEAD at revision {}", git_rev))?;
+        tracing::info!("Checking out revision {}", git_rev);
+    }
+
+    // Perform the checkout to main worktree
+    let (_repo, _outcome) = prepare_checkout
+        .main_worktree(gix::progress::Discard, &gix::interrupt::IS_INTERRUPTED)
+        .wrap_err("Failed to checkout repository")?;
+
+    tracing::info!("Repository cloned successfully");
+
+    Ok(())
+}
+
+/// Fetch the repository of a job, either a single revision or the ref of the job
+fn fetch_repository(
+    job_config: &JobConfig,
+    project_dir: &PathBuf,
+    git_rev: Option<&str>,
+) -> Result<gix::clone::PrepareCheckout> {
     // Prepare the clone operation
     let mut prepare_clone = gix::prepare_clone(job_config.project_url.as_str(), project_dir)
         .wrap_err("Failed to prepare repository clone")?;
@@ -249,31 +309,112 @@ fn clone_repository(job_config: &JobConfig, project_dir: &PathBuf) -> Result<()>
         prepare_clone = prepare_clone.with_shallow(Shallow::DepthAtRemote(depth));
     }
 
-    // Configure to checkout specific ref if provided
-    if let Some(git_ref) = &job_config.git_ref {
-        tracing::info!("Checking out ref {}", git_ref);
-        prepare_clone = prepare_clone
-            .with_ref_name(Some(git_ref.as_str()))
-            .wrap_err_with(|| format!("Invalid git ref '{}'", git_ref))?;
+    // Authenticate with the job's access token for private repositories
+    if let Some(token) = job_config.git_token.clone() {
+        tracing::info!("Using access token for clone");
+        prepare_clone = prepare_clone.configure_connection(move |connection| {
+            let token = token.clone();
+            connection.set_credentials(move |action| match action {
+                gix::credentials::helper::Action::Get(ctx) => {
+                    Ok(Some(gix::credentials::protocol::Outcome {
+                        identity: gix::sec::identity::Account {
+                            username: "x-access-token".to_string(),
+                            password: token.expose().to_string(),
+                        },
+                        next: ctx.into(),
+                    }))
+                }
+                gix::credentials::helper::Action::Store(_)
+                | gix::credentials::helper::Action::Erase(_) => Ok(None),
+            });
+            Ok(())
+        });
+    }
+
+    match (git_rev, &job_config.git_ref) {
+        // Fetch only the commit of the job, which GitHub allows for any reachable commit
+        (Some(git_rev), _) => {
+            tracing::info!("Fetching revision {}", git_rev);
+            let refspec = format!("{git_rev}:refs/heads/devenv-job");
+            prepare_clone = prepare_clone.configure_remote(move |remote| {
+                Ok(remote.with_refspecs([refspec.as_str()], gix::remote::Direction::Fetch)?)
+            });
+        }
+        // Configure to checkout specific ref if provided
+        (None, Some(git_ref)) => {
+            tracing::info!("Checking out ref {}", git_ref);
+            prepare_clone = prepare_clone
+                .with_ref_name(Some(git_ref.as_str()))
+                .wrap_err_with(|| format!("Invalid git ref '{}'", git_ref))?;
+        }
+        (None, None) => {}
     }
 
     // Fetch and prepare for checkout
-    let (mut prepare_checkout, _outcome) = prepare_clone
+    let (prepare_checkout, _outcome) = prepare_clone
         .fetch_then_checkout(gix::progress::Discard, &gix::interrupt::IS_INTERRUPTED)
         .wrap_err("Failed to fetch repository")?;
+    Ok(prepare_checkout)
+}
 
-    // Perform the checkout to main worktree
-    let (_repo, _outcome) = prepare_checkout
-        .main_worktree(gix::progress::Discard, &gix::interrupt::IS_INTERRUPTED)
-        .wrap_err("Failed to checkout repository")?;
+/// Shut down the VM
+fn shutdown() {
+    tracing::info!("Shutting down VM");
 
-    tracing::info!("Repository cloned successfully");
+    #[cfg(target_os = "linux")]
+    unsafe {
+        // Sync filesystem data before shutdown
+        libc::sync();
+        // Initiate system shutdown
+        libc::reboot(libc::RB_HALT_SYSTEM);
+    }
 
-    Ok(())
+    #[cfg(target_os = "macos")]
+    {
+        // On macOS, use the shutdown command
+        std::process::Command::new("sudo")
+            .args(&["shutdown", "-h", "now"])
+            .spawn()
+            .ok();
+    }
+}
+
+/// Run a task of the job, reporting its start and outcome to the host
+async fn run_task<T>(
+    reporter: &Arc<Mutex<JobReporter>>,
+    name: &str,
+    task: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    if let Err(e) = reporter
+        .lock()
+        .await
+        .report_task_started(name.to_string())
+        .await
+    {
+        tracing::error!("Failed to report start of task {}: {:?}", name, e);
+    }
+
+    let started = Instant::now();
+    let result = task.await;
+
+    if let Err(e) = reporter
+        .lock()
+        .await
+        .report_task_finished(name.to_string(), result.is_ok(), started.elapsed())
+        .await
+    {
+        tracing::error!("Failed to report outcome of task {}: {:?}", name, e);
+    }
+
+    result
 }
 
 /// Set up and run devenv with the provided configuration
-async fn run_devenv(job_config: &JobConfig, project_dir: &PathBuf) -> Result<()> {
+async fn run_devenv(
+    job_config: &JobConfig,
+    project_dir: &PathBuf,
+    reporter: &Arc<Mutex<JobReporter>>,
+) -> Result<()> {
     // Ensure we have an absolute path
     let project_dir = project_dir
         .canonicalize()
@@ -298,6 +439,16 @@ async fn run_devenv(job_config: &JobConfig, project_dir: &PathBuf) -> Result<()>
         // TODO: Set up Cachix push options here
     }
 
+    // The shell inherits the environment of the driver
+    if !job_config.secrets.is_empty() {
+        tracing::info!("Exposing {} secrets", job_config.secrets.len());
+    }
+    for (name, value) in &job_config.secrets {
+        unsafe {
+            std::env::set_var(name, value.expose());
+        }
+    }
+
     // Create shutdown signal for coordinated shutdown
     let shutdown = Shutdown::new();
 
@@ -327,5 +478,93 @@ async fn run_devenv(job_config: &JobConfig, project_dir: &PathBuf) -> Result<()>
         .await
         .map_err(|e| eyre!("Failed to build devenv shell: {:?}", e))?;
 
+    // Run the tasks in order, stopping at the first one that fails
+    for task in &job_config.tasks {
+        tracing::info!("Running task {}", task);
+        run_task(reporter, task, run_devenv_task(&devenv, task)).await?;
+    }
+
+    Ok(())
+}
+
+/// Run a task of the job
+///
+/// `build` and `test` run the devenv commands of the same name, anything else
+/// is the name of a devenv task such as `devenv:lint`.
+async fn run_devenv_task(devenv: &Devenv, task: &str) -> Result<()> {
+    let result = match task {
+        "build" => devenv.build(&[]).await,
+        "test" => devenv.test().await,
+        _ => devenv
+            .tasks_run(vec![task.to_string()], RunMode::Single)
+            .await
+            .map(|_| ()),
+    };
+    result.map_err(|e| eyre!("Task {} failed: {:?}", task, e))
+}
+
+/// Collect files matching the configured artifact patterns and stream them to the host
+async fn upload_artifacts(
+    job_config: &JobConfig,
+    project_dir: &Path,
+    reporter: &Arc<Mutex<JobReporter>>,
+) -> Result<()> {
+    let mut files = Vec::new();
+    for pattern in &job_config.artifacts {
+        let full_pattern = project_dir.join(pattern);
+        let paths = glob::glob(&full_pattern.to_string_lossy())
+            .wrap_err_with(|| format!("Invalid artifact pattern '{}'", pattern))?;
+
+        let mut matched = false;
+        for path in paths.flatten() {
+            matched = true;
+            collect_files(&path, &mut files)?;
+        }
+        if !matched {
+            tracing::warn!("Artifact pattern '{}' did not match any files", pattern);
+        }
+    }
+    files.sort();
+    files.dedup();
+
+    for path in files {
+        let Ok(relative) = path.strip_prefix(project_dir) else {
+            continue;
+        };
+        let name = relative.to_string_lossy().to_string();
+
+        tracing::info!("Uploading artifact {}", name);
+        let mut file = std::fs::File::open(&path)
+            .wrap_err_with(|| format!("Failed to open artifact {}", path.display()))?;
+        let mut buffer = vec![0u8; ARTIFACT_CHUNK_SIZE];
+        loop {
+            let read = file
+                .read(&mut buffer)
+                .wrap_err_with(|| format!("Failed to read artifact {}", path.display()))?;
+            let last = read == 0;
+            reporter
+                .lock()
+                .await
+                .send_artifact_chunk(name.clone(), buffer[..read].to_vec(), last)
+                .await?;
+            if last {
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Add a path to the list of files, descending into directories
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let metadata = std::fs::symlink_metadata(path)?;
+    if metadata.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            collect_files(&entry?.path(), files)?;
+        }
+    } else if metadata.is_file() {
+        files.push(path.to_path_buf());
+    }
     Ok(())
 }
@@ -3,6 +3,7 @@ use devenv_runner::config::VmConfig;
 use devenv_runner::protocol::{JobConfig, Platform, VM};
 use devenv_runner::resource_manager::ResourceManager;
 use devenv_runner::vm_manager::{VmCompletionEvent, VmManager};
+use devenv_runner::vsock::GuestEvent;
 use signal_hook::consts::signal::{SIGINT, SIGQUIT, SIGTERM};
 use signal_hook::iterator::Signals;
 use std::path::PathBuf;
@@ -74,9 +75,13 @@ async fn main() -> Result<()> {
         id: job_id,
         project_url: "https://github.com/cachix/devenv".to_string(),
         git_ref: Some("main".to_string()),
+        git_rev: None,
         tasks: vec!["build".to_string()],
         cachix_push: false,
         clone_depth: Some(1),
+        git_token: None,
+        artifacts: vec![],
+        secrets: Default::default(),
     };
 
     tracing::info!("Setting job configuration: {:?}", job_config);
@@ -91,9 +96,37 @@ async fn main() -> Result<()> {
         }
     });
 
+    // Artifacts are not uploaded anywhere when running locally
+    let (event_sender, mut event_receiver) = mpsc::channel::<GuestEvent>(100);
+
+    tokio::spawn(async move {
+        while let Some(event) = event_receiver.recv().await {
+            match event {
+                GuestEvent::Started => tracing::info!("Guest started the job"),
+                GuestEvent::ArtifactChunk { path, data, last } => tracing::info!(
+                    "Artifact {}: received {} bytes{}",
+                    path,
+                    data.len(),
+                    if last { " (done)" } else { "" }
+                ),
+                GuestEvent::TaskStarted { name } => tracing::info!("Task {} started", name),
+                GuestEvent::TaskFinished {
+                    name,
+                    success,
+                    duration_ms,
+                } => tracing::info!(
+                    "Task {} {} after {}ms",
+                    name,
+                    if success { "succeeded" } else { "failed" },
+                    duration_ms
+                ),
+            }
+        }
+    });
+
     // Launch VM using VmManager
     vm_manager
-        .launch_vm(job_id, vm_config, job_config, log_sender)
+        .launch_vm(job_id, vm_config, job_config, log_sender, event_sender)
         .await?;
 
     // Set up signal handling
@@ -138,6 +171,10 @@ async fn main() -> Result<()> {
                             tracing::error!("Job failed! ✗");
                             std::process::exit(1);
                         }
+                        devenv_runner::vm::VmExitStatus::InfrastructureFailure => {
+                            tracing::error!("Job couldn't be set up! ✗");
+                            std::process::exit(1);
+                        }
                     }
                 }
             }
@@ -178,6 +215,10 @@ async fn main() -> Result<()> {
                         tracing::error!("Job failed! ✗");
                         1
                     }
+                    devenv_runner::vm::VmExitStatus::InfrastructureFailure => {
+                        tracing::error!("Job couldn't be set up! ✗");
+                        1
+                    }
                 }
             }
             // Wait for shutdown signal
@@ -195,7 +236,8 @@ async fn main() -> Result<()> {
                 match completion_rx.recv().await {
                     Some(event) => match event.status {
                         devenv_runner::vm::VmExitStatus::Success => 0,
-                        devenv_runner::vm::VmExitStatus::Failure => 1,
+                        devenv_runner::vm::VmExitStatus::Failure
+                        | devenv_runner::vm::VmExitStatus::InfrastructureFailure => 1,
                     },
                     None => 1,
                 }
@@ -1,18 +1,21 @@
 use backon::{ExponentialBuilder, Retryable};
 use clap::Parser;
 use devenv_logger::Log;
-use devenv_runner::client::{WebSocketClient, WebSocketError};
+use devenv_runner::client::{Registration, WebSocketClient, WebSocketError};
 use devenv_runner::config::VmConfig;
 use devenv_runner::job_manager::{JobManager, JobStatusEvent};
+use devenv_runner::mask::Masker;
 use devenv_runner::protocol::{
-    ClientMessage, CompletionStatus, JobConfig, JobStatus, RunnerMetrics, ServerMessage,
+    ActiveJob, ClientMessage, CompletionStatus, JobStatus, RunnerMetrics, ServerMessage,
 };
 use devenv_runner::resource_manager::ResourceManager;
 use devenv_runner::vm_manager::{VmCompletionEvent, VmManager};
+use devenv_runner::vsock::{GuestEvent, LogEntry};
 use eyre::Result;
 use futures_util::{Stream, StreamExt};
 use reqwest::{Body, Client as HttpClient};
-use std::path::PathBuf;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::sync::Arc;
 use std::time::Duration;
@@ -25,33 +28,112 @@ use uuid::Uuid;
 
 // Constants
 const LOG_CHANNEL_BUFFER_SIZE: usize = 100;
+/// How long a log line is held back waiting for the line that may continue a secret
+const LOG_MASK_DELAY: Duration = Duration::from_millis(100);
+/// How often the leases of running jobs are renewed, well below the server's lease duration
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
 
 /// Type alias for the WebSocket read stream
 type WsReadStream = Pin<Box<dyn Stream<Item = Result<ServerMessage, WebSocketError>> + Send>>;
 
 /// Reconnect to WebSocket server with exponential backoff
-async fn reconnect_websocket(ws_url: Uri) -> Result<(WebSocketClient, WsReadStream)> {
-    let (client, read) = (|| async { WebSocketClient::new(ws_url.clone(), None).await })
-        .retry(
-            ExponentialBuilder::default()
-                .with_min_delay(Duration::from_secs(1))
-                .with_max_delay(Duration::from_secs(60))
-                .with_max_times(usize::MAX),
-        ) // Retry indefinitely
-        .when(|_: &_| true) // Retry on all errors
-        .notify(|err, dur| {
-            tracing::error!(
-                "Failed to connect to WebSocket: {}. Retrying in {:?}",
-                err,
-                dur
-            );
-        })
-        .await
-        .map_err(|e| eyre::eyre!("Failed to connect to WebSocket after all retries: {}", e))?;
+async fn reconnect_websocket(
+    ws_url: Uri,
+    registration: &Registration,
+) -> Result<(WebSocketClient, WsReadStream)> {
+    let (client, read) =
+        (|| async { WebSocketClient::new(ws_url.clone(), registration, None).await })
+            .retry(
+                ExponentialBuilder::default()
+                    .with_min_delay(Duration::from_secs(1))
+                    .with_max_delay(Duration::from_secs(60))
+                    .with_max_times(usize::MAX),
+            ) // Retry indefinitely
+            .when(|_: &_| true) // Retry on all errors
+            .notify(|err, dur| {
+                tracing::error!(
+                    "Failed to connect to WebSocket: {}. Retrying in {:?}",
+                    err,
+                    dur
+                );
+            })
+            .await
+            .map_err(|e| eyre::eyre!("Failed to connect to WebSocket after all retries: {}", e))?;
 
     Ok((client, Box::pin(read)))
 }
 
+/// Load the runner's persistent id from the state directory, creating it on first start
+fn load_runner_id(state_dir: &Path) -> Result<Uuid> {
+    let path = state_dir.join("runner-id");
+    match std::fs::read_to_string(&path) {
+        Ok(contents) => contents
+            .trim()
+            .parse()
+            .map_err(|e| eyre::eyre!("Invalid runner id in {}: {}", path.display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let runner_id = Uuid::now_v7();
+            std::fs::create_dir_all(state_dir)?;
+            std::fs::write(&path, runner_id.to_string())?;
+            Ok(runner_id)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Tell the server which jobs are still executing, so it can reconcile them after a reconnect
+async fn reconcile_jobs(client: &mut WebSocketClient, job_manager: &JobManager) -> Result<()> {
+    let ids = job_manager.active_job_ids().await;
+    tracing::debug!("Reconciling {} active jobs with the server", ids.len());
+    client
+        .send_message(ClientMessage::ReconcileJobs { ids }, None)
+        .await?;
+    Ok(())
+}
+
+/// Messages the server must not miss, held back while it can't be reached
+///
+/// A job that completed while the runner was disconnected would otherwise be
+/// failed as lost once the runner reconnects, and its tasks would be missing.
+#[derive(Default)]
+struct Outbox(VecDeque<ClientMessage>);
+
+impl Outbox {
+    /// Send a message, keeping it to resend after a reconnect if that fails
+    ///
+    /// Messages already held back go first, so the server sees them in order.
+    async fn send(&mut self, client: &mut WebSocketClient, message: ClientMessage) {
+        if !self.0.is_empty() {
+            self.push(message);
+            return;
+        }
+        if let Err(e) = client.send_message(message.clone(), None).await {
+            tracing::warn!(
+                "Failed to send message to server, resending it after reconnecting: {}",
+                e
+            );
+            self.push(message);
+        }
+    }
+
+    /// Hold back a message until the next flush
+    fn push(&mut self, message: ClientMessage) {
+        self.0.push_back(message);
+    }
+
+    /// Resend the held back messages in order, stopping at the first failure
+    async fn flush(&mut self, client: &mut WebSocketClient) -> Result<()> {
+        if !self.0.is_empty() {
+            tracing::info!("Resending {} messages to the server", self.0.len());
+        }
+        while let Some(message) = self.0.front() {
+            client.send_message(message.clone(), None).await?;
+            self.0.pop_front();
+        }
+        Ok(())
+    }
+}
+
 /// Checks if the runner has enough capacity to handle more jobs, and if so, requests one.
 ///
 /// Requires at least 1 CPU and some available memory.
@@ -70,12 +152,84 @@ async fn check_capacity_and_request_job(
     Ok(())
 }
 
+/// A line of a job log, structured unless the guest sent plain text
+struct LogLine {
+    entry: LogEntry,
+    /// Plain text lines are logged verbatim
+    plain: bool,
+    timestamp: String,
+}
+
+impl LogLine {
+    fn parse(message: String) -> Self {
+        let timestamp = chrono::Utc::now().to_rfc3339();
+        match serde_json::from_str::<LogEntry>(&message) {
+            Ok(entry) => Self {
+                entry,
+                plain: false,
+                timestamp,
+            },
+            Err(_) => Self {
+                entry: LogEntry {
+                    level: "INFO".to_string(),
+                    target: String::new(),
+                    message,
+                    fields: HashMap::new(),
+                },
+                plain: true,
+                timestamp,
+            },
+        }
+    }
+
+    fn mask(&mut self, masker: &Masker) {
+        masker.mask(&mut self.entry.message);
+        for value in self.entry.fields.values_mut() {
+            masker.mask(value);
+        }
+    }
+
+    fn into_log(self) -> Log {
+        let entry = self.entry;
+        if self.plain {
+            return Log {
+                message: entry.message,
+                timestamp: self.timestamp,
+                level: entry.level,
+            };
+        }
+
+        // Format the message with level and target info
+        let message = if entry.fields.is_empty() {
+            format!("[{}] {}: {}", entry.level, entry.target, entry.message)
+        } else {
+            let fields_str = entry
+                .fields
+                .iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect::<Vec<_>>()
+                .join(" ");
+            format!(
+                "[{}] {}: {} {}",
+                entry.level, entry.target, entry.message, fields_str
+            )
+        };
+
+        Log {
+            message,
+            timestamp: self.timestamp,
+            level: entry.level,
+        }
+    }
+}
+
 /// Sets up a log streaming infrastructure for a job.
 ///
 /// This function creates a logging channel and spawns a background task that:
 /// 1. Sends an initial log message about VM startup
-/// 2. Converts log messages to NDJSON format
-/// 3. Sends all logs via a streaming HTTP request
+/// 2. Redacts the job's sensitive values from log messages
+/// 3. Converts log messages to NDJSON format
+/// 4. Sends all logs via a streaming HTTP request
 ///
 /// Returns a channel sender that can be used to send additional log messages.
 async fn setup_log_stream(
@@ -84,6 +238,7 @@ async fn setup_log_stream(
     job_id: Uuid,
     cpu_count: usize,
     memory_mb: u64,
+    masker: Masker,
 ) -> mpsc::Sender<String> {
     // Create stream for logs with buffer
     let (log_sender, log_receiver) = mpsc::channel::<String>(LOG_CHANNEL_BUFFER_SIZE);
@@ -92,7 +247,7 @@ async fn setup_log_stream(
     // Start a background task that will handle the continuous log stream
     tokio::spawn(async move {
         // Initial log message as structured log
-        let log_entry = devenv_runner::vsock::LogEntry {
+        let log_entry = LogEntry {
             level: "INFO".to_string(),
             target: "runner".to_string(),
             message: format!(
@@ -108,52 +263,46 @@ async fn setup_log_stream(
             }
         }
 
-        // Transform log messages into NDJSON
-        let log_stream = ReceiverStream::new(log_receiver).map(|message| {
-            // Try to parse as structured log first
-            let log = if let Ok(log_entry) =
-                serde_json::from_str::<devenv_runner::vsock::LogEntry>(&message)
-            {
-                // Format the message with level and target info
-                let formatted_message = if log_entry.fields.is_empty() {
-                    format!(
-                        "[{}] {}: {}",
-                        log_entry.level, log_entry.target, log_entry.message
-                    )
-                } else {
-                    let fields_str = log_entry
-                        .fields
-                        .iter()
-                        .map(|(k, v)| format!("{}={}", k, v))
-                        .collect::<Vec<_>>()
-                        .join(" ");
-                    format!(
-                        "[{}] {}: {} {}",
-                        log_entry.level, log_entry.target, log_entry.message, fields_str
-                    )
-                };
-
-                Log {
-                    message: formatted_message,
-                    timestamp: chrono::Utc::now().to_rfc3339(),
-                    level: log_entry.level,
-                }
-            } else {
-                // Fallback for plain text messages
-                Log {
-                    message,
-                    timestamp: chrono::Utc::now().to_rfc3339(),
-                    level: "INFO".to_string(),
+        // Redact sensitive values, holding each line back until the next one
+        // arrives or the log goes quiet, so values split across lines are caught
+        let lines = futures_util::stream::unfold(
+            (log_receiver, masker, None::<LogLine>),
+            |(mut receiver, masker, mut pending)| async move {
+                loop {
+                    match tokio::time::timeout(LOG_MASK_DELAY, receiver.recv()).await {
+                        Ok(Some(message)) => {
+                            let mut line = LogLine::parse(message);
+                            line.mask(&masker);
+                            if masker.is_empty() {
+                                return Some((line, (receiver, masker, None)));
+                            }
+                            if let Some(mut previous) = pending.take() {
+                                masker.mask_split(
+                                    &mut previous.entry.message,
+                                    &mut line.entry.message,
+                                );
+                                return Some((previous, (receiver, masker, Some(line))));
+                            }
+                            pending = Some(line);
+                        }
+                        Ok(None) => return pending.map(|line| (line, (receiver, masker, None))),
+                        Err(_) => {
+                            if let Some(line) = pending.take() {
+                                return Some((line, (receiver, masker, None)));
+                            }
+                        }
+                    }
                 }
-            };
+            },
+        );
 
-            match serde_json::to_string(&log) {
-                Ok(s) => Ok::<_, std::io::Error>(s + "\n"),
-                Err(e) => {
-                    tracing::error!("Failed to serialize log: {}", e);
-                    // Return empty string on error to keep stream alive
-                    Ok::<_, std::io::Error>(String::new())
-                }
+        // Transform log lines into NDJSON
+        let log_stream = lines.map(|line| match serde_json::to_string(&line.into_log()) {
+            Ok(s) => Ok::<_, std::io::Error>(s + "\n"),
+            Err(e) => {
+                tracing::error!("Failed to serialize log: {}", e);
+                // Return empty string on error to keep stream alive
+                Ok::<_, std::io::Error>(String::new())
             }
         });
 
@@ -177,6 +326,119 @@ async fn setup_log_stream(
     log_sender
 }
 
+/// Set up handling of guest events for a job
+///
+/// Records when the guest starts the job, so heartbeats keep reporting it, and
+/// tells the server right away. Forwards the lifecycle of the job's tasks to
+/// the server.
+///
+/// Artifacts arrive from the guest in chunks. Each file gets its own streaming
+/// HTTP upload which is finished once the last chunk for that path arrives.
+///
+/// Returns a channel sender for guest events.
+fn setup_guest_events(
+    http_client: HttpClient,
+    artifacts_url: url::Url,
+    token: String,
+    job_id: Uuid,
+    job_manager: Arc<JobManager>,
+    server_sender: mpsc::Sender<ClientMessage>,
+) -> mpsc::Sender<GuestEvent> {
+    let (event_sender, mut event_receiver) = mpsc::channel::<GuestEvent>(LOG_CHANNEL_BUFFER_SIZE);
+
+    tokio::spawn(async move {
+        let mut uploads: HashMap<String, mpsc::Sender<Vec<u8>>> = HashMap::new();
+
+        while let Some(event) = event_receiver.recv().await {
+            match event {
+                GuestEvent::Started => {
+                    job_manager.mark_guest_started(job_id).await;
+                    // A heartbeat of just this job, rather than waiting for the next one
+                    let message = ClientMessage::Heartbeat {
+                        jobs: vec![ActiveJob {
+                            id: job_id,
+                            started: true,
+                        }],
+                    };
+                    if let Err(e) = server_sender.send(message).await {
+                        tracing::error!("Failed to report start of job {}: {}", job_id, e);
+                    }
+                }
+                GuestEvent::TaskStarted { name } => {
+                    let message = ClientMessage::TaskStarted { id: job_id, name };
+                    if let Err(e) = server_sender.send(message).await {
+                        tracing::error!("Failed to forward task start of job {}: {}", job_id, e);
+                    }
+                }
+                GuestEvent::TaskFinished {
+                    name,
+                    success,
+                    duration_ms,
+                } => {
+                    let message = ClientMessage::TaskFinished {
+                        id: job_id,
+                        name,
+                        success,
+                        duration_ms,
+                    };
+                    if let Err(e) = server_sender.send(message).await {
+                        tracing::error!("Failed to forward task result of job {}: {}", job_id, e);
+                    }
+                }
+                GuestEvent::ArtifactChunk { path, data, last } => {
+                    let chunk_sender = uploads.entry(path.clone()).or_insert_with(|| {
+                        let (chunk_sender, chunk_receiver) = mpsc::channel::<Vec<u8>>(16);
+                        let mut url = artifacts_url.clone();
+                        url.query_pairs_mut().append_pair("name", &path);
+                        let body = Body::wrap_stream(
+                            ReceiverStream::new(chunk_receiver).map(Ok::<_, std::io::Error>),
+                        );
+                        let http_client = http_client.clone();
+                        let token = token.clone();
+                        let path = path.clone();
+
+                        tokio::spawn(async move {
+                            let result = http_client
+                                .put(url)
+                                .bearer_auth(token)
+                                .header("Content-Type", "application/octet-stream")
+                                .body(body)
+                                .send()
+                                .await
+                                .and_then(|response| response.error_for_status());
+
+                            match result {
+                                Ok(_) => {
+                                    tracing::info!("Uploaded artifact {} for job {}", path, job_id)
+                                }
+                                Err(e) => tracing::error!(
+                                    "Failed to upload artifact {} for job {}: {}",
+                                    path,
+                                    job_id,
+                                    e
+                                ),
+                            }
+                        });
+
+                        chunk_sender
+                    });
+
+                    if !data.is_empty() && chunk_sender.send(data).await.is_err() {
+                        tracing::error!("Artifact upload for {} was aborted", path);
+                    }
+
+                    // Dropping the sender ends the request body
+                    if last {
+                        uploads.remove(&path);
+                    }
+                }
+            }
+        }
+    });
+
+    event_sender
+}
+
 #[derive(Parser)]
 #[command(
     color = clap::ColorChoice::Auto,
@@ -186,6 +448,14 @@ struct Cli {
     #[arg(short = 'u', long, default_value = "ws://cloud.devenv.sh/", value_parser = parse_uri)]
     host: Uri,
 
+    /// Registration token issued by the server
+    #[arg(long, env = "DEVENV_RUNNER_TOKEN", hide_env_values = true)]
+    token: String,
+
+    /// Labels describing this runner's capabilities, which jobs can require with `runs-on`
+    #[arg(long = "label", env = "DEVENV_RUNNER_LABELS", value_delimiter = ',', value_parser = parse_label)]
+    labels: Vec<String>,
+
     #[arg(long, env = "RESOURCES_DIR")]
     resources_dir: PathBuf,
 
@@ -206,6 +476,17 @@ fn parse_uri(s: &str) -> Result<Uri, String> {
     s.parse::<Uri>().map_err(|e| format!("Invalid URI: {}", e))
 }
 
+fn parse_label(s: &str) -> Result<String, String> {
+    let label = s.trim();
+    if !devenv_runner::protocol::is_valid_label(label) {
+        return Err(format!(
+            "Invalid label '{}': only letters, digits, '-', '_', '.', ':' and '=' are allowed",
+            s
+        ));
+    }
+    Ok(label.to_string())
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     color_eyre::install()?;
@@ -224,6 +505,12 @@ async fn main() -> Result<()> {
 
     let cli = Cli::parse();
     let vm_config = cli.vm_config();
+    let registration = Arc::new(Registration {
+        runner_id: load_runner_id(&cli.state_dir)?,
+        token: cli.token.clone(),
+        labels: cli.labels.clone(),
+    });
+    tracing::info!("Runner id: {}", registration.runner_id);
 
     // Create resource manager with platform-specific defaults
     let resource_manager = Arc::new(ResourceManager::with_platform_defaults());
@@ -283,7 +570,7 @@ async fn main() -> Result<()> {
     // Connect to WebSocket server with retry logic
     let (mut client, mut read): (WebSocketClient, WsReadStream) = loop {
         tokio::select! {
-            result = WebSocketClient::new((*ws_uri).clone(), None) => {
+            result = WebSocketClient::new((*ws_uri).clone(), &registration, None) => {
                 match result {
                     Ok((client, read)) => break (client, Box::pin(read)),
                     Err(e) => {
@@ -300,6 +587,9 @@ async fn main() -> Result<()> {
     };
     tracing::info!("Connected, waiting for jobs...");
 
+    // Jobs from before a restart are gone, let the server know
+    reconcile_jobs(&mut client, &job_manager).await?;
+
     // Initial job request
     check_capacity_and_request_job(&mut client, &resource_manager).await?;
 
@@ -311,10 +601,7 @@ async fn main() -> Result<()> {
             // Resources are automatically released when the ResourceGuard in vm_task_impl drops
 
             // Convert VM exit status to job completion status
-            let completion_status = match event.status {
-                devenv_runner::vm::VmExitStatus::Success => CompletionStatus::Success,
-                devenv_runner::vm::VmExitStatus::Failure => CompletionStatus::Failed,
-            };
+            let completion_status = CompletionStatus::from(event.status);
 
             // Attempt to update job status - state transition logic will handle conflicts
             // If job was already marked as canceled/timed out, this will be rejected
@@ -338,16 +625,28 @@ async fn main() -> Result<()> {
     let resource_manager_clone = resource_manager.clone();
     let job_manager_clone = job_manager.clone();
     let ws_url_clone = ws_uri.clone();
+    let registration_clone = registration.clone();
 
     // Main event loop with shutdown handling
     let mut shutdown_rx = shutdown_rx;
     let mut shutting_down = false;
+    // Set by the server to stop offering this runner new jobs
+    let mut cordoned = false;
+
+    // Messages to the server from tasks without access to the WebSocket client
+    let (server_tx, mut server_rx) = mpsc::channel::<ClientMessage>(LOG_CHANNEL_BUFFER_SIZE);
 
     let main_loop_thread = tokio::spawn(async move {
+        let mut outbox = Outbox::default();
+
         // Create metrics reporting interval
         let mut metrics_interval = tokio::time::interval(Duration::from_secs(1));
         metrics_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
 
+        // Create heartbeat interval renewing the leases of running jobs
+        let mut heartbeat_interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+        heartbeat_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
         'main: loop {
             // Exit if we're shutting down and all jobs are complete
             if shutting_down && job_manager.active_job_count().await == 0 {
@@ -372,23 +671,20 @@ async fn main() -> Result<()> {
                     match event.status {
                         JobStatus::Complete(status) => {
                             // Job completed, update backend
-                            if let Err(e) = client
-                                .send_message(
+                            outbox
+                                .send(
+                                    &mut client,
                                     ClientMessage::UpdateJobStatus {
                                         id: event.job_id,
                                         status: JobStatus::Complete(status.clone()),
                                     },
-                                    None,
                                 )
-                                .await
-                            {
-                                tracing::error!("Failed to update job status: {}", e);
-                            }
+                                .await;
 
                             tracing::info!("Job {} completed with status: {:?}", event.job_id, status);
 
                             // Request another job if we have capacity and not shutting down
-                            if !shutting_down {
+                            if !shutting_down && !cordoned {
                                 if let Err(e) = check_capacity_and_request_job(&mut client, &resource_manager).await {
                                     tracing::error!("Failed to request new job: {}", e);
                                 }
@@ -400,6 +696,7 @@ async fn main() -> Result<()> {
                         JobStatus::Queued => {
                             tracing::info!("Job {} is queued", event.job_id);
                         }
+                        JobStatus::AwaitingApproval => {}
                     }
                 }
 
@@ -413,7 +710,10 @@ async fn main() -> Result<()> {
                             &resource_manager_clone,
                             &job_manager_clone,
                             &http_client,
-                            shutting_down,
+                            &registration_clone.token,
+                            &server_tx,
+                            &mut shutting_down,
+                            &mut cordoned,
                         ).await.unwrap(),
                         Some(Err(e)) => {
                             tracing::error!("WebSocket error: {}", e);
@@ -430,12 +730,31 @@ async fn main() -> Result<()> {
 
                             tracing::info!("WebSocket connection closed, attempting to reconnect...");
 
-                            match reconnect_websocket((*ws_url_clone).clone()).await {
+                            match reconnect_websocket((*ws_url_clone).clone(), &registration_clone).await {
                                 Ok((new_client, new_read)) => {
                                     client = new_client;
                                     read = new_read;
                                     tracing::info!("Successfully reconnected!");
-                                    if !shutting_down {
+                                    // The server tells cordoned runners again on connect
+                                    cordoned = false;
+                                    // Completions go first, so the server doesn't take their jobs for lost.
+                                    // That includes the jobs that completed while reconnecting.
+                                    while let Ok(event) = job_status_rx.try_recv() {
+                                        if let JobStatus::Complete(status) = event.status {
+                                            tracing::info!("Job {} completed with status: {:?}", event.job_id, status);
+                                            outbox.push(ClientMessage::UpdateJobStatus {
+                                                id: event.job_id,
+                                                status: JobStatus::Complete(status),
+                                            });
+                                        }
+                                    }
+                                    if let Err(e) = outbox.flush(&mut client).await {
+                                        tracing::error!("Failed to resend messages: {}", e);
+                                    }
+                                    if let Err(e) = reconcile_jobs(&mut client, &job_manager).await {
+                                        tracing::error!("Failed to reconcile jobs: {}", e);
+                                    }
+                                    if !shutting_down && !cordoned {
                                         check_capacity_and_request_job(&mut client, &resource_manager).await.unwrap()
                                     }
                                 }
@@ -450,6 +769,11 @@ async fn main() -> Result<()> {
                     }
                 }
 
+                // Forward messages from guest event handlers
+                Some(message) = server_rx.recv() => {
+                    outbox.send(&mut client, message).await;
+                }
+
                 // Periodic metrics reporting
                 _ = metrics_interval.tick() => {
                     let metrics = collect_metrics(&resource_manager, &job_manager).await;
@@ -460,6 +784,17 @@ async fn main() -> Result<()> {
                         tracing::error!("Failed to send metrics update: {}", e);
                     }
                 }
+
+                // Periodic heartbeat
+                _ = heartbeat_interval.tick() => {
+                    let jobs = job_manager.active_jobs().await;
+                    if let Err(e) = client
+                        .send_message(ClientMessage::Heartbeat { jobs }, None)
+                        .await
+                    {
+                        tracing::error!("Failed to send heartbeat: {}", e);
+                    }
+                }
             }
         }
     });
@@ -531,6 +866,7 @@ async fn collect_metrics(
         queued_jobs,
         running_jobs,
         max_instances: resource_manager.limits.max_instances,
+        version: Some(env!("CARGO_PKG_VERSION").to_string()),
     }
 }
 
@@ -540,17 +876,24 @@ async fn handle_server_message(
     client: &mut WebSocketClient,
     vm_manager: &VmManager,
     resource_manager: &Arc<ResourceManager>,
-    job_manager: &JobManager,
+    job_manager: &Arc<JobManager>,
     http_client: &HttpClient,
-    shutting_down: bool,
+    token: &str,
+    server_sender: &mpsc::Sender<ClientMessage>,
+    shutting_down: &mut bool,
+    cordoned: &mut bool,
 ) -> Result<()> {
     match message {
         ServerMessage::NewJobAvailable { id, vm } => {
             // Skip new jobs if we're shutting down
-            if shutting_down {
+            if *shutting_down {
                 tracing::info!("Ignoring new job {} while shutting down", id);
                 return Ok(());
             }
+            if *cordoned {
+                tracing::info!("Ignoring new job {} while cordoned", id);
+                return Ok(());
+            }
 
             tracing::info!("New job available with ID: {}", id);
 
@@ -579,25 +922,26 @@ async fn handle_server_message(
                 );
             }
         }
-        ServerMessage::JobClaimed { id, vm, log_url } => {
+        ServerMessage::JobClaimed {
+            id,
+            vm,
+            log_url,
+            artifacts_url,
+            config: job_config,
+        } => {
             // If we're shutting down but somehow got a job claim response,
             // we should reject it
-            if shutting_down {
+            if *shutting_down {
                 tracing::info!("Ignoring job claim {} while shutting down", id);
                 return Ok(());
             }
 
-            tracing::info!("Job {} claimed successfully", id);
-
-            // Create job configuration
-            let job_config = JobConfig {
+            tracing::info!(
+                "Job {} claimed successfully ({} at {})",
                 id,
-                project_url: "https://github.com/cachix/devenv".to_string(),
-                git_ref: None,
-                tasks: vec!["build".to_string(), "test".to_string()],
-                cachix_push: false,
-                clone_depth: None,
-            };
+                job_config.project_url,
+                job_config.git_rev.as_deref().unwrap_or("HEAD")
+            );
 
             // Register job with job manager
             job_manager.register_job(id, job_config.clone()).await?;
@@ -609,12 +953,23 @@ async fn handle_server_message(
                 id,
                 vm.cpu_count,
                 vm.memory_size_mb,
+                Masker::new(job_config.sensitive_values()),
             )
             .await;
 
+            // Set up guest event handling and artifact uploads
+            let event_sender = setup_guest_events(
+                http_client.clone(),
+                artifacts_url,
+                token.to_string(),
+                id,
+                job_manager.clone(),
+                server_sender.clone(),
+            );
+
             // Launch VM for this job
             if let Err(e) = vm_manager
-                .launch_vm(id, vm.clone(), job_config, log_sender.clone())
+                .launch_vm(id, vm.clone(), job_config, log_sender.clone(), event_sender)
                 .await
             {
                 tracing::error!("Failed to launch VM: {}", e);
@@ -627,8 +982,11 @@ async fn handle_server_message(
                     tracing::error!("Failed to send error log: {}", e);
                 }
 
-                // Update job status to failed
-                if let Err(e) = job_manager.complete_job(id, CompletionStatus::Failed).await {
+                // The job never ran, so the backend retries it
+                if let Err(e) = job_manager
+                    .complete_job(id, CompletionStatus::InfrastructureFailure)
+                    .await
+                {
                     tracing::error!("Failed to update job status: {}", e);
                 }
             } else {
@@ -660,31 +1018,70 @@ async fn handle_server_message(
         }
         ServerMessage::JobCancelled { id } => {
             tracing::info!("Job {} cancelled by user, sending shutdown command", id);
-
-            // Update job status to cancelled
-            if let Err(e) = job_manager
-                .complete_job(id, CompletionStatus::Cancelled)
-                .await
-            {
-                tracing::error!("Failed to update cancelled job: {}", e);
+            cancel_job(vm_manager, resource_manager, job_manager, id).await;
+        }
+        ServerMessage::Cordon => {
+            tracing::info!("Runner cordoned, no longer claiming new jobs");
+            *cordoned = true;
+        }
+        ServerMessage::Uncordon => {
+            tracing::info!("Runner uncordoned, claiming new jobs again");
+            *cordoned = false;
+            if !*shutting_down {
+                check_capacity_and_request_job(client, resource_manager).await?;
             }
-
-            // Send shutdown command to VM
-            if let Err(e) = vm_manager.shutdown_vm(&id).await {
-                tracing::error!("Failed to shutdown VM: {}", e);
-
-                // If shutdown fails, make sure resources are released
-                resource_manager.release_job(id).await;
+        }
+        ServerMessage::Drain => {
+            let active_jobs = job_manager.active_job_count().await;
+            tracing::info!(
+                "Runner drained by the server, waiting for {} active jobs to complete",
+                active_jobs
+            );
+            *shutting_down = true;
+        }
+        ServerMessage::Evict => {
+            let ids = job_manager.active_job_ids().await;
+            tracing::info!(
+                "Runner evicted by the server, cancelling {} active jobs",
+                ids.len()
+            );
+            for id in ids {
+                cancel_job(vm_manager, resource_manager, job_manager, id).await;
             }
-
-            // The VM completion handler will handle resource cleanup and backend status updates
-            // when the VM actually exits
+            *shutting_down = true;
         }
     }
 
     Ok(())
 }
 
+/// Cancel a job and shut down its VM
+async fn cancel_job(
+    vm_manager: &VmManager,
+    resource_manager: &Arc<ResourceManager>,
+    job_manager: &Arc<JobManager>,
+    id: Uuid,
+) {
+    // Update job status to cancelled
+    if let Err(e) = job_manager
+        .complete_job(id, CompletionStatus::Cancelled)
+        .await
+    {
+        tracing::error!("Failed to update cancelled job: {}", e);
+    }
+
+    // Send shutdown command to VM
+    if let Err(e) = vm_manager.shutdown_vm(&id).await {
+        tracing::error!("Failed to shutdown VM: {}", e);
+
+        // If shutdown fails, make sure resources are released
+        resource_manager.release_job(id).await;
+    }
+
+    // The VM completion handler will handle resource cleanup and backend status updates
+    // when the VM actually exits
+}
+
 /// Implement the function to handle shutdown signals
 async fn handle_shutdown_signals(shutdown_tx: oneshot::Sender<()>) {
     // Handle SIGINT (Ctrl+C)
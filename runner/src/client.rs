@@ -33,6 +33,17 @@ pub enum WebSocketError {
     UnsupportedPlatform,
 }
 
+/// How a runner identifies itself to the server
+#[derive(Debug, Clone)]
+pub struct Registration {
+    /// Persistent id of the runner, kept across reconnects and restarts
+    pub runner_id: uuid::Uuid,
+    /// Runner registration token used to authenticate with the server
+    pub token: String,
+    /// Capabilities of this runner jobs can require, e.g. `region=eu`
+    pub labels: Vec<String>,
+}
+
 pub struct WebSocketClient {
     pub write: futures_util::stream::SplitSink<
         tokio_tungstenite::WebSocketStream<
@@ -47,9 +58,11 @@ impl WebSocketClient {
     ///
     /// # Arguments
     /// * `uri` - WebSocket server URI
+    /// * `registration` - Identity and credentials of this runner
     /// * `connect_timeout_secs` - Optional connection timeout in seconds (defaults to 30 seconds)
     pub async fn new(
         uri: Uri,
+        registration: &Registration,
         connect_timeout_secs: Option<u64>,
     ) -> Result<
         (
@@ -93,6 +106,9 @@ impl WebSocketClient {
             .header("Upgrade", "websocket")
             .header("Connection", "upgrade")
             .header("X-Runner-Platform", platform.to_string())
+            .header("Authorization", format!("Bearer {}", registration.token))
+            .header("X-Runner-Id", registration.runner_id.to_string())
+            .header("X-Runner-Labels", registration.labels.join(","))
             .header("Sec-Websocket-Key", generate_key())
             .header("Sec-Websocket-Version", "13")
             .body(())
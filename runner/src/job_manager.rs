@@ -1,4 +1,4 @@
-use crate::protocol::{CompletionStatus, JobConfig, JobStatus};
+use crate::protocol::{ActiveJob, CompletionStatus, JobConfig, JobStatus};
 use eyre::Result;
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -18,6 +18,8 @@ pub struct JobInfo {
     pub id: Uuid,
     pub config: JobConfig,
     pub status: JobStatus,
+    /// Whether the guest started working on the job
+    pub guest_started: bool,
 }
 
 impl JobInfo {
@@ -27,6 +29,7 @@ impl JobInfo {
             id,
             config,
             status: JobStatus::Queued,
+            guest_started: false,
         }
     }
 }
@@ -85,6 +88,10 @@ impl JobManager {
         let transition_allowed = match (&job.status, &status) {
             (JobStatus::Queued, JobStatus::Running) => true,
             (JobStatus::Queued, JobStatus::Complete(CompletionStatus::Cancelled)) => true,
+            // The VM of the job failed to launch
+            (JobStatus::Queued, JobStatus::Complete(CompletionStatus::InfrastructureFailure)) => {
+                true
+            }
             (JobStatus::Running, JobStatus::Complete(_)) => true,
             // All other transitions are not allowed
             _ => false,
@@ -130,6 +137,14 @@ impl JobManager {
             .await
     }
 
+    /// Record that the guest started working on a job
+    pub async fn mark_guest_started(&self, job_id: Uuid) {
+        let mut jobs = self.jobs.write().await;
+        if let Some(job) = jobs.get_mut(&job_id) {
+            job.guest_started = true;
+        }
+    }
+
     /// Check if a job exists
     pub async fn job_exists(&self, job_id: &Uuid) -> bool {
         let jobs = self.jobs.read().await;
@@ -150,6 +165,27 @@ impl JobManager {
             .count()
     }
 
+    /// Get the ids of jobs that haven't completed yet
+    pub async fn active_job_ids(&self) -> Vec<Uuid> {
+        let jobs = self.jobs.read().await;
+        jobs.values()
+            .filter(|job| !matches!(job.status, JobStatus::Complete(_)))
+            .map(|job| job.id)
+            .collect()
+    }
+
+    /// Get the jobs that haven't completed yet, as reported in heartbeats
+    pub async fn active_jobs(&self) -> Vec<ActiveJob> {
+        let jobs = self.jobs.read().await;
+        jobs.values()
+            .filter(|job| !matches!(job.status, JobStatus::Complete(_)))
+            .map(|job| ActiveJob {
+                id: job.id,
+                started: job.guest_started,
+            })
+            .collect()
+    }
+
     /// Get job counts by status
     pub async fn get_job_counts(&self) -> (usize, usize, usize) {
         let jobs = self.jobs.read().await;
@@ -167,7 +203,7 @@ impl JobManager {
                     running += 1;
                     active += 1;
                 }
-                JobStatus::Complete(_) => {}
+                JobStatus::Complete(_) | JobStatus::AwaitingApproval => {}
             }
         }
 
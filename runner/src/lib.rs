@@ -4,6 +4,8 @@ pub mod client;
 pub mod config;
 #[cfg(feature = "client")]
 pub mod job_manager;
+#[cfg(feature = "client")]
+pub mod mask;
 pub mod protocol;
 #[cfg(feature = "client")]
 pub mod resource_manager;
@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub enum Platform {
     #[serde(rename = "x86_64-linux")]
     X86_64Linux,
@@ -44,6 +44,16 @@ impl std::str::FromStr for Platform {
     }
 }
 
+/// Check whether a runner label only uses characters that can be advertised
+///
+/// Labels are sent comma-separated in a header, e.g. `region=eu,hugepages`.
+pub fn is_valid_label(label: &str) -> bool {
+    !label.is_empty()
+        && label
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':' | '='))
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct VM {
     pub cpu_count: usize,
@@ -51,6 +61,31 @@ pub struct VM {
     pub platform: Platform,
 }
 
+/// A sensitive value such as an access token
+///
+/// Serializes as a plain string, but is redacted from `Debug` output so it
+/// doesn't end up in logs.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Self(value.into())
+    }
+
+    /// Returns the underlying value
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Secret(***)")
+    }
+}
+
 /// Configuration for a job to be run in a VM
 /// This is sent over the vsock connection to the guest
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -61,12 +96,31 @@ pub struct JobConfig {
     pub project_url: String,
     /// Git reference (branch, tag, or commit SHA) to check out
     pub git_ref: Option<String>,
+    /// Exact commit SHA the job was created for
+    pub git_rev: Option<String>,
     /// Tasks to run after setup
     pub tasks: Vec<String>,
     /// Whether to push to Cachix
     pub cachix_push: bool,
     /// Clone depth (shallow clone)
     pub clone_depth: Option<u32>,
+    /// Short-lived token used to authenticate the clone of private repositories
+    pub git_token: Option<Secret>,
+    /// Glob patterns, relative to the project directory, of files to collect as artifacts
+    pub artifacts: Vec<String>,
+    /// Secrets exposed to the devenv shell as environment variables
+    #[serde(default)]
+    pub secrets: std::collections::BTreeMap<String, Secret>,
+}
+
+impl JobConfig {
+    /// Values given to the job that must not show up in its log
+    pub fn sensitive_values(&self) -> impl Iterator<Item = &str> {
+        self.git_token
+            .iter()
+            .chain(self.secrets.values())
+            .map(Secret::expose)
+    }
 }
 
 /// Port numbers for the vsock protocol
@@ -86,6 +140,8 @@ pub enum VsockGuestMessage {
     Ready { id: uuid::Uuid },
     /// Job execution completed
     Complete { id: uuid::Uuid, success: bool },
+    /// The job couldn't be set up, e.g. the repository failed to clone
+    SetupFailed { id: uuid::Uuid, reason: String },
     /// Log message from guest
     Log {
         id: uuid::Uuid,
@@ -94,6 +150,22 @@ pub enum VsockGuestMessage {
         message: String,
         fields: std::collections::HashMap<String, String>,
     },
+    /// Chunk of an artifact file, `last` marks the end of the file
+    Artifact {
+        id: uuid::Uuid,
+        path: String,
+        data: Vec<u8>,
+        last: bool,
+    },
+    /// A task of the job started
+    TaskStarted { id: uuid::Uuid, name: String },
+    /// A task of the job finished
+    TaskFinished {
+        id: uuid::Uuid,
+        name: String,
+        success: bool,
+        duration_ms: u64,
+    },
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -106,6 +178,8 @@ pub enum ServerMessage {
         id: uuid::Uuid,
         vm: VM,
         log_url: url::Url,
+        artifacts_url: url::Url,
+        config: JobConfig,
     },
     JobTimedOut {
         id: uuid::Uuid,
@@ -115,10 +189,19 @@ pub enum ServerMessage {
     JobCancelled {
         id: uuid::Uuid,
     },
+    /// Stop claiming new jobs until uncordoned
+    Cordon,
+    /// Resume claiming new jobs
+    Uncordon,
+    /// Finish the running jobs, then disconnect and exit
+    Drain,
+    /// Cancel the running jobs, then disconnect and exit
+    Evict,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct RunnerMetrics {
+    #[schema(value_type = String)]
     pub platform: Platform,
     pub cpu_count: usize,
     pub memory_size_mb: u64,
@@ -130,14 +213,51 @@ pub struct RunnerMetrics {
     pub queued_jobs: usize,
     pub running_jobs: usize,
     pub max_instances: Option<usize>,
+    /// Version of the runner, missing for runners predating it
+    #[serde(default)]
+    pub version: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// A job a runner is executing, reported with every heartbeat
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ActiveJob {
+    pub id: uuid::Uuid,
+    /// Whether the guest started working on the job
+    pub started: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum ClientMessage {
-    ClaimJob { id: uuid::Uuid, vm: VM },
-    UpdateJobStatus { id: uuid::Uuid, status: JobStatus },
+    ClaimJob {
+        id: uuid::Uuid,
+        vm: VM,
+    },
+    UpdateJobStatus {
+        id: uuid::Uuid,
+        status: JobStatus,
+    },
     RequestJob,
     ReportMetrics(RunnerMetrics),
+    /// Jobs the runner is still executing, sent after every (re)connect
+    ReconcileJobs {
+        ids: Vec<uuid::Uuid>,
+    },
+    /// Renews the leases of the jobs the runner is executing
+    Heartbeat {
+        jobs: Vec<ActiveJob>,
+    },
+    /// A task of a job started in the guest
+    TaskStarted {
+        id: uuid::Uuid,
+        name: String,
+    },
+    /// A task of a job finished in the guest
+    TaskFinished {
+        id: uuid::Uuid,
+        name: String,
+        success: bool,
+        duration_ms: u64,
+    },
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, ToSchema)]
@@ -147,6 +267,8 @@ pub enum CompletionStatus {
     Cancelled,
     TimedOut,
     Skipped,
+    /// The job couldn't run because of the runner, not because of its tasks
+    InfrastructureFailure,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, ToSchema)]
@@ -154,4 +276,7 @@ pub enum JobStatus {
     Queued,
     Running,
     Complete(CompletionStatus),
+    /// The job waits for a maintainer to approve it, e.g. for pull requests
+    /// from forks. Runners never see jobs in this state.
+    AwaitingApproval,
 }
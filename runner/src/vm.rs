@@ -12,6 +12,9 @@ pub enum VmExitStatus {
     Success,
     /// VM exited with an error (non-zero exit code)
     Failure,
+    /// The VM or the guest couldn't set up the job, e.g. the VM failed to
+    /// boot or the repository failed to clone
+    InfrastructureFailure,
 }
 
 impl From<VmExitStatus> for CompletionStatus {
@@ -19,6 +22,17 @@ impl From<VmExitStatus> for CompletionStatus {
         match status {
             VmExitStatus::Success => CompletionStatus::Success,
             VmExitStatus::Failure => CompletionStatus::Failed,
+            VmExitStatus::InfrastructureFailure => CompletionStatus::InfrastructureFailure,
+        }
+    }
+}
+
+impl From<crate::vsock::GuestResult> for VmExitStatus {
+    fn from(result: crate::vsock::GuestResult) -> Self {
+        match result {
+            crate::vsock::GuestResult::Success => VmExitStatus::Success,
+            crate::vsock::GuestResult::Failure => VmExitStatus::Failure,
+            crate::vsock::GuestResult::SetupFailed => VmExitStatus::InfrastructureFailure,
         }
     }
 }
@@ -58,6 +72,7 @@ pub trait Vm: Send {
         &mut self,
         job_config: JobConfig,
         log_sender: tokio::sync::mpsc::Sender<String>,
+        event_sender: tokio::sync::mpsc::Sender<crate::vsock::GuestEvent>,
     ) -> Result<()>;
 }
 
@@ -54,7 +54,7 @@ pub struct LinuxVm {
     /// Directory containing the VM's root filesystem
     vm_rootfs_dir: PathBuf,
     /// Shared state for job result from vsock
-    job_result: Arc<Mutex<Option<bool>>>,
+    job_result: Arc<Mutex<Option<vsock::GuestResult>>>,
     /// Join handle for the vsock server task
     vsock_server_handle: Option<tokio::task::JoinHandle<()>>,
 }
@@ -364,11 +364,11 @@ impl Vm for LinuxVm {
         // Poll until the process exits
         loop {
             // First check if we have a job result (job completed)
-            let job_result = self.job_result.lock().await.clone();
-            if let Some(success) = job_result {
+            let job_result = *self.job_result.lock().await;
+            if let Some(result) = job_result {
                 tracing::info!(
-                    "Job completed with result: {} - initiating VM shutdown",
-                    if success { "success" } else { "failure" }
+                    "Job completed with result: {:?} - initiating VM shutdown",
+                    result
                 );
 
                 // Shutdown the VM since the job is complete
@@ -377,11 +377,7 @@ impl Vm for LinuxVm {
                 }
 
                 // Return the job result
-                return Ok(if success {
-                    crate::vm::VmExitStatus::Success
-                } else {
-                    crate::vm::VmExitStatus::Failure
-                });
+                return Ok(result.into());
             }
 
             // Check process status
@@ -391,8 +387,7 @@ impl Vm for LinuxVm {
 
                     // Determine exit status: prioritize job result over exit code
                     let exit_status = match job_result {
-                        Some(true) => crate::vm::VmExitStatus::Success,
-                        Some(false) => crate::vm::VmExitStatus::Failure,
+                        Some(result) => result.into(),
                         None if exit_code == 0 => crate::vm::VmExitStatus::Success,
                         _ => crate::vm::VmExitStatus::Failure,
                     };
@@ -472,6 +467,7 @@ impl Vm for LinuxVm {
         &mut self,
         job_config: JobConfig,
         log_sender: tokio::sync::mpsc::Sender<String>,
+        event_sender: tokio::sync::mpsc::Sender<vsock::GuestEvent>,
     ) -> Result<()> {
         // Store job config
         self.job_config = Some(job_config.clone());
@@ -493,6 +489,7 @@ impl Vm for LinuxVm {
                 job_config,
                 Some(job_result),
                 log_sender,
+                event_sender,
             )
             .await
             {
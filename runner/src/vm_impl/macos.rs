@@ -280,7 +280,7 @@ pub struct MacosVm {
     /// Job configuration if available
     job_config: Option<JobConfig>,
     /// Shared state for job result from vsock
-    job_result: Arc<Mutex<Option<bool>>>,
+    job_result: Arc<Mutex<Option<vsock::GuestResult>>>,
     #[allow(dead_code)]
     /// The delegate for vsock connection callbacks. Keep alive to retain the listener.
     socket_delegate: Option<Arc<Retained<VsockListenerDelegate>>>,
@@ -541,7 +541,9 @@ impl VmTrait for MacosVm {
                 // VM has stopped, either normally or due to error
                 VZVirtualMachineState::Stopped => {
                     info!("VM {} has stopped normally", self.id);
-                    return Ok(crate::vm::VmExitStatus::Success);
+                    // The guest may have reported a failure before shutting down
+                    let job_result = *self.job_result.lock().await;
+                    return Ok(job_result.map_or(crate::vm::VmExitStatus::Success, Into::into));
                 }
 
                 // VM is in error state
@@ -605,6 +607,7 @@ impl VmTrait for MacosVm {
         &mut self,
         job_config: JobConfig,
         log_sender: tokio::sync::mpsc::Sender<String>,
+        event_sender: tokio::sync::mpsc::Sender<vsock::GuestEvent>,
     ) -> Result<()> {
         // Store job config
         self.job_config = Some(job_config.clone());
@@ -690,6 +693,7 @@ impl VmTrait for MacosVm {
                                 let job_result = job_result.clone();
                                 let shutdown_notify = shutdown_notify.clone();
                                 let log_sender = log_sender.clone();
+                                let event_sender = event_sender.clone();
 
                                 info!("Starting config server communication for job {}", job_id);
 
@@ -715,6 +719,7 @@ impl VmTrait for MacosVm {
                                         Some(job_result),
                                         shutdown_notify,
                                         log_sender,
+                                        event_sender,
                                     )
                                     .await
                                     {
@@ -287,19 +287,25 @@ mod tests {
 
         // Create a channel to receive logs
         let (log_sender, mut log_receiver) = tokio::sync::mpsc::channel::<String>(100);
+        let (event_sender, _event_receiver) =
+            tokio::sync::mpsc::channel::<crate::vsock::GuestEvent>(100);
 
         // Create a test job configuration
         let job_config = crate::protocol::JobConfig {
             id: uuid::Uuid::new_v4(),
             project_url: "https://github.com/octocat/Hello-World".to_string(),
             git_ref: None,
+            git_rev: None,
             tasks: vec![],
             cachix_push: false,
             clone_depth: Some(1),
+            git_token: None,
+            artifacts: vec![],
+            secrets: Default::default(),
         };
 
         // Set job configuration with log sender
-        vm.set_job_config(job_config.clone(), log_sender.clone())
+        vm.set_job_config(job_config.clone(), log_sender.clone(), event_sender)
             .await
             .expect("Failed to set job config");
 
@@ -2,6 +2,7 @@ use crate::config::VmConfig;
 use crate::protocol::{JobConfig, VM};
 use crate::resource_manager::ResourceManager;
 use crate::vm::VmExitStatus;
+use crate::vsock::GuestEvent;
 use eyre::Result;
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -55,6 +56,7 @@ impl VmManager {
         vm_config: VM,
         job_config: JobConfig,
         log_sender: mpsc::Sender<String>,
+        event_sender: mpsc::Sender<GuestEvent>,
     ) -> Result<()> {
         // Create control channel for this VM
         let (control_tx, control_rx) = mpsc::channel::<VmCommand>(10);
@@ -89,6 +91,7 @@ impl VmManager {
                         vm_cfg,
                         control_rx,
                         log_sender,
+                        event_sender,
                     )
                     .await;
                 });
@@ -108,6 +111,7 @@ impl VmManager {
                     vm_cfg,
                     control_rx,
                     log_sender,
+                    event_sender,
                 )
                 .await;
             });
@@ -168,6 +172,7 @@ async fn vm_task_impl(
     config: VmConfig,
     mut control_rx: mpsc::Receiver<VmCommand>,
     log_sender: mpsc::Sender<String>,
+    event_sender: mpsc::Sender<GuestEvent>,
 ) {
     // Create and start VM
     let vm_result = async {
@@ -175,7 +180,8 @@ async fn vm_task_impl(
             crate::vm::create_vm(vm_config, job_id.to_string(), resource_manager, &config).await?;
 
         // Set job configuration (send to guest)
-        vm.set_job_config(job_config, log_sender).await?;
+        vm.set_job_config(job_config, log_sender.clone(), event_sender)
+            .await?;
 
         // Start VM
         vm.start().await?;
@@ -230,10 +236,13 @@ async fn vm_task_impl(
         Err(e) => {
             // VM creation failed - the IP guard will automatically release the IP when dropped
             tracing::error!("Failed to create VM for job {}: {}", job_id, e);
+            if let Err(e) = log_sender.send(format!("Failed to launch VM: {e}")).await {
+                tracing::error!("Failed to send error log: {}", e);
+            }
             let _ = completion_tx
                 .send(VmCompletionEvent {
                     job_id,
-                    status: VmExitStatus::Failure,
+                    status: VmExitStatus::InfrastructureFailure,
                 })
                 .await;
         }
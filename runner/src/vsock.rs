@@ -39,6 +39,60 @@ impl JobReporter {
         Ok(())
     }
 
+    /// Report that the job couldn't be set up, so it didn't run at all
+    pub async fn report_setup_failed(&mut self, reason: String) -> Result<()> {
+        let failed = VsockGuestMessage::SetupFailed {
+            id: self.job_id,
+            reason,
+        };
+        self.stream.write_message(&failed).await?;
+        Ok(())
+    }
+
+    /// Send a chunk of an artifact file to the host
+    pub async fn send_artifact_chunk(
+        &mut self,
+        path: String,
+        data: Vec<u8>,
+        last: bool,
+    ) -> Result<()> {
+        let chunk = VsockGuestMessage::Artifact {
+            id: self.job_id,
+            path,
+            data,
+            last,
+        };
+        self.stream.write_message(&chunk).await?;
+        Ok(())
+    }
+
+    /// Report the start of a task to the host
+    pub async fn report_task_started(&mut self, name: String) -> Result<()> {
+        let started = VsockGuestMessage::TaskStarted {
+            id: self.job_id,
+            name,
+        };
+        self.stream.write_message(&started).await?;
+        Ok(())
+    }
+
+    /// Report the outcome of a task to the host
+    pub async fn report_task_finished(
+        &mut self,
+        name: String,
+        success: bool,
+        duration: Duration,
+    ) -> Result<()> {
+        let finished = VsockGuestMessage::TaskFinished {
+            id: self.job_id,
+            name,
+            success,
+            duration_ms: duration.as_millis() as u64,
+        };
+        self.stream.write_message(&finished).await?;
+        Ok(())
+    }
+
     /// Send a log message to the host
     pub async fn send_log(
         &mut self,
@@ -59,6 +113,38 @@ impl JobReporter {
     }
 }
 
+/// Outcome of a job reported by the guest
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GuestResult {
+    /// The tasks of the job succeeded
+    Success,
+    /// A task of the job failed
+    Failure,
+    /// The guest couldn't get to the tasks of the job
+    SetupFailed,
+}
+
+/// Events from the guest, other than logs, that are handed to the runner
+#[derive(Debug)]
+pub enum GuestEvent {
+    /// The guest acknowledged the job and started working on it
+    Started,
+    /// Chunk of an artifact file, `last` marks the end of the file
+    ArtifactChunk {
+        path: String,
+        data: Vec<u8>,
+        last: bool,
+    },
+    /// A task of the job started
+    TaskStarted { name: String },
+    /// A task of the job finished
+    TaskFinished {
+        name: String,
+        success: bool,
+        duration_ms: u64,
+    },
+}
+
 /// Structured log entry to be sent through vsock
 #[derive(Serialize, Deserialize)]
 pub struct LogEntry {
@@ -197,8 +283,9 @@ impl MessageStream for UnixStream {}
 pub async fn start_unix_config_server(
     vsock_socket_path: PathBuf,
     job_config: JobConfig,
-    job_result: Option<Arc<tokio::sync::Mutex<Option<bool>>>>,
+    job_result: Option<Arc<tokio::sync::Mutex<Option<GuestResult>>>>,
     log_sender: mpsc::Sender<String>,
+    event_sender: mpsc::Sender<GuestEvent>,
 ) -> Result<()> {
     // Construct the UNIX socket path as per Cloud Hypervisor documentation
     let socket_path = PathBuf::from(format!(
@@ -230,9 +317,10 @@ pub async fn start_unix_config_server(
                         let job_result = job_result.clone();
                         let shutdown_notify_task = shutdown_notify.clone();
                         let log_sender = log_sender.clone();
+                        let event_sender = event_sender.clone();
                         tokio::spawn(async move {
                             if let Err(e) =
-                                handle_guest_connection(&mut stream, job_config, job_result, shutdown_notify_task, log_sender).await
+                                handle_guest_connection(&mut stream, job_config, job_result, shutdown_notify_task, log_sender, event_sender).await
                             {
                                 error!("Error handling guest connection: {:?}", e);
                             }
@@ -258,13 +346,11 @@ pub async fn start_unix_config_server(
     Ok(())
 }
 
-/// Handle a guest connection and send the job configuration
-pub async fn handle_guest_connection(
+/// Send the job configuration to the guest and wait for it to acknowledge the job
+async fn handshake(
     stream: &mut UnixStream,
     job_config: JobConfig,
-    job_result_state: Option<Arc<tokio::sync::Mutex<Option<bool>>>>,
-    shutdown_notify: Arc<Notify>,
-    log_sender: mpsc::Sender<String>,
+    event_sender: &mpsc::Sender<GuestEvent>,
 ) -> Result<()> {
     let job_id = job_config.id;
 
@@ -284,6 +370,9 @@ pub async fn handle_guest_connection(
     match response {
         VsockGuestMessage::Ready { id } if id == job_id => {
             info!("Guest ready to execute job {}", id);
+            if let Err(e) = event_sender.send(GuestEvent::Started).await {
+                error!("Failed to forward job start: {}", e);
+            }
         }
         VsockGuestMessage::Ready { id } => {
             return Err(eyre::eyre!(
@@ -292,9 +381,9 @@ pub async fn handle_guest_connection(
                 id
             ));
         }
-        VsockGuestMessage::Complete { .. } => {
+        VsockGuestMessage::Complete { .. } | VsockGuestMessage::SetupFailed { .. } => {
             return Err(eyre::eyre!(
-                "Unexpected Complete message during initial handshake"
+                "Unexpected completion message during initial handshake"
             ));
         }
         VsockGuestMessage::Log { .. } => {
@@ -302,6 +391,39 @@ pub async fn handle_guest_connection(
                 "Unexpected Log message during initial handshake"
             ));
         }
+        VsockGuestMessage::Artifact { .. } => {
+            return Err(eyre::eyre!(
+                "Unexpected Artifact message during initial handshake"
+            ));
+        }
+        VsockGuestMessage::TaskStarted { .. } | VsockGuestMessage::TaskFinished { .. } => {
+            return Err(eyre::eyre!(
+                "Unexpected task message during initial handshake"
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Handle a guest connection and send the job configuration
+pub async fn handle_guest_connection(
+    stream: &mut UnixStream,
+    job_config: JobConfig,
+    job_result_state: Option<Arc<tokio::sync::Mutex<Option<GuestResult>>>>,
+    shutdown_notify: Arc<Notify>,
+    log_sender: mpsc::Sender<String>,
+    event_sender: mpsc::Sender<GuestEvent>,
+) -> Result<()> {
+    let job_id = job_config.id;
+
+    if let Err(e) = handshake(stream, job_config, &event_sender).await {
+        // The guest never got to the job, so it isn't the job's fault
+        if let Some(result_state) = &job_result_state {
+            *result_state.lock().await = Some(GuestResult::SetupFailed);
+        }
+        shutdown_notify.notify_one();
+        return Err(e);
     }
 
     // Keep the connection alive to receive job result
@@ -314,7 +436,16 @@ pub async fn handle_guest_connection(
                     id,
                     if success { "success" } else { "failure" }
                 );
-                received_result = Some(success);
+                received_result = Some(if success {
+                    GuestResult::Success
+                } else {
+                    GuestResult::Failure
+                });
+                break;
+            }
+            Ok(VsockGuestMessage::SetupFailed { id, reason }) if id == job_id => {
+                error!("Job {} couldn't be set up: {}", id, reason);
+                received_result = Some(GuestResult::SetupFailed);
                 break;
             }
             Ok(VsockGuestMessage::Complete { id, .. }) => {
@@ -356,6 +487,41 @@ pub async fn handle_guest_connection(
                     job_id, id
                 );
             }
+            Ok(VsockGuestMessage::Artifact {
+                id,
+                path,
+                data,
+                last,
+            }) if id == job_id => {
+                if let Err(e) = event_sender
+                    .send(GuestEvent::ArtifactChunk { path, data, last })
+                    .await
+                {
+                    error!("Failed to forward artifact chunk: {}", e);
+                }
+            }
+            Ok(VsockGuestMessage::TaskStarted { id, name }) if id == job_id => {
+                if let Err(e) = event_sender.send(GuestEvent::TaskStarted { name }).await {
+                    error!("Failed to forward task start: {}", e);
+                }
+            }
+            Ok(VsockGuestMessage::TaskFinished {
+                id,
+                name,
+                success,
+                duration_ms,
+            }) if id == job_id => {
+                if let Err(e) = event_sender
+                    .send(GuestEvent::TaskFinished {
+                        name,
+                        success,
+                        duration_ms,
+                    })
+                    .await
+                {
+                    error!("Failed to forward task result: {}", e);
+                }
+            }
             Ok(msg) => info!(
                 "Received unexpected message from guest for job {}: {:?}",
                 job_id, msg
@@ -371,14 +537,10 @@ pub async fn handle_guest_connection(
     }
 
     // Store the job result in the shared state if provided
-    if let (Some(success), Some(result_state)) = (received_result, job_result_state) {
+    if let (Some(result), Some(result_state)) = (received_result, job_result_state) {
         let mut result_guard = result_state.lock().await;
-        *result_guard = Some(success);
-        info!(
-            "Job {} result stored: {}",
-            job_id,
-            if success { "success" } else { "failure" }
-        );
+        *result_guard = Some(result);
+        info!("Job {} result stored: {:?}", job_id, result);
     }
 
     // Signal the server to shut down after job completion